@@ -0,0 +1,121 @@
+//! Throughput benchmark for the capture and color pipeline.
+//!
+//! `run_benchmark` captures and processes frames back to back for a fixed time, without
+//! frame delays, transitions or any OpenRGB device, to show how fast the current settings
+//! (`sample_step`, LED count, compute resolution, ...) can go on this machine.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::error::KeyBloomError;
+use crate::capture::FrameSource;
+use crate::sync_loop::{prepare_frame, ColorPipeline};
+
+/// Frame time statistics collected by a benchmark run.
+#[derive(Debug, Default)]
+pub struct BenchmarkReport {
+    /// Capture plus processing time of every frame, in the order measured.
+    frame_times: Vec<Duration>,
+    capture_total: Duration,
+    process_total: Duration,
+    elapsed: Duration,
+}
+
+impl BenchmarkReport {
+    /// Add the timings of one frame.
+    pub fn record(&mut self, capture: Duration, process: Duration) {
+        self.frame_times.push(capture + process);
+        self.capture_total += capture;
+        self.process_total += process;
+    }
+
+    /// Frames completed per second of wall-clock time.
+    pub fn fps(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+        self.frame_times.len() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// The frame time below which `fraction` (0-1) of the frames finished.
+    pub fn percentile(&self, fraction: f64) -> Duration {
+        let mut sorted = self.frame_times.clone();
+        sorted.sort_unstable();
+        let Some(last) = sorted.len().checked_sub(1) else {
+            return Duration::ZERO;
+        };
+        sorted[((last as f64 * fraction).ceil() as usize).min(last)]
+    }
+
+    fn average(&self, total: Duration) -> Duration {
+        total / self.frame_times.len().max(1) as u32
+    }
+
+    /// The report as a JSON object, with all times in milliseconds.
+    pub fn to_json(&self) -> Value {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let total = self.capture_total + self.process_total;
+        json!({
+            "frames": self.frame_times.len(),
+            "seconds": self.elapsed.as_secs_f64(),
+            "fps": self.fps(),
+            "min_ms": ms(self.percentile(0.0)),
+            "avg_ms": ms(self.average(total)),
+            "max_ms": ms(self.percentile(1.0)),
+            "p99_ms": ms(self.percentile(0.99)),
+            "avg_capture_ms": ms(self.average(self.capture_total)),
+            "avg_process_ms": ms(self.average(self.process_total)),
+        })
+    }
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let total = self.capture_total + self.process_total;
+        writeln!(
+            f,
+            "Benchmark: {} frames in {:.1} s",
+            self.frame_times.len(),
+            self.elapsed.as_secs_f64()
+        )?;
+        writeln!(f, "  frame time min    {:8.2} ms", ms(self.percentile(0.0)))?;
+        writeln!(f, "  frame time avg    {:8.2} ms", ms(self.average(total)))?;
+        writeln!(f, "  frame time max    {:8.2} ms", ms(self.percentile(1.0)))?;
+        writeln!(f, "  frame time p99    {:8.2} ms", ms(self.percentile(0.99)))?;
+        writeln!(f, "  capture avg       {:8.2} ms", ms(self.average(self.capture_total)))?;
+        writeln!(f, "  process avg       {:8.2} ms", ms(self.average(self.process_total)))?;
+        write!(f, "  effective FPS     {:8.1}", self.fps())
+    }
+}
+
+/// Run the capture and color pipeline as fast as possible for `duration`.
+///
+/// Frames come from the configured monitor, or from `simulate_image` if one is set.
+pub fn run_benchmark(
+    config: &Config,
+    duration: Duration,
+) -> Result<BenchmarkReport, KeyBloomError> {
+    let mut source = FrameSource::open(config)?;
+    let max_leds = config.devices.iter().map(|d| d.num_leds).max().unwrap_or(0);
+    let pipeline = ColorPipeline::new(config, max_leds)?;
+
+    let mut report = BenchmarkReport::default();
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        let frame_start = Instant::now();
+        let frame = source.capture()?;
+        let captured = Instant::now();
+
+        let frame = prepare_frame(frame, config, None);
+        let colors = pipeline.colors(&frame, config, None);
+        std::hint::black_box(colors);
+
+        report.record(captured - frame_start, captured.elapsed());
+    }
+    report.elapsed = started.elapsed();
+    Ok(report)
+}
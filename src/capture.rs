@@ -0,0 +1,224 @@
+//! Frame sources for the color pipeline.
+//!
+//! Frames come from a monitor, a single window (see `CaptureTarget`), or a fixed test
+//! image when simulating. A captured window can close or be renamed at any time, so window
+//! capture falls back to the monitor while the window is missing and looks for it again
+//! periodically.
+
+use std::time::{Duration, Instant};
+
+use image::RgbaImage;
+use tracing::{info, warn};
+use xcap::{Monitor, Window, XCapResult};
+
+use crate::config::{CaptureBackend, CaptureTarget, Config};
+use crate::error::KeyBloomError;
+use crate::sync_loop::select_monitor;
+
+/// How often a missing window is looked for again.
+const WINDOW_LOOKUP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where frames come from.
+pub enum FrameSource {
+    Monitor(Monitor),
+    Window(Box<WindowCapture>),
+    Image(RgbaImage),
+}
+
+impl FrameSource {
+    /// Capture the configured `capture_target`, with `monitor` as the monitor to use.
+    pub fn for_target(config: &Config, monitor: Monitor) -> Self {
+        match &config.capture_target {
+            CaptureTarget::Monitor => FrameSource::Monitor(monitor),
+            CaptureTarget::Window { title } => {
+                FrameSource::Window(Box::new(WindowCapture::new(title.clone(), monitor)))
+            }
+        }
+    }
+
+    /// Use the test image if one is configured, otherwise the configured capture target.
+    pub fn open(config: &Config) -> Result<Self, KeyBloomError> {
+        match &config.simulate_image {
+            Some(path) => Ok(FrameSource::Image(image::open(path)?.to_rgba8())),
+            None => Ok(Self::for_target(config, select_monitor(config)?)),
+        }
+    }
+
+    /// Human-readable name of what is being captured.
+    pub fn describe(&self) -> String {
+        match self {
+            FrameSource::Monitor(monitor) => format!(
+                "monitor {} ({}x{})",
+                monitor.name(),
+                monitor.width(),
+                monitor.height()
+            ),
+            FrameSource::Window(capture) => format!(
+                "window matching '{}' (falling back to monitor {})",
+                capture.title,
+                capture.fallback.name()
+            ),
+            FrameSource::Image(image) => {
+                format!("test image ({}x{})", image.width(), image.height())
+            }
+        }
+    }
+
+    /// Size of a full frame: the monitor's (the fallback monitor's for a window) or the
+    /// test image's.
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            FrameSource::Monitor(monitor) => (monitor.width(), monitor.height()),
+            FrameSource::Window(capture) => (capture.fallback.width(), capture.fallback.height()),
+            FrameSource::Image(image) => image.dimensions(),
+        }
+    }
+
+    /// Grab the next frame.
+    pub fn capture(&mut self) -> Result<RgbaImage, KeyBloomError> {
+        match self {
+            FrameSource::Monitor(monitor) => checked(monitor.capture_image()),
+            FrameSource::Window(capture) => capture.capture(),
+            FrameSource::Image(image) => Ok(image.clone()),
+        }
+    }
+
+    /// A note about the capture to show alongside the sync state, if anything is off.
+    pub fn notice(&self) -> Option<String> {
+        match self {
+            FrameSource::Window(capture) if capture.window.is_none() => Some(format!(
+                "Window '{}' not found; capturing the monitor instead.",
+                capture.title
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Captures one window, falling back to a monitor while the window is missing.
+pub struct WindowCapture {
+    title: String,
+    window: Option<Window>,
+    fallback: Monitor,
+    last_lookup: Option<Instant>,
+}
+
+impl WindowCapture {
+    fn new(title: String, fallback: Monitor) -> Self {
+        Self {
+            title,
+            window: None,
+            fallback,
+            last_lookup: None,
+        }
+    }
+
+    fn capture(&mut self) -> Result<RgbaImage, KeyBloomError> {
+        if self.window.is_none()
+            && self
+                .last_lookup
+                .is_none_or(|t| t.elapsed() >= WINDOW_LOOKUP_INTERVAL)
+        {
+            let first_lookup = self.last_lookup.is_none();
+            self.last_lookup = Some(Instant::now());
+            self.window = find_window(&self.title);
+            match &self.window {
+                Some(window) => info!("Capturing window: {}", window.title()),
+                // Later misses were already reported when the window went away
+                None if first_lookup => warn!(
+                    "No window matching '{}'; capturing the monitor until it appears.",
+                    self.title
+                ),
+                None => {}
+            }
+        }
+
+        if let Some(window) = &self.window {
+            match window.capture_image() {
+                Ok(image) if image.width() > 0 && image.height() > 0 => return Ok(image),
+                _ => {
+                    warn!(
+                        "Window '{}' is gone; capturing the monitor until it returns.",
+                        window.title()
+                    );
+                    self.window = None;
+                    self.last_lookup = Some(Instant::now());
+                }
+            }
+        }
+        checked(self.fallback.capture_image())
+    }
+}
+
+/// The first visible window whose title contains `title`, ignoring case.
+fn find_window(title: &str) -> Option<Window> {
+    let needle = title.to_lowercase();
+    Window::all()
+        .ok()?
+        .into_iter()
+        .find(|window| !window.is_minimized() && window.title().to_lowercase().contains(&needle))
+}
+
+/// The backend xcap captures through in this session, detected the same way xcap does.
+///
+/// `None` off Linux, where there is only the native backend.
+pub fn session_backend() -> Option<CaptureBackend> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let session = std::env::var("XDG_SESSION_TYPE").unwrap_or_default();
+    let display = std::env::var("WAYLAND_DISPLAY").unwrap_or_default();
+    if session == "wayland" || display.to_lowercase().contains("wayland") {
+        Some(CaptureBackend::Wayland)
+    } else {
+        Some(CaptureBackend::X11)
+    }
+}
+
+/// Warn if `capture_backend` names a backend this session won't use, and how to switch.
+pub fn check_backend(config: &Config) {
+    match (config.capture_backend, session_backend()) {
+        (CaptureBackend::Auto, _) => {}
+        (CaptureBackend::X11, Some(CaptureBackend::Wayland)) => warn!(
+            "capture_backend is x11, but this is a Wayland session, so capture goes through \
+             the screenshot portal. To capture through XWayland instead, start KeyBloom with \
+             XDG_SESSION_TYPE=x11 and WAYLAND_DISPLAY unset."
+        ),
+        (CaptureBackend::Wayland, Some(CaptureBackend::X11)) => warn!(
+            "capture_backend is wayland, but no Wayland session was detected \
+             (XDG_SESSION_TYPE, WAYLAND_DISPLAY), so X11 capture is used."
+        ),
+        (wanted, None) => {
+            warn!("capture_backend = {wanted:?} only applies on Linux; using native capture.")
+        }
+        _ => {}
+    }
+}
+
+/// The `capture_fallback_image` as a frame source, if one is configured and loads.
+pub fn fallback_source(config: &Config) -> Option<FrameSource> {
+    let path = config.capture_fallback_image.as_ref()?;
+    match image::open(path) {
+        Ok(image) => {
+            warn!("Using {} in place of the screen until restarted.", path.display());
+            Some(FrameSource::Image(image.to_rgba8()))
+        }
+        Err(err) => {
+            warn!("Failed to load capture_fallback_image {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+/// Turn a capture result into a frame, giving Wayland portal failures and empty frames
+/// errors that say what to do about them.
+fn checked(result: XCapResult<RgbaImage>) -> Result<RgbaImage, KeyBloomError> {
+    match result {
+        Ok(image) if image.width() == 0 || image.height() == 0 => Err(KeyBloomError::EmptyCapture),
+        Ok(image) => Ok(image),
+        Err(err) if session_backend() == Some(CaptureBackend::Wayland) => {
+            Err(KeyBloomError::WaylandCapture(err))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
@@ -0,0 +1,109 @@
+//! Command-line interface for KeyBloom.
+//!
+//! Without arguments KeyBloom opens the TUI menu. The flags here allow running it
+//! unattended (e.g. from a systemd user service) and overriding config fields per run.
+
+use crate::color_log::ColorLogFormat;
+use crate::config::Config;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Real-time RGB keyboard synchronization with on-screen colors.
+#[derive(Parser, Debug, Clone)]
+#[command(name = "keybloom", version, about)]
+pub struct Cli {
+    /// Start syncing immediately without showing the interactive menu.
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Load the named profile instead of the default configuration.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Capture this monitor (0-based), overriding `monitor_index`.
+    #[arg(long, value_name = "INDEX")]
+    pub monitor: Option<usize>,
+
+    /// Append every frame sent to the devices to this file.
+    #[arg(long, value_name = "PATH")]
+    pub color_log: Option<PathBuf>,
+
+    /// Format of the color log.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub color_log_format: Option<ColorLogFormat>,
+
+    /// Flush the color log after every record.
+    #[arg(long)]
+    pub color_log_flush: bool,
+
+    /// Print the computed colors instead of sending them to OpenRGB.
+    #[arg(long)]
+    pub simulate: bool,
+
+    /// Use this image instead of capturing the screen when simulating.
+    #[arg(long, value_name = "PATH", requires = "simulate")]
+    pub simulate_image: Option<PathBuf>,
+
+    /// Log verbosity or filter directives (e.g. `debug`), overriding `RUST_LOG`.
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Measure the capture-to-LED latency of the current settings and exit.
+    #[arg(long)]
+    pub latency_test: bool,
+
+    /// Light LED 0 red, 1 green, 2 blue and the rest dim white for a few seconds, then exit.
+    #[arg(long, conflicts_with_all = ["headless", "once", "latency_test", "benchmark"])]
+    pub test_leds: bool,
+
+    /// Capture the screen once, set the LEDs (with the configured transition) and exit.
+    #[arg(long, conflicts_with_all = ["headless", "latency_test", "benchmark"])]
+    pub once: bool,
+
+    /// Run the capture and color pipeline for this many seconds and report the frame rate.
+    #[arg(long, value_name = "SECONDS")]
+    pub benchmark: Option<u64>,
+
+    /// Print the benchmark report as JSON.
+    #[arg(long, requires = "benchmark")]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// One-shot commands that print information and exit instead of syncing.
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)] // variant names are the subcommand names
+pub enum Command {
+    /// List the monitors that can be captured (for `monitor_index`).
+    ListMonitors,
+    /// List the windows that can be captured (for `capture_target`).
+    ListWindows,
+    /// List the controllers on the OpenRGB server (for `device_name`).
+    ListDevices,
+}
+
+impl Cli {
+    /// Override config fields with the values given on the command line.
+    pub fn apply_overrides(&self, config: &mut Config) {
+        if let Some(monitor) = self.monitor {
+            config.monitor_index = monitor;
+        }
+        if let Some(path) = &self.color_log {
+            config.color_log = Some(path.clone());
+        }
+        if let Some(format) = self.color_log_format {
+            config.color_log_format = format;
+        }
+        if self.color_log_flush {
+            config.color_log_flush = true;
+        }
+        if self.simulate {
+            config.simulate = true;
+        }
+        if let Some(path) = &self.simulate_image {
+            config.simulate_image = Some(path.clone());
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Machine-readable log of every frame sent to the devices.
+//!
+//! Each record holds a timestamp, the device name and the per-LED colors as hex strings,
+//! written either as CSV rows or as JSON lines.
+
+use crate::color_utils::color_to_hex;
+use crate::sync_loop::lock_or_recover;
+use openrgb::data::Color;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File format of the color log.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorLogFormat {
+    /// `timestamp_ms,device,#rrggbb,#rrggbb,...`
+    #[default]
+    Csv,
+    /// `{"timestamp_ms":..,"device":"..","colors":["#rrggbb",..]}`
+    Jsonl,
+}
+
+/// One logged frame.
+#[derive(Serialize)]
+struct ColorRecord<'a> {
+    timestamp_ms: u128,
+    device: &'a str,
+    colors: Vec<String>,
+}
+
+/// Appends one record per device update to a file.
+pub struct ColorLog {
+    writer: Mutex<BufWriter<File>>,
+    format: ColorLogFormat,
+    /// Flush after every record instead of relying on the buffer.
+    flush_each: bool,
+}
+
+impl ColorLog {
+    /// Open (or create) the log file for appending.
+    pub fn open(path: &Path, format: ColorLogFormat, flush_each: bool) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+            format,
+            flush_each,
+        })
+    }
+
+    /// Append a record for the colors just sent to `device`.
+    pub fn record(&self, device: &str, colors: &[Color]) -> io::Result<()> {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let line = format_record(self.format, timestamp_ms, device, colors);
+        let mut writer = lock_or_recover(&self.writer);
+        writeln!(writer, "{line}")?;
+        if self.flush_each {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Serialize one record in the given format, without the trailing newline.
+fn format_record(
+    format: ColorLogFormat,
+    timestamp_ms: u128,
+    device: &str,
+    colors: &[Color],
+) -> String {
+    let hex: Vec<String> = colors
+        .iter()
+        .map(|&c| color_to_hex(c))
+        .collect();
+    match format {
+        ColorLogFormat::Csv => {
+            // Quote the device name so commas in it can't shift the columns
+            let device = format!("\"{}\"", device.replace('"', "\"\""));
+            let mut fields = vec![timestamp_ms.to_string(), device];
+            fields.extend(hex);
+            fields.join(",")
+        }
+        ColorLogFormat::Jsonl => serde_json::to_string(&ColorRecord {
+            timestamp_ms,
+            device,
+            colors: hex,
+        })
+        .expect("color records always serialize"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [Color; 2] = [Color { r: 255, g: 136, b: 0 }, Color { r: 0, g: 0, b: 0 }];
+
+    #[test]
+    fn csv_record() {
+        assert_eq!(
+            format_record(ColorLogFormat::Csv, 1700000000123, "Keyboard", &COLORS),
+            "1700000000123,\"Keyboard\",#ff8800,#000000"
+        );
+        // Quotes in the device name are escaped, commas stay inside the quoted field
+        assert_eq!(
+            format_record(ColorLogFormat::Csv, 5, "Strip \"A\", left", &[]),
+            "5,\"Strip \"\"A\"\", left\""
+        );
+    }
+
+    #[test]
+    fn jsonl_record() {
+        let line = format_record(ColorLogFormat::Jsonl, 1700000000123, "Key\"board", &COLORS);
+        assert_eq!(
+            line,
+            r##"{"timestamp_ms":1700000000123,"device":"Key\"board","colors":["#ff8800","#000000"]}"##
+        );
+        let parsed: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed["device"], "Key\"board");
+    }
+}
@@ -0,0 +1,79 @@
+//! The color input shared by the color-valued menu fields.
+//!
+//! Colors are typed as hex (`#ff8800`, `ff8800` or the `#f80` shorthand) or as decimal
+//! channels (`255 136 0` or `255,136,0`). While such a field is edited, the menu shows
+//! what the input parses to as a live swatch next to the edit box.
+
+use openrgb::data::Color;
+use ratatui::style::{Color as RColor, Style};
+use ratatui::text::{Line, Span, Text};
+
+use crate::color_utils::{color_to_hex, parse_hex_color};
+
+/// Parse a color typed as hex or as three decimal channels.
+pub fn parse_color(input: &str) -> Option<[u8; 3]> {
+    if let Some(Color { r, g, b }) = parse_hex_color(input) {
+        return Some([r, g, b]);
+    }
+    let channels: Vec<u8> = input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    channels.try_into().ok()
+}
+
+/// Format a color the way `parse_color` reads it back.
+pub fn format_color([r, g, b]: [u8; 3]) -> String {
+    color_to_hex(Color { r, g, b })
+}
+
+/// The preview swatch: a bar of `color` over its hex and decimal values, or a hint if
+/// the input isn't a color.
+pub fn swatch(color: Option<[u8; 3]>, hint: Style) -> Text<'static> {
+    let Some([r, g, b]) = color else {
+        return Text::from(vec![
+            Line::styled("not a color", hint),
+            Line::styled("#rrggbb or R G B", hint),
+        ]);
+    };
+    Text::from(vec![
+        Line::from(Span::styled(" ".repeat(16), Style::default().bg(RColor::Rgb(r, g, b)))),
+        Line::from(format_color([r, g, b])),
+        Line::from(format!("R {r} G {g} B {b}")),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_and_shorthand() {
+        assert_eq!(parse_color("#ff8800"), Some([255, 136, 0]));
+        assert_eq!(parse_color("FF8800"), Some([255, 136, 0]));
+        assert_eq!(parse_color("#f80"), Some([255, 136, 0]));
+    }
+
+    #[test]
+    fn parses_decimal_channels() {
+        assert_eq!(parse_color("255 136 0"), Some([255, 136, 0]));
+        assert_eq!(parse_color(" 12,34, 56 "), Some([12, 34, 56]));
+        assert_eq!(parse_color("0 0 0"), Some([0, 0, 0]));
+    }
+
+    #[test]
+    fn rejects_anything_else() {
+        for input in ["", "256 0 0", "1 2", "1 2 3 4", "-1 0 0", "#12345", "orange"] {
+            assert_eq!(parse_color(input), None, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn format_round_trips() {
+        for color in [[0, 0, 0], [255, 255, 255], [18, 52, 86]] {
+            assert_eq!(parse_color(&format_color(color)), Some(color));
+        }
+        assert_eq!(format_color([255, 136, 0]), "#ff8800");
+    }
+}
@@ -5,9 +5,18 @@
 //! adjustments (brightness and saturation).
 
 use openrgb::data::Color;
-use palette::{FromColor, Hsv, RgbHue, Srgb};
+use palette::convert::FromColorUnclamped;
+use palette::{FromColor, Hsv, Lab, Oklab, Oklch, RgbHue, Srgb};
+
+use crate::config::{Easing, InterpolationSpace, ThresholdMetric};
 use palette::IntoColor;
 
+/// Saturation at or below which a color is treated as gray, i.e. as having no defined hue.
+const GRAY_SATURATION: f32 = 1e-3;
+
+/// How far outside 0.0-1.0 a channel may stray and still count as inside the sRGB gamut.
+const GAMUT_EPSILON: f32 = 1e-4;
+
 /// Convert an OpenRGB `Color` to a palette `Srgb<f32>`.
 pub fn color_to_srgb(color: Color) -> Srgb<f32> {
     Srgb::new(
@@ -25,6 +34,73 @@ pub fn srgb_to_color(srgb: Srgb<f32>) -> Color {
     Color { r, g, b }
 }
 
+/// Parse a hex color: `#RRGGBB`, `RRGGBB`, or the `#RGB` shorthand.
+///
+/// Returns `None` for anything else.
+pub fn parse_hex_color(input: &str) -> Option<Color> {
+    let trimmed = input.trim();
+    let hex = trimmed.strip_prefix('#').unwrap_or(trimmed);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+    match hex.len() {
+        6 => Some(Color {
+            r: channel(&hex[0..2])?,
+            g: channel(&hex[2..4])?,
+            b: channel(&hex[4..6])?,
+        }),
+        // Shorthand needs the '#' so it isn't mistaken for a truncated color
+        3 if trimmed.starts_with('#') => {
+            let expand = |i: usize| channel(&hex[i..i + 1]).map(|v| v * 17);
+            Some(Color {
+                r: expand(0)?,
+                g: expand(1)?,
+                b: expand(2)?,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Parse one frame of colors: hex colors separated by whitespace or commas.
+///
+/// Returns the first entry that isn't a valid color as the error.
+pub fn parse_color_frame(line: &str) -> Result<Vec<Color>, String> {
+    line.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| parse_hex_color(part).ok_or_else(|| part.to_string()))
+        .collect()
+}
+
+/// Format a color as a lowercase `#rrggbb` hex string.
+pub fn color_to_hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// Convert a gamma-encoded channel value (0.0-1.0) to linear light.
+pub fn srgb_to_linear(value: f32, gamma: f32) -> f32 {
+    value.clamp(0.0, 1.0).powf(gamma)
+}
+
+/// Convert a linear-light channel value (0.0-1.0) back to its gamma-encoded form.
+pub fn linear_to_srgb(value: f32, gamma: f32) -> f32 {
+    value.clamp(0.0, 1.0).powf(1.0 / gamma)
+}
+
+/// Lookup table mapping every 8-bit channel value to linear light scaled to 0-65535.
+///
+/// Pixels are linearized through this table while they are summed, so averages are taken
+/// in linear light without a `powf` per sample.
+pub fn linearization_table(gamma: f32) -> [u16; 256] {
+    let mut table = [0u16; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        let linear = srgb_to_linear(value as f32 / 255.0, gamma);
+        *entry = (linear * 65535.0).round() as u16;
+    }
+    table
+}
+
 /// Interpolate between two colors in HSV space, with t in [0.0..1.0].
 ///
 /// # Arguments
@@ -40,9 +116,17 @@ pub fn interpolate_color_hsv(start: Srgb<f32>, end: Srgb<f32>, t: f32) -> Srgb<f
     let shsv = Hsv::from_color(start);
     let ehsv = Hsv::from_color(end);
 
+    // A gray endpoint has no meaningful hue, so borrow the other endpoint's hue
+    // instead of sweeping through unrelated colors
+    let mut shue_deg = shsv.hue.into_degrees();
+    let mut ehue_deg = ehsv.hue.into_degrees();
+    if shsv.saturation <= GRAY_SATURATION {
+        shue_deg = ehue_deg;
+    } else if ehsv.saturation <= GRAY_SATURATION {
+        ehue_deg = shue_deg;
+    }
+
     // Handle potential hue wrap-around
-    let shue_deg = shsv.hue.into_degrees();
-    let ehue_deg = ehsv.hue.into_degrees();
     let mut delta_hue = ehue_deg - shue_deg;
     if delta_hue > 180.0 {
         delta_hue -= 360.0;
@@ -62,16 +146,672 @@ pub fn interpolate_color_hsv(start: Srgb<f32>, end: Srgb<f32>, t: f32) -> Srgb<f
     .into_color()
 }
 
+/// Interpolate between two colors in OKLab space, with t in [0.0..1.0].
+///
+/// OKLab is perceptually uniform, so fades between complementary hues pass through
+/// even-looking intermediates instead of HSV's detour around the hue wheel.
+pub fn interpolate_color_oklab(start: Srgb<f32>, end: Srgb<f32>, t: f32) -> Srgb<f32> {
+    let s = Oklab::from_color(start);
+    let e = Oklab::from_color(end);
+    let mixed = Oklab::new(
+        s.l + (e.l - s.l) * t,
+        s.a + (e.a - s.a) * t,
+        s.b + (e.b - s.b) * t,
+    );
+    let rgb = Srgb::from_color(mixed);
+    Srgb::new(
+        rgb.red.clamp(0.0, 1.0),
+        rgb.green.clamp(0.0, 1.0),
+        rgb.blue.clamp(0.0, 1.0),
+    )
+}
+
+/// Interpolate between two colors in linear-light RGB, with t in [0.0..1.0].
+pub fn interpolate_color_linear(start: Srgb<f32>, end: Srgb<f32>, t: f32) -> Srgb<f32> {
+    let s = start.into_linear();
+    let e = end.into_linear();
+    let mixed = s + (e - s) * t;
+    Srgb::from_linear(mixed)
+}
+
+/// Interpolate between two colors in the given color space, with t in [0.0..1.0].
+pub fn interpolate_color(
+    space: InterpolationSpace,
+    start: Srgb<f32>,
+    end: Srgb<f32>,
+    t: f32,
+) -> Srgb<f32> {
+    match space {
+        InterpolationSpace::Hsv => interpolate_color_hsv(start, end, t),
+        InterpolationSpace::Oklab => interpolate_color_oklab(start, end, t),
+        InterpolationSpace::LinearRgb => interpolate_color_linear(start, end, t),
+    }
+}
+
+/// Limits on how strongly the saturation boost applies to near-neutral or protected hues.
+///
+/// The default (`strength` 0.0) leaves the boost untouched.
+#[derive(Debug, Clone, Default)]
+pub struct SaturationProtection {
+    /// How much of the boost is withheld from protected colors (0.0-1.0).
+    pub strength: f32,
+    /// Saturation below which colors are protected, fading out towards this value.
+    pub low_saturation: f32,
+    /// Hue ranges in degrees (`[start, end]`, may wrap past 360) that are always protected.
+    pub hue_ranges: Vec<[f32; 2]>,
+}
+
+impl SaturationProtection {
+    /// The saturation factor to apply to `hsv` instead of `factor`.
+    ///
+    /// Only boosts are damped; a factor below 1.0 is returned unchanged.
+    pub fn effective_factor(&self, hsv: &Hsv, factor: f32) -> f32 {
+        if self.strength <= 0.0 || factor <= 1.0 {
+            return factor;
+        }
+        // 0.0 = fully protected, 1.0 = unprotected
+        let low_saturation_weight = if self.low_saturation > 0.0 {
+            (hsv.saturation / self.low_saturation).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let hue = hsv.hue.into_positive_degrees();
+        let in_protected_hue = self.hue_ranges.iter().any(|&[start, end]| {
+            if start <= end {
+                (start..=end).contains(&hue)
+            } else {
+                hue >= start || hue <= end
+            }
+        });
+        let weight = if in_protected_hue { 0.0 } else { low_saturation_weight };
+        let damping = self.strength.clamp(0.0, 1.0) * (1.0 - weight);
+        1.0 + (factor - 1.0) * (1.0 - damping)
+    }
+}
+
 /// Increase the saturation of an `Srgb<f32>` color by a given factor, clamping at 1.0.
-pub fn adjust_saturation(srgb: Srgb<f32>, factor: f32) -> Srgb<f32> {
+///
+/// The boost is reduced for colors covered by `protection`.
+pub fn adjust_saturation(
+    srgb: Srgb<f32>,
+    factor: f32,
+    protection: &SaturationProtection,
+) -> Srgb<f32> {
     let mut hsv = Hsv::from_color(srgb);
+    let factor = protection.effective_factor(&hsv, factor);
     hsv.saturation = (hsv.saturation * factor).clamp(0.0, 1.0);
     hsv.into_color()
 }
 
+/// Scale the OKLCh chroma of an `Srgb<f32>` color by `factor`, keeping lightness and hue.
+///
+/// A boost that would leave the sRGB gamut is reduced to the largest chroma that still
+/// fits, rather than clipping each channel (which would change the lightness).
+pub fn adjust_saturation_oklch(srgb: Srgb<f32>, factor: f32) -> Srgb<f32> {
+    let lch = Oklch::from_color(srgb);
+    let with_chroma = |chroma: f32| Srgb::from_color_unclamped(Oklch { chroma, ..lch });
+    let in_gamut = |color: Srgb<f32>| {
+        [color.red, color.green, color.blue]
+            .iter()
+            .all(|channel| (-GAMUT_EPSILON..=1.0 + GAMUT_EPSILON).contains(channel))
+    };
+
+    let target = lch.chroma * factor.max(0.0);
+    let mut adjusted = with_chroma(target);
+    if !in_gamut(adjusted) {
+        // The original chroma is in gamut; search between it and the target
+        let (mut low, mut high) = (lch.chroma.min(target), target);
+        for _ in 0..16 {
+            let mid = (low + high) / 2.0;
+            if in_gamut(with_chroma(mid)) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        adjusted = with_chroma(low);
+    }
+    Srgb::new(
+        adjusted.red.clamp(0.0, 1.0),
+        adjusted.green.clamp(0.0, 1.0),
+        adjusted.blue.clamp(0.0, 1.0),
+    )
+}
+
+/// Apply brightness and saturation factors in a single HSV round-trip.
+///
+/// Equivalent to `increase_brightness` followed by `adjust_saturation`, but converts only
+/// once so repeated adjustments don't accumulate hue drift. Grays have no defined hue, so
+/// their saturation is left at zero rather than boosting whatever hue the conversion produced.
+pub fn adjust_brightness_saturation(
+    srgb: Srgb<f32>,
+    brightness_factor: f32,
+    saturation_factor: f32,
+    protection: &SaturationProtection,
+) -> Srgb<f32> {
+    let mut hsv = Hsv::from_color(srgb);
+    hsv.value = (hsv.value * brightness_factor).clamp(0.0, 1.0);
+    if hsv.saturation <= GRAY_SATURATION {
+        hsv.saturation = 0.0;
+    } else {
+        let factor = protection.effective_factor(&hsv, saturation_factor);
+        hsv.saturation = (hsv.saturation * factor).clamp(0.0, 1.0);
+    }
+    hsv.into_color()
+}
+
 /// Increase the brightness of an `Srgb<f32>` color by a given factor, clamping at 1.0.
 pub fn increase_brightness(srgb: Srgb<f32>, factor: f32) -> Srgb<f32> {
     let mut hsv = Hsv::from_color(srgb);
     hsv.value = (hsv.value * factor).clamp(0.0, 1.0);
     hsv.into_color()
 }
+
+/// Map linear transition progress `t` (0.0-1.0) through the easing curve `kind`.
+///
+/// Every curve starts at 0.0 and ends at 1.0; `t` outside that range is clamped.
+pub fn ease(t: f32, kind: Easing) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    match kind {
+        Easing::Linear => t,
+        Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+        Easing::Cubic => {
+            if t < 0.5 {
+                4.0 * t * t * t
+            } else {
+                1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+            }
+        }
+    }
+}
+
+/// Bend a 0.0-1.0 `value` by `value^(1/gamma)`.
+///
+/// The curve is monotonic and keeps 0.0 and 1.0 in place, so unlike a linear factor it
+/// never clips. A gamma above 1.0 raises low values, below 1.0 lowers them, and 1.0 leaves
+/// the value unchanged.
+pub fn apply_gamma_curve(value: f32, gamma: f32) -> f32 {
+    if gamma == 1.0 || gamma <= 0.0 {
+        return value.clamp(0.0, 1.0);
+    }
+    value.clamp(0.0, 1.0).powf(1.0 / gamma)
+}
+
+/// Apply `apply_gamma_curve` to the HSV value and saturation of a color.
+pub fn apply_curves(srgb: Srgb<f32>, brightness_gamma: f32, saturation_gamma: f32) -> Srgb<f32> {
+    if brightness_gamma == 1.0 && saturation_gamma == 1.0 {
+        return srgb;
+    }
+    let mut hsv = Hsv::from_color(srgb);
+    hsv.value = apply_gamma_curve(hsv.value, brightness_gamma);
+    hsv.saturation = apply_gamma_curve(hsv.saturation, saturation_gamma);
+    hsv.into_color()
+}
+
+/// Chroma (saturation times value) below which `ThresholdMetric::HueDelta` compares
+/// brightness instead of hue.
+const HUE_DELTA_MIN_CHROMA: f32 = 0.05;
+
+/// Difference between two colors under `metric`, on the scale of `color_change_threshold`.
+pub fn color_distance(metric: ThresholdMetric, a: Color, b: Color) -> f32 {
+    match metric {
+        ThresholdMetric::RgbEuclidean => {
+            let dr = a.r as f32 - b.r as f32;
+            let dg = a.g as f32 - b.g as f32;
+            let db = a.b as f32 - b.b as f32;
+            (dr * dr + dg * dg + db * db).sqrt() / 255.0
+        }
+        ThresholdMetric::HueDelta => {
+            let a = Hsv::from_color(color_to_srgb(a));
+            let b = Hsv::from_color(color_to_srgb(b));
+            let chroma = (a.saturation * a.value).min(b.saturation * b.value);
+            if chroma < HUE_DELTA_MIN_CHROMA {
+                return (a.value - b.value).abs();
+            }
+            let delta = (a.hue - b.hue).into_degrees().abs();
+            delta / 180.0 * chroma
+        }
+        ThresholdMetric::DeltaE => {
+            let lab = |color: Color| Lab::from_color(color_to_srgb(color).into_linear());
+            let (a, b): (Lab, Lab) = (lab(a), lab(b));
+            let (dl, da, db) = (a.l - b.l, a.a - b.a, a.b - b.b);
+            (dl * dl + da * da + db * db).sqrt() / 100.0
+        }
+    }
+}
+
+/// Raise the HSV value of a color to at least `floor`, keeping its hue and saturation.
+///
+/// Black has no hue, so it becomes a dim gray.
+pub fn apply_min_brightness(srgb: Srgb<f32>, floor: f32) -> Srgb<f32> {
+    if floor <= 0.0 {
+        return srgb;
+    }
+    let mut hsv = Hsv::from_color(srgb);
+    hsv.value = hsv.value.max(floor.min(1.0));
+    hsv.into_color()
+}
+
+/// Reference white of `apply_white_balance`, at which it leaves colors unchanged.
+pub const NEUTRAL_KELVIN: u16 = 6500;
+
+/// Approximate RGB color (0.0-1.0) of a blackbody at `kelvin`.
+///
+/// Uses Tanner Helland's curve fit of the blackbody locus, valid for 1000-40000 K.
+fn blackbody_rgb(kelvin: f32) -> [f32; 3] {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    } else {
+        329.698_73 * (t - 60.0).powf(-0.133_204_76)
+    };
+    let green = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_16 * (t - 60.0).powf(-0.075_514_85)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+    [red, green, blue].map(|c| (c / 255.0).clamp(0.0, 1.0))
+}
+
+/// Tint a color towards the white of a blackbody at `kelvin`.
+///
+/// Each channel is scaled by the ratio of that blackbody's color to the one at
+/// `NEUTRAL_KELVIN`, so lower values warm the output up and higher values cool it down.
+pub fn apply_white_balance(srgb: Srgb<f32>, kelvin: u16) -> Srgb<f32> {
+    if kelvin == NEUTRAL_KELVIN {
+        return srgb;
+    }
+    let target = blackbody_rgb(kelvin as f32);
+    let neutral = blackbody_rgb(NEUTRAL_KELVIN as f32);
+    let gain = |i: usize| target[i] / neutral[i].max(f32::EPSILON);
+    Srgb::new(
+        (srgb.red * gain(0)).clamp(0.0, 1.0),
+        (srgb.green * gain(1)).clamp(0.0, 1.0),
+        (srgb.blue * gain(2)).clamp(0.0, 1.0),
+    )
+}
+
+/// HSV saturation below which `warm_whites` starts to tint a color.
+const NEAR_WHITE_SATURATION: f32 = 0.15;
+
+/// Pull near-white colors towards the white of a blackbody at `kelvin`.
+///
+/// The tint fades out as saturation rises towards `NEAR_WHITE_SATURATION`, so saturated
+/// colors are left alone and only whites and pale tints are warmed (or cooled).
+pub fn warm_whites(color: Color, kelvin: u16) -> Color {
+    let srgb = color_to_srgb(color);
+    let saturation = Hsv::from_color(srgb).saturation;
+    let weight = (1.0 - saturation / NEAR_WHITE_SATURATION).clamp(0.0, 1.0);
+    if weight == 0.0 {
+        return color;
+    }
+    let tinted = apply_white_balance(srgb, kelvin);
+    srgb_to_color(interpolate_color_linear(srgb, tinted, weight))
+}
+
+/// Scale a color down so that no channel exceeds `max_channel`.
+///
+/// All channels are scaled by the same factor, so the ratios between them (and with them
+/// the hue) are preserved. Colors already within the limit are returned unchanged.
+pub fn clamp_max_channel(color: Color, max_channel: u8) -> Color {
+    let peak = color.r.max(color.g).max(color.b);
+    if peak <= max_channel {
+        return color;
+    }
+    let scale = max_channel as f32 / peak as f32;
+    let scaled = |channel: u8| (channel as f32 * scale).round().min(max_channel as f32) as u8;
+    Color {
+        r: scaled(color.r),
+        g: scaled(color.g),
+        b: scaled(color.b),
+    }
+}
+
+/// Multiply every channel of a color by `factor`, clamping to the valid byte range.
+pub fn scale_color(color: Color, factor: f32) -> Color {
+    if factor == 1.0 {
+        return color;
+    }
+    let scaled = |channel: u8| (channel as f32 * factor).round().clamp(0.0, 255.0) as u8;
+    Color {
+        r: scaled(color.r),
+        g: scaled(color.g),
+        b: scaled(color.b),
+    }
+}
+
+/// Correct each channel of a color for the hardware: `channel * gain + offset`, clamped.
+///
+/// The default gain of 1.0 and offset of 0 leave a channel unchanged.
+pub fn apply_channel_calibration(color: Color, gain: [f32; 3], offset: [i16; 3]) -> Color {
+    let correct = |channel: u8, i: usize| -> u8 {
+        (channel as f32 * gain[i] + offset[i] as f32)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    Color {
+        r: correct(color.r, 0),
+        g: correct(color.g, 1),
+        b: correct(color.b, 2),
+    }
+}
+
+/// Apply a separate gamma exponent to each channel of an OpenRGB `Color`.
+///
+/// Each channel is normalized to [0.0..1.0] and raised to its gamma, so black and full
+/// intensity stay fixed while midtones shift. A gamma of 1.0 leaves the channel unchanged.
+pub fn apply_channel_gamma(color: Color, gamma: [f32; 3]) -> Color {
+    let correct = |channel: u8, gamma: f32| -> u8 {
+        if gamma == 1.0 {
+            return channel;
+        }
+        let normalized = channel as f32 / 255.0;
+        (normalized.powf(gamma.max(f32::EPSILON)) * 255.0)
+            .clamp(0.0, 255.0)
+            .round() as u8
+    };
+    Color {
+        r: correct(color.r, gamma[0]),
+        g: correct(color.g, gamma[1]),
+        b: correct(color.b, gamma[2]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+    const WHITE: Color = Color { r: 255, g: 255, b: 255 };
+    const RED: Color = Color { r: 255, g: 0, b: 0 };
+    const GREEN: Color = Color { r: 0, g: 255, b: 0 };
+    const BLUE: Color = Color { r: 0, g: 0, b: 255 };
+
+    fn assert_close(actual: Srgb<f32>, expected: Srgb<f32>) {
+        let channels = |c: Srgb<f32>| [c.red, c.green, c.blue];
+        for (a, e) in channels(actual).into_iter().zip(channels(expected)) {
+            assert!((a - e).abs() < 1e-3, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn srgb_round_trip_is_stable() {
+        let orange = Color { r: 255, g: 136, b: 17 };
+        for color in [BLACK, WHITE, RED, GREEN, BLUE, orange] {
+            assert_eq!(srgb_to_color(color_to_srgb(color)), color);
+        }
+    }
+
+    #[test]
+    fn srgb_to_color_clamps_out_of_range_channels() {
+        assert_eq!(srgb_to_color(Srgb::new(1.5, -0.2, 0.5)), Color { r: 255, g: 0, b: 128 });
+    }
+
+    #[test]
+    fn hsv_interpolation_wraps_through_zero() {
+        let start: Srgb<f32> = Hsv::new(RgbHue::from_degrees(350.0), 1.0, 1.0).into_color();
+        let end: Srgb<f32> = Hsv::new(RgbHue::from_degrees(10.0), 1.0, 1.0).into_color();
+
+        // Halfway is pure red (0°), not cyan (180°) as the long way round would give
+        assert_close(interpolate_color_hsv(start, end, 0.5), Srgb::new(1.0, 0.0, 0.0));
+        let quarter = Hsv::from_color(interpolate_color_hsv(start, end, 0.25));
+        assert!((quarter.hue.into_positive_degrees() - 355.0).abs() < 0.1);
+        assert_close(interpolate_color_hsv(start, end, 0.0), start);
+        assert_close(interpolate_color_hsv(start, end, 1.0), end);
+    }
+
+    #[test]
+    fn adjust_saturation_clamps() {
+        let protection = SaturationProtection::default();
+        let pastel = Srgb::new(1.0, 0.5, 0.5);
+        let boosted = Hsv::from_color(adjust_saturation(pastel, 10.0, &protection));
+        assert!((boosted.saturation - 1.0).abs() < 1e-4);
+        assert_close(adjust_saturation(pastel, 10.0, &protection), Srgb::new(1.0, 0.0, 0.0));
+        assert_close(adjust_saturation(pastel, -1.0, &protection), Srgb::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn increase_brightness_clamps() {
+        let dim_red = Srgb::new(0.5, 0.0, 0.0);
+        assert_close(increase_brightness(dim_red, 10.0), Srgb::new(1.0, 0.0, 0.0));
+        assert_close(increase_brightness(dim_red, -1.0), Srgb::new(0.0, 0.0, 0.0));
+        assert_close(increase_brightness(Srgb::new(1.0, 1.0, 1.0), 2.0), Srgb::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn parse_hex_color_accepts_full_colors() {
+        let color = Color { r: 0x1a, g: 0xff, b: 0xc3 };
+        assert_eq!(parse_hex_color("#1affc3"), Some(color));
+        assert_eq!(parse_hex_color("1AFFC3"), Some(color));
+        assert_eq!(parse_hex_color("  #1aFFc3 "), Some(color));
+        assert_eq!(color_to_hex(color), "#1affc3");
+        for color in [BLACK, WHITE, RED, GREEN, BLUE] {
+            assert_eq!(parse_hex_color(&color_to_hex(color)), Some(color));
+        }
+    }
+
+    #[test]
+    fn parse_hex_color_expands_shorthand() {
+        let orange = Color { r: 0xff, g: 0x88, b: 0x00 };
+        assert_eq!(parse_hex_color("#f80"), Some(orange));
+        assert_eq!(parse_hex_color("#F80"), Some(orange));
+        // Without the '#' three digits read as a truncated color
+        assert_eq!(parse_hex_color("f80"), None);
+    }
+
+    #[test]
+    fn parse_hex_color_rejects_malformed_input() {
+        let malformed = [
+            "", "#", "#12345", "#1234567", "#gg0000", "12 34 56", "##123456", "#+1+2+3", "#f\u{e9}0",
+            "red",
+        ];
+        for input in malformed {
+            assert_eq!(parse_hex_color(input), None, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn gamma_curve_is_monotonic_and_bounded() {
+        for gamma in [0.25, 0.5, 1.0, 2.2, 4.0] {
+            let mut previous = apply_gamma_curve(0.0, gamma);
+            assert_eq!(previous, 0.0);
+            for i in 1..=100 {
+                let value = apply_gamma_curve(i as f32 / 100.0, gamma);
+                assert!((0.0..=1.0).contains(&value), "gamma {gamma}: {value}");
+                assert!(value >= previous, "gamma {gamma} decreases at {i}");
+                previous = value;
+            }
+            assert!((previous - 1.0).abs() < 1e-6);
+        }
+        // Out-of-range input is clamped rather than extrapolated
+        assert_eq!(apply_gamma_curve(-0.5, 2.0), 0.0);
+        assert_eq!(apply_gamma_curve(1.5, 0.5), 1.0);
+    }
+
+    #[test]
+    fn gamma_of_one_is_a_no_op() {
+        for value in [0.0, 0.1, 0.5, 0.9, 1.0] {
+            assert_eq!(apply_gamma_curve(value, 1.0), value);
+        }
+        let color = Srgb::new(0.8, 0.3, 0.1);
+        assert_eq!(apply_curves(color, 1.0, 1.0), color);
+        // A gamma above 1.0 lifts shadows
+        assert!(apply_gamma_curve(0.2, 2.0) > 0.2);
+    }
+
+    #[test]
+    fn oklch_saturation_keeps_lightness() {
+        let colors = [
+            Srgb::new(0.8, 0.3, 0.1),
+            Srgb::new(0.2, 0.5, 0.7),
+            Srgb::new(0.6, 0.6, 0.55),
+            Srgb::new(1.0, 0.0, 0.0),
+        ];
+        for color in colors {
+            let lightness = Oklch::from_color(color).l;
+            for factor in [0.0, 0.5, 2.0, 10.0] {
+                let adjusted = Oklch::from_color(adjust_saturation_oklch(color, factor));
+                assert!(
+                    (adjusted.l - lightness).abs() < 1e-3,
+                    "{color:?} x{factor}: L {} -> {}",
+                    lightness,
+                    adjusted.l
+                );
+            }
+        }
+        // The boost still happens where the gamut allows it
+        let muted = Srgb::new(0.6, 0.5, 0.45);
+        let boosted = adjust_saturation_oklch(muted, 2.0);
+        assert!(Oklch::from_color(boosted).chroma > Oklch::from_color(muted).chroma);
+    }
+
+    #[test]
+    fn max_channel_clamp_preserves_hue() {
+        let hue = |color: Color| Hsv::from_color(color_to_srgb(color)).hue.into_positive_degrees();
+        let colors = [
+            RED,
+            Color { r: 255, g: 128, b: 0 },
+            Color { r: 40, g: 200, b: 240 },
+            Color { r: 250, g: 10, b: 180 },
+        ];
+        for color in colors {
+            let clamped = clamp_max_channel(color, 180);
+            assert_eq!(clamped.r.max(clamped.g).max(clamped.b), 180);
+            assert!((hue(clamped) - hue(color)).abs() < 1.0, "{color:?} -> {clamped:?}");
+        }
+        // Colors within the limit pass through unchanged
+        let dim = Color { r: 100, g: 20, b: 60 };
+        assert_eq!(clamp_max_channel(dim, 180), dim);
+        assert_eq!(clamp_max_channel(WHITE, 255), WHITE);
+    }
+
+    #[test]
+    fn warm_whites_only_tints_near_white() {
+        let warmed = warm_whites(WHITE, 3000);
+        assert!(warmed.r > warmed.b, "{warmed:?}");
+        assert_eq!(warm_whites(RED, 3000), RED);
+        assert_eq!(warm_whites(BLUE, 3000), BLUE);
+    }
+
+    #[test]
+    fn channel_gamma_shifts_midtones_only() {
+        let gamma = [1.0, 1.2, 0.8];
+        assert_eq!(apply_channel_gamma(BLACK, gamma), BLACK);
+        assert_eq!(apply_channel_gamma(WHITE, gamma), WHITE);
+
+        let gray = Color { r: 128, g: 128, b: 128 };
+        let corrected = apply_channel_gamma(gray, gamma);
+        assert_eq!(corrected.r, 128);
+        assert!(corrected.g < 128 && corrected.b > 128, "{corrected:?}");
+        assert_eq!(apply_channel_gamma(gray, [1.0; 3]), gray);
+    }
+
+    #[test]
+    fn red_to_cyan_midpoints_per_space() {
+        let red = Srgb::new(1.0, 0.0, 0.0);
+        let cyan = Srgb::new(0.0, 1.0, 1.0);
+        let midpoint = |space| interpolate_color(space, red, cyan, 0.5);
+
+        // HSV keeps full saturation and value, passing through a vivid hue
+        let hsv = midpoint(InterpolationSpace::Hsv);
+        let channels = [hsv.red, hsv.green, hsv.blue];
+        assert!((channels.iter().cloned().fold(0.0, f32::max) - 1.0).abs() < 1e-3);
+        assert!(channels.iter().cloned().fold(1.0, f32::min).abs() < 1e-3);
+
+        // Linear light averages to a light gray (0.5 linear is about 0.735 encoded)
+        let linear = midpoint(InterpolationSpace::LinearRgb);
+        assert_close(linear, Srgb::new(0.7354, 0.7354, 0.7354));
+
+        // OKLab lands halfway in perceived lightness
+        let oklab = Oklab::from_color(midpoint(InterpolationSpace::Oklab));
+        let halfway = (Oklab::from_color(red).l + Oklab::from_color(cyan).l) / 2.0;
+        assert!((oklab.l - halfway).abs() < 1e-3, "{} vs {halfway}", oklab.l);
+
+        let spaces = [
+            InterpolationSpace::Hsv,
+            InterpolationSpace::Oklab,
+            InterpolationSpace::LinearRgb,
+        ];
+        for space in spaces {
+            assert_close(interpolate_color(space, red, cyan, 0.0), red);
+            assert_close(interpolate_color(space, red, cyan, 1.0), cyan);
+        }
+    }
+
+    #[test]
+    fn protection_boosts_low_saturation_less() {
+        let protection = SaturationProtection {
+            strength: 1.0,
+            low_saturation: 0.4,
+            hue_ranges: Vec::new(),
+        };
+        let saturation_gain = |color: Srgb<f32>| {
+            let before = Hsv::from_color(color).saturation;
+            Hsv::from_color(adjust_saturation(color, 2.0, &protection)).saturation / before
+        };
+        let pale = Srgb::new(0.8, 0.72, 0.72); // saturation 0.1
+        let mid = Srgb::new(0.8, 0.48, 0.48); // saturation 0.4
+        assert!(saturation_gain(pale) < saturation_gain(mid));
+        assert!((saturation_gain(mid) - 2.0).abs() < 1e-3);
+
+        // Protected hues aren't boosted at all
+        let skin = SaturationProtection {
+            hue_ranges: vec![[10.0, 40.0]],
+            ..protection
+        };
+        let tan = Srgb::new(0.8, 0.56, 0.4);
+        let boosted = Hsv::from_color(adjust_saturation(tan, 2.0, &skin)).saturation;
+        assert!((boosted - Hsv::from_color(tan).saturation).abs() < 1e-4);
+    }
+
+    #[test]
+    fn easing_curves_keep_their_endpoints() {
+        for kind in [Easing::Linear, Easing::EaseInOut, Easing::EaseOut, Easing::Cubic] {
+            assert_eq!(ease(0.0, kind), 0.0, "{kind:?}");
+            assert_eq!(ease(1.0, kind), 1.0, "{kind:?}");
+            assert_eq!(ease(-0.5, kind), 0.0, "{kind:?}");
+            assert_eq!(ease(1.5, kind), 1.0, "{kind:?}");
+            let mut previous = 0.0;
+            for i in 1..=20 {
+                let value = ease(i as f32 / 20.0, kind);
+                assert!(value >= previous, "{kind:?} decreases at {i}");
+                previous = value;
+            }
+        }
+        assert_eq!(ease(0.5, Easing::EaseInOut), 0.5);
+        assert!(ease(0.25, Easing::EaseOut) > 0.25);
+        assert!(ease(0.25, Easing::Cubic) < 0.25);
+    }
+
+    #[test]
+    fn repeated_adjustments_keep_near_gray_hue() {
+        let protection = SaturationProtection::default();
+        let warm_gray = Srgb::new(0.50, 0.49, 0.48);
+        let hue = |c: Srgb<f32>| Hsv::from_color(c).hue.into_positive_degrees();
+        let start_hue = hue(warm_gray);
+
+        let mut color = warm_gray;
+        for frame in 0..500 {
+            // Brightness and saturation wobbling up and down, as with adaptive factors
+            let (brightness, saturation) = if frame % 2 == 0 { (1.05, 1.1) } else { (0.95, 0.9) };
+            color = adjust_brightness_saturation(color, brightness, saturation, &protection);
+            let drift = (hue(color) - start_hue).abs();
+            assert!(drift.min(360.0 - drift) < 2.0, "frame {frame}: {color:?}");
+        }
+
+        // A true gray never picks up a hue at all
+        let mut gray = Srgb::new(0.5, 0.5, 0.5);
+        for _ in 0..500 {
+            gray = adjust_brightness_saturation(gray, 1.01, 1.5, &protection);
+            assert!(gray.red == gray.green && gray.green == gray.blue, "{gray:?}");
+        }
+    }
+}
@@ -1,48 +1,841 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use directories::ProjectDirs;
+use tracing::{error, info, warn};
 
+use crate::color_log::ColorLogFormat;
+
+/// One OpenRGB device driven by KeyBloom.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Config {
+#[serde(default)]
+pub struct DeviceConfig {
+    /// Name (or part of the name) of the OpenRGB controller.
+    pub device_name: String,
+    /// Number of LEDs to drive on this device.
     pub num_leds: usize,
+    /// Multiplier for this device's output brightness, to balance it against the others
+    /// (1.0 = unchanged).
+    pub brightness_scale: f32,
+}
+
+/// How the captured screen is divided into the segments averaged for the LEDs.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum SegmentationMode {
+    /// One column per LED, left to right.
+    #[default]
+    Vertical,
+    /// One row per LED, top to bottom.
+    Horizontal,
+    /// A `rows` x `cols` grid mapped to the LEDs in row-major order.
+    Grid { rows: usize, cols: usize },
+    /// Equal stretches of the screen's border, clockwise from the top-left corner. Every
+    /// pixel belongs to the nearest edge, as for LEDs mounted around a monitor.
+    Edge,
+    /// Equal slices of angle around the screen's center, clockwise from 12 o'clock, as
+    /// for a ring device.
+    Radial,
+}
+
+impl SegmentationMode {
+    /// Number of segments to average when the largest device has `led_count` LEDs.
+    pub fn segment_count(&self, led_count: usize) -> usize {
+        match *self {
+            SegmentationMode::Vertical
+            | SegmentationMode::Horizontal
+            | SegmentationMode::Edge
+            | SegmentationMode::Radial => led_count,
+            SegmentationMode::Grid { rows, cols } => rows * cols,
+        }
+    }
+}
+
+/// Color space in which transitions are interpolated.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum InterpolationSpace {
+    /// Hue, saturation and value; sweeps around the hue wheel.
+    #[default]
+    Hsv,
+    /// Perceptually uniform OKLab.
+    Oklab,
+    /// Linear-light RGB.
+    LinearRgb,
+}
+
+/// How `saturation_factor` changes a color's saturation.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SaturationMode {
+    /// Scale HSV saturation; strong boosts can shift perceived brightness and clip.
+    #[default]
+    Hsv,
+    /// Scale OKLCh chroma at constant lightness, staying within the sRGB gamut.
+    Oklch,
+}
+
+/// Easing curve applied to the progress of a transition.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    /// Constant speed.
+    #[default]
+    Linear,
+    /// Starts and ends gently (smoothstep).
+    EaseInOut,
+    /// Starts fast and slows down towards the target.
+    EaseOut,
+    /// Like `EaseInOut`, but with a longer gentle start and end (cubic).
+    Cubic,
+}
+
+/// Color scheme of the TUI.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UiTheme {
+    /// The original cyan and yellow scheme.
+    #[default]
+    Default,
+    /// Muted colors for dark terminal backgrounds.
+    Dark,
+    /// White on black with a yellow selection, for readability.
+    HighContrast,
+    /// No colors, only bold, underline and reverse video.
+    Mono,
+}
+
+impl UiTheme {
+    /// All themes in the order the menu cycles through them.
+    pub const ALL: [UiTheme; 4] = [
+        UiTheme::Default,
+        UiTheme::Dark,
+        UiTheme::HighContrast,
+        UiTheme::Mono,
+    ];
+
+    /// The theme after this one, wrapping around.
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&theme| theme == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// How the color of each segment is derived from its sampled pixels.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum ColorExtraction {
+    /// Average of all sampled pixels.
+    #[default]
+    Average,
+    /// Centroid of the largest of `k` k-means clusters.
+    DominantKMeans { k: usize },
+}
+
+/// How the captured frame's aspect ratio is reconciled with the LED layout's.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AspectMode {
+    /// Map the whole frame onto the layout, distorting it if the aspects differ.
+    #[default]
+    Stretch,
+    /// Letterbox the frame inside the layout; LEDs outside it show the fill color.
+    Fit,
+    /// Crop the frame to the layout's aspect so every LED maps to content.
+    Fill,
+}
+
+/// How the two ends of the LED array are treated by neighbour-based processing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LedEdges {
+    /// The first and last LED are the ends of a strip, with one neighbour each.
+    #[default]
+    Clamp,
+    /// The LEDs form a ring: the last LED and the first are neighbours.
+    Wrap,
+}
+
+/// Numeric type used to sum the sampled pixels of each segment.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Accumulator {
+    /// Integer sums of 16-bit linearized channels.
+    #[default]
+    U64,
+    /// Floating-point sums of exactly linearized channels; more precise in dark tones.
+    F32,
+}
+
+/// Serde adapter storing `[r, g, b]` colors as `"#rrggbb"` strings.
+///
+/// Arrays are still accepted when reading, so configs written before colors were stored
+/// as hex keep loading.
+mod hex_color {
+    use openrgb::data::Color;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    use crate::color_utils::{color_to_hex, parse_hex_color};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawColor {
+        Hex(String),
+        Channels([u8; 3]),
+    }
+
+    pub fn serialize<S: Serializer>(color: &[u8; 3], serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b] = *color;
+        serializer.serialize_str(&color_to_hex(Color { r, g, b }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 3], D::Error> {
+        match RawColor::deserialize(deserializer)? {
+            RawColor::Channels(channels) => Ok(channels),
+            RawColor::Hex(hex) => parse_hex_color(&hex)
+                .map(|c| [c.r, c.g, c.b])
+                .ok_or_else(|| de::Error::custom(format!("invalid hex color '{hex}'"))),
+        }
+    }
+}
+
+/// How the difference between the current and the target color of an LED is measured for
+/// `color_change_threshold` and `scene_cut_threshold`.
+///
+/// Each metric is scaled so that a threshold of about 0.05 is a sensible starting point.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdMetric {
+    /// Euclidean RGB distance, as a fraction of one full channel (255).
+    #[default]
+    RgbEuclidean,
+    /// Hue difference as a fraction of 180°, weighted by how colorful both colors are, so
+    /// brightness changes alone don't count. Near-grays compare their brightness instead.
+    HueDelta,
+    /// Perceptual CIE76 ΔE in Lab, as a fraction of 100 (0.05 ≈ a clearly visible change).
+    DeltaE,
+}
+
+/// What KeyBloom does with the devices.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SyncMode {
+    /// Follow the screen colors.
+    #[default]
+    ScreenSync,
+    /// Show one fixed color without capturing the screen.
+    Static {
+        #[serde(with = "hex_color")]
+        color: [u8; 3],
+    },
+    /// Show the colors of the desktop wallpaper (or `wallpaper_image`) without capturing
+    /// the screen.
+    Wallpaper,
+    /// Transition to the frames of hex colors read from stdin, one frame per line. Meant
+    /// for `--headless`, where stdin isn't the terminal.
+    External,
+    /// Turn the LEDs off and exit.
+    Off,
+    /// Cycle a rainbow across the LEDs, `speed` times per second.
+    Rainbow { speed: f32 },
+    /// Fade `color` in and out, `speed` times per second.
+    Breathing {
+        #[serde(with = "hex_color")]
+        color: [u8; 3],
+        speed: f32,
+    },
+}
+
+impl SyncMode {
+    /// Default speed (cycles per second) of the rainbow effect.
+    pub const RAINBOW_SPEED: f32 = 0.1;
+    /// Default speed (breaths per second) of the breathing effect.
+    pub const BREATHING_SPEED: f32 = 0.25;
+}
+
+/// What the screen sync captures.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CaptureTarget {
+    /// The whole monitor selected by `monitor_index`.
+    #[default]
+    Monitor,
+    /// The first window whose title contains `title` (case-insensitive). While no such
+    /// window exists, the monitor is captured instead.
+    Window { title: String },
+}
+
+/// Display server the screen is expected to be captured through (Linux only).
+///
+/// xcap picks the backend from the session (`XDG_SESSION_TYPE`, `WAYLAND_DISPLAY`); a
+/// mismatch with this hint is reported together with how to switch.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureBackend {
+    /// Whatever the session uses.
+    #[default]
+    Auto,
+    /// X11, including XWayland.
+    X11,
+    /// The Wayland screenshot portal.
+    Wayland,
+}
+
+/// A monitor that fills a range of LEDs (see `Config::monitor_mappings`).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonitorMapping {
+    /// Index of the monitor to capture, as listed by `list-monitors`.
+    pub monitor_index: usize,
+    /// First LED (0-based) filled from this monitor.
+    pub first_led: usize,
+    /// Number of LEDs filled from this monitor.
+    pub led_count: usize,
+}
+
+/// A rectangle of the monitor, in pixels from its top-left corner.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Clip the region to a `width` x `height` area; `None` if nothing of it remains.
+    pub fn clamped(&self, width: u32, height: u32) -> Option<Region> {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        let region = Region {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        };
+        (region.width > 0 && region.height > 0).then_some(region)
+    }
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            device_name: "G213".to_string(),
+            num_leds: 5,
+            brightness_scale: 1.0,
+        }
+    }
+}
+
+/// Connection to an MQTT broker, e.g. for Home Assistant.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MqttConfig {
+    /// Hostname or IP of the broker.
+    pub host: String,
+    /// Port of the broker.
+    pub port: u16,
+    /// Base topic: the color is published to `<topic>/color`, the sync state to
+    /// `<topic>/state`, and `ON`/`OFF` commands are read from `<topic>/set`.
+    pub topic: String,
+    /// Client identifier sent to the broker.
+    pub client_id: String,
+    /// Username for brokers that require authentication.
+    pub username: Option<String>,
+    /// Password for brokers that require authentication.
+    pub password: Option<String>,
+    /// How often (ms) the current color and state are published.
+    pub publish_interval_ms: u64,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 1883,
+            topic: "keybloom".to_string(),
+            client_id: "keybloom".to_string(),
+            username: None,
+            password: None,
+            publish_interval_ms: 1000,
+        }
+    }
+}
+
+/// Layout version of the config files written by this build.
+///
+/// Bump it together with a new step in `Config::migrate` whenever fields are renamed,
+/// moved or given new meaning, so older files are upgraded and rewritten on load.
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Pixels sampled per frame with `adaptive_sampling`, e.g. a step of 6 at 1920x1080 and 13
+/// at 3840x2160.
+pub const ADAPTIVE_SAMPLE_TARGET: u32 = 50_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Layout version of the file (see `CONFIG_VERSION`); files without one are version 1.
+    pub version: u32,
+    /// Devices lit from the same capture; the first one is edited from the menu.
+    pub devices: Vec<DeviceConfig>,
     pub transition_steps: usize,
     pub transition_delay_ms: u64,
     pub frame_delay_ms: u64,
+    /// Capture this many frames per second, accounting for the time each frame takes;
+    /// overrides `frame_delay_ms` when set.
+    pub target_fps: Option<u32>,
     pub sample_step: usize,
+    /// Derive the sample step from the monitor size instead of using `sample_step`, so
+    /// every resolution samples about `ADAPTIVE_SAMPLE_TARGET` pixels per frame.
+    pub adaptive_sampling: bool,
     pub color_change_threshold: f32,
+    /// How color changes are measured against `color_change_threshold`.
+    pub threshold_metric: ThresholdMetric,
     pub brightness_factor: f32,
     pub saturation_factor: f32,
     pub debounce_duration_ms: u64,
     pub openrgb_host: String,
     pub openrgb_port: u16,
-    pub device_name: String,
+    /// Name KeyBloom registers with the OpenRGB server, shown in its client list.
+    pub client_name: String,
+    /// Saved OpenRGB profile to load on the server after connecting, before syncing starts.
+    pub openrgb_profile: Option<String>,
     pub monitor_index: usize,
+    /// Capture the whole monitor or a single window.
+    pub capture_target: CaptureTarget,
+    /// Capture backend expected on Linux (`auto`, `x11` or `wayland`).
+    pub capture_backend: CaptureBackend,
+    /// Image used in place of the screen while capturing fails, so colors can still be tuned.
+    pub capture_fallback_image: Option<PathBuf>,
+    /// Capture several monitors, each filling its own range of LEDs of the largest device.
+    /// Segmentation, mirroring and LED order apply within each range. When empty, the
+    /// `capture_target` fills all LEDs.
+    pub monitor_mappings: Vec<MonitorMapping>,
+    /// Output gamma applied to the red channel of each LED (1.0 = unchanged).
+    pub gamma_r: f32,
+    /// Output gamma applied to the green channel of each LED (1.0 = unchanged).
+    pub gamma_g: f32,
+    /// Output gamma applied to the blue channel of each LED (1.0 = unchanged).
+    pub gamma_b: f32,
+    /// Per-channel `[r, g, b]` gain applied to every LED color, to calibrate the hardware
+    /// (e.g. raise a weak red channel).
+    pub channel_gain: [f32; 3],
+    /// Per-channel `[r, g, b]` offset (-255 to 255) added after `channel_gain`.
+    pub channel_offset: [i16; 3],
+    /// Multiplier for the output brightness of every device, on top of each device's own
+    /// `brightness_scale`. Unlike `brightness_factor` it doesn't change the color math, only
+    /// what is sent.
+    pub device_brightness_scale: f32,
+    /// Highest value (0-255) any channel is sent at; brighter colors are scaled down
+    /// evenly, keeping their hue.
+    pub max_channel: u8,
+    /// Pull near-white LEDs towards the white of this color temperature (K), e.g. 3500
+    /// for a warmer white; unset leaves whites unchanged.
+    pub desaturate_whites: Option<u16>,
+    /// Consecutive failed device writes after which the connection is re-established.
+    pub max_write_failures: u32,
+    /// Minimum time (ms) between device writes during a transition; 0 writes every step.
+    pub update_interval_ms: u64,
+    /// Size of the thread pool used for color averaging; 0 uses all cores.
+    pub compute_threads: usize,
+    /// Re-sample a frame at a finer step if any segment gets fewer samples; 0 disables.
+    pub min_segment_samples: u64,
+    /// Pixels less opaque than this (0.0-1.0) are ignored; the rest are weighted by alpha.
+    pub alpha_threshold: f32,
+    /// Read the LED colors back after each write and log mismatches (adds latency).
+    pub verify_writes: bool,
+    /// Per-channel difference tolerated by `verify_writes` before flagging a mismatch.
+    pub verify_tolerance: u8,
+    /// Delay (ms) before the first connection attempt, e.g. when autostarted at login.
+    pub startup_delay_ms: u64,
+    /// How long (ms) to keep retrying the initial OpenRGB connection; 0 tries once.
+    pub connect_wait_ms: u64,
+    /// How long (ms) a single connection attempt or device lookup may take before giving up.
+    pub connect_timeout_ms: u64,
+    /// Color change (0.0-1.0, measured like `color_change_threshold`) above which the
+    /// debounce is skipped so scene cuts update immediately; 0.0 disables.
+    pub scene_cut_threshold: f32,
+    /// Continuously estimate the scene's white point and neutralize colors against it.
+    pub auto_white_point: bool,
+    /// Time constant (ms) used to smooth the estimated white point between frames.
+    pub white_point_time_constant_ms: u64,
+    /// Resize every captured frame to exactly this `[width, height]` before segmenting.
+    /// Takes precedence over `sample_step` (every pixel of the small frame is sampled) and
+    /// over the `max_compute_*` caps.
+    pub downscale_to: Option<(u32, u32)>,
+    /// Downscale captured frames wider than this before averaging; 0 disables the cap.
+    pub max_compute_width: u32,
+    /// Downscale captured frames taller than this before averaging; 0 disables the cap.
+    pub max_compute_height: u32,
+    /// Optional `.cube` 3D LUT applied to every LED color before it is sent to the device.
+    pub lut_path: Option<PathBuf>,
+    /// TOML or JSON file giving every LED its own screen rectangle; when set it replaces
+    /// `segmentation`, `mirror_segments`, `reverse_led_order` and `led_offset`.
+    pub layout_file: Option<PathBuf>,
+    /// Apply brightness and saturation in one HSV pass, leaving grays unsaturated.
+    pub preserve_hue: bool,
+    /// Color space `saturation_factor` is applied in (`hsv` or `oklch`). The OKLCh mode
+    /// already keeps hue and lightness, so `preserve_hue` only affects `hsv`.
+    pub saturation_mode: SaturationMode,
+    /// Transition to the average of the targets seen during the debounce window
+    /// instead of the latest frame.
+    pub average_debounce_targets: bool,
+    /// Upper sanity bound for a device's `num_leds`, guarding against typos.
+    pub max_leds: usize,
+    /// Append every frame sent to the devices to this file.
+    pub color_log: Option<PathBuf>,
+    /// Format of `color_log` (`csv` or `jsonl`).
+    pub color_log_format: ColorLogFormat,
+    /// Flush the color log after every record instead of buffering.
+    pub color_log_flush: bool,
+    /// Listen for JSON control commands on this localhost port when running headless.
+    pub control_port: Option<u16>,
+    /// Publish the current color to, and take on/off commands from, an MQTT broker when
+    /// running headless.
+    pub mqtt: Option<MqttConfig>,
+    /// Global hotkey (e.g. `ctrl+alt+k`) that turns output on or off when running
+    /// headless. Needs a build with the `global-hotkey` feature and an X11 session.
+    pub toggle_hotkey: Option<String>,
+    /// How the screen is divided into segments (`vertical`, `horizontal`, `grid`, `edge`
+    /// or `radial`).
+    pub segmentation: SegmentationMode,
+    /// Scale the number of transition steps with the size of the color change, using
+    /// `transition_steps` for the largest possible jump.
+    pub scale_transition_steps: bool,
+    /// Fewest steps a transition may take when `scale_transition_steps` is enabled.
+    pub min_transition_steps: usize,
+    /// Display gamma used to average captured pixels in linear light (1.0 averages the
+    /// encoded values directly).
+    pub gamma: f32,
+    /// Color space used for transitions (`hsv`, `oklab` or `linear_rgb`).
+    pub interpolation_space: InterpolationSpace,
+    /// Easing of transitions (`linear`, `ease_in_out`, `ease_out` or `cubic`).
+    pub easing: Easing,
+    /// Spread the start of each LED's transition over this many ms across the array to
+    /// avoid simultaneous current spikes; 0 ramps all LEDs together.
+    pub power_stagger_ms: u64,
+    /// How segment colors are derived (`average` or `dominant_k_means` with `k`).
+    pub extraction: ColorExtraction,
+    /// Weight (0.0-1.0) of each new frame in a per-LED moving average of the targets;
+    /// 1.0 disables smoothing, lower values damp flashes.
+    pub smoothing_alpha: f32,
+    /// How much (0.0-1.0) each LED is blended with its two neighbours to soften hard edges
+    /// between segments; 0.0 disables it.
+    pub spatial_smoothing: f32,
+    /// Whether the LEDs form a strip (`clamp`) or a ring (`wrap`). On a ring the last and
+    /// first LED are neighbours for `spatial_smoothing`, and `power_stagger_ms` ramps
+    /// outwards from LED 0 both ways, so nothing jumps at the seam.
+    pub led_edges: LedEdges,
+    /// How the frame is mapped when its aspect differs from `layout_aspect`.
+    pub aspect_mode: AspectMode,
+    /// Width-to-height ratio of the LED layout (e.g. 1.333 for 4:3); 0.0 uses the frame's.
+    pub layout_aspect: f32,
+    /// Color of LEDs that map outside the letterboxed frame in `fit` mode.
+    #[serde(with = "hex_color")]
+    pub aspect_fill_color: [u8; 3],
+    /// Minimum time (ms) between a sync loop exiting and the next one starting.
+    pub restart_cooldown_ms: u64,
+    /// Detect black letterbox bars and sample only the content between them.
+    pub crop_letterbox: bool,
+    /// Highest channel value (0-255) still counted as black when detecting bars.
+    pub letterbox_black_level: u8,
+    /// How often (ms) the bars are re-detected.
+    pub letterbox_detect_interval_ms: u64,
+    /// How much of the saturation boost is withheld from near-neutral and protected hues
+    /// (0.0 disables, 1.0 leaves them unboosted).
+    pub saturation_protection: f32,
+    /// Saturation (0.0-1.0) below which colors count as near-neutral for the protection.
+    pub saturation_protection_threshold: f32,
+    /// Hue ranges in degrees, e.g. `[[10.0, 50.0]]` for skin tones, that are protected too.
+    pub protected_hue_ranges: Vec<[f32; 2]>,
+    /// Numeric type used to sum sampled pixels (`u64` or `f32`).
+    pub accumulator: Accumulator,
+    /// Stop processing frames after the screen has been unchanged this long (s), checking
+    /// only occasionally for a change until it resumes; unset never pauses.
+    pub idle_pause_secs: Option<u64>,
+    /// Start a gentle breathing animation after the scene has been static this long (ms);
+    /// 0 disables it.
+    pub idle_animation_after_ms: u64,
+    /// Relative brightness swing (0.0-1.0) of the idle animation.
+    pub idle_animation_amplitude: f32,
+    /// Duration (ms) of one breath of the idle animation.
+    pub idle_animation_period_ms: u64,
+    /// Only use controllers named exactly like `device_name`, never a partial match or
+    /// the keyboard fallback.
+    pub strict_device_match: bool,
+    /// Fall back to any controller with "keyboard" in its name if none matches
+    /// `device_name`.
+    pub keyboard_fallback: bool,
+    /// Mirror the screen left-to-right before mapping it to the LEDs.
+    pub flip_horizontal: bool,
+    /// Mirror the screen top-to-bottom before mapping it to the LEDs.
+    pub flip_vertical: bool,
+    /// Reverse the order of the LEDs, for devices indexed right-to-left.
+    pub reverse_led_order: bool,
+    /// Shift the colors this many LEDs along the device, wrapping around at the end.
+    pub led_offset: usize,
+    /// Sample only half as many segments and mirror them onto the second half of the LEDs,
+    /// e.g. for a strip wrapping around the keyboard. The LED order becomes segments
+    /// `0, 1, .., n-1, n-1, .., 1, 0`; with an odd LED count the middle one isn't repeated.
+    /// A grid is mirrored as a whole and then needs twice `rows * cols` LEDs.
+    pub mirror_segments: bool,
+    /// Only update these LED indices; the others keep the colors they had when syncing
+    /// started (e.g. a static WASD highlight). Unset updates every LED.
+    pub controlled_leds: Option<Vec<usize>>,
+    /// Only sample this part of the monitor; unset uses the whole screen.
+    pub capture_region: Option<Region>,
+    /// Sync with the screen, show a static color or the wallpaper, run an effect, or turn
+    /// the LEDs off.
+    pub mode: SyncMode,
+    /// Image used by the wallpaper mode instead of the detected desktop wallpaper.
+    pub wallpaper_image: Option<PathBuf>,
+    /// Color temperature (K) the output is balanced to; 6500 leaves colors unchanged,
+    /// lower values are warmer.
+    pub white_balance_kelvin: u16,
+    /// Lowest brightness (0.0-1.0) any LED is dimmed to, so dark scenes never turn the
+    /// keyboard fully off.
+    pub min_brightness: f32,
+    /// Curve applied to each color's brightness before `brightness_factor`: values above
+    /// 1.0 lift dark scenes while leaving full brightness in place, 1.0 is linear.
+    pub brightness_gamma: f32,
+    /// Curve applied to each color's saturation before `saturation_factor`, like
+    /// `brightness_gamma`.
+    pub saturation_gamma: f32,
+    /// Print the computed colors instead of connecting to OpenRGB.
+    pub simulate: bool,
+    /// Image used as the captured frame in simulation, e.g. on machines without a display.
+    pub simulate_image: Option<PathBuf>,
+    /// Color scheme of the TUI (`default`, `dark`, `high_contrast` or `mono`).
+    pub ui_theme: UiTheme,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            num_leds: 5,
+            version: CONFIG_VERSION,
+            devices: vec![DeviceConfig::default()],
             transition_steps: 10,
             transition_delay_ms: 15,
             frame_delay_ms: 100,
+            target_fps: None,
             sample_step: 10,
+            adaptive_sampling: false,
             color_change_threshold: 0.05,
+            threshold_metric: ThresholdMetric::RgbEuclidean,
             brightness_factor: 5.0,
             saturation_factor: 4.0,
             debounce_duration_ms: 500,
             openrgb_host: "localhost".to_string(),
             openrgb_port: 6742,
-            device_name: "G213".to_string(),
+            client_name: "KeyBloom".to_string(),
+            openrgb_profile: None,
             monitor_index: 1,
+            capture_target: CaptureTarget::Monitor,
+            capture_backend: CaptureBackend::Auto,
+            capture_fallback_image: None,
+            monitor_mappings: Vec::new(),
+            gamma_r: 1.0,
+            gamma_g: 1.0,
+            gamma_b: 1.0,
+            channel_gain: [1.0; 3],
+            channel_offset: [0; 3],
+            device_brightness_scale: 1.0,
+            max_channel: 255,
+            desaturate_whites: None,
+            max_write_failures: 3,
+            update_interval_ms: 0,
+            compute_threads: 0,
+            min_segment_samples: 0,
+            alpha_threshold: 0.1,
+            verify_writes: false,
+            verify_tolerance: 2,
+            startup_delay_ms: 0,
+            connect_wait_ms: 0,
+            connect_timeout_ms: 5000,
+            scene_cut_threshold: 0.0,
+            auto_white_point: false,
+            white_point_time_constant_ms: 2000,
+            downscale_to: None,
+            max_compute_width: 0,
+            max_compute_height: 0,
+            lut_path: None,
+            layout_file: None,
+            preserve_hue: false,
+            saturation_mode: SaturationMode::Hsv,
+            average_debounce_targets: false,
+            max_leds: 4096,
+            color_log: None,
+            color_log_format: ColorLogFormat::Csv,
+            color_log_flush: false,
+            control_port: None,
+            mqtt: None,
+            toggle_hotkey: None,
+            segmentation: SegmentationMode::Vertical,
+            scale_transition_steps: false,
+            min_transition_steps: 1,
+            gamma: 2.2,
+            interpolation_space: InterpolationSpace::Hsv,
+            easing: Easing::Linear,
+            power_stagger_ms: 0,
+            extraction: ColorExtraction::Average,
+            smoothing_alpha: 1.0,
+            spatial_smoothing: 0.0,
+            led_edges: LedEdges::Clamp,
+            aspect_mode: AspectMode::Stretch,
+            layout_aspect: 0.0,
+            aspect_fill_color: [0, 0, 0],
+            restart_cooldown_ms: 250,
+            crop_letterbox: false,
+            letterbox_black_level: 16,
+            letterbox_detect_interval_ms: 1000,
+            saturation_protection: 0.0,
+            saturation_protection_threshold: 0.25,
+            protected_hue_ranges: Vec::new(),
+            accumulator: Accumulator::U64,
+            idle_pause_secs: None,
+            idle_animation_after_ms: 0,
+            idle_animation_amplitude: 0.1,
+            idle_animation_period_ms: 4000,
+            strict_device_match: false,
+            keyboard_fallback: false,
+            flip_horizontal: false,
+            flip_vertical: false,
+            reverse_led_order: false,
+            led_offset: 0,
+            mirror_segments: false,
+            controlled_leds: None,
+            capture_region: None,
+            mode: SyncMode::ScreenSync,
+            wallpaper_image: None,
+            white_balance_kelvin: 6500,
+            min_brightness: 0.0,
+            brightness_gamma: 1.0,
+            saturation_gamma: 1.0,
+            simulate: false,
+            simulate_image: None,
+            ui_theme: UiTheme::Default,
         }
     }
 }
 
 impl Config {
+    /// The device edited from the menu (the first configured device).
+    ///
+    /// Loaded configs always contain at least one device.
+    pub fn primary_device(&self) -> &DeviceConfig {
+        &self.devices[0]
+    }
+
+    /// Mutable access to the primary device, adding a default one if the list is empty.
+    pub fn primary_device_mut(&mut self) -> &mut DeviceConfig {
+        if self.devices.is_empty() {
+            self.devices.push(DeviceConfig::default());
+        }
+        &mut self.devices[0]
+    }
+
+    /// Parse a TOML config, upgrading older layouts first.
+    ///
+    /// Also returns whether the file was upgraded and should be rewritten.
+    fn parse(content: &str) -> Result<(Self, bool), toml::de::Error> {
+        let mut table: toml::Table = toml::from_str(content)?;
+        let migrated = Self::migrate(&mut table);
+        let mut config: Self = toml::Value::Table(table).try_into()?;
+        if config.devices.is_empty() {
+            config.devices.push(DeviceConfig::default());
+        }
+        Ok((config, migrated))
+    }
+
+    /// Upgrade a config table from its `version` to `CONFIG_VERSION`, one step at a time.
+    ///
+    /// Fields a step doesn't touch are left to their serde defaults. Returns whether
+    /// anything was upgraded; files from a newer build are loaded as they are.
+    fn migrate(table: &mut toml::Table) -> bool {
+        let version = table
+            .get("version")
+            .and_then(toml::Value::as_integer)
+            .unwrap_or(1);
+        if version > CONFIG_VERSION as i64 {
+            warn!(
+                "Config version {version} is newer than this build supports; some settings \
+                 may be ignored."
+            );
+            return false;
+        }
+        if version == CONFIG_VERSION as i64 {
+            return false;
+        }
+
+        // 1 -> 2: `device_name` and `num_leds` moved from the top level into `devices`
+        if version < 2 && !table.contains_key("devices") {
+            let mut device = toml::Table::new();
+            for key in ["device_name", "num_leds"] {
+                if let Some(value) = table.remove(key) {
+                    device.insert(key.to_string(), value);
+                }
+            }
+            if !device.is_empty() {
+                table.insert(
+                    "devices".to_string(),
+                    toml::Value::Array(vec![toml::Value::Table(device)]),
+                );
+            }
+        }
+
+        table.insert("version".to_string(), toml::Value::Integer(CONFIG_VERSION as i64));
+        true
+    }
+
+    /// Time budget of one frame: `1000 / target_fps` ms if set, else `frame_delay_ms`.
+    ///
+    /// The loops sleep for whatever is left of it after capturing and processing.
+    pub fn frame_interval(&self) -> Duration {
+        match self.target_fps {
+            Some(fps) if fps > 0 => Duration::from_secs(1) / fps,
+            _ => Duration::from_millis(self.frame_delay_ms),
+        }
+    }
+
+    /// Pixel step used when sampling a prepared frame.
+    ///
+    /// A downscaled frame is already small, so every pixel of it is sampled.
+    pub fn sampling_step(&self) -> usize {
+        if self.downscale_to.is_some() {
+            1
+        } else {
+            self.sample_step.max(1)
+        }
+    }
+
+    /// Sample step that samples about `ADAPTIVE_SAMPLE_TARGET` pixels of a `width` x
+    /// `height` frame.
+    pub fn adaptive_sample_step(width: u32, height: u32) -> usize {
+        let pixels = width as f64 * height as f64;
+        ((pixels / ADAPTIVE_SAMPLE_TARGET as f64).sqrt().round() as usize).max(1)
+    }
+
+    /// Number of screen segments sampled when the largest device has `led_count` LEDs.
+    pub fn sampled_segments(&self, led_count: usize) -> usize {
+        if self.mirror_segments {
+            self.segmentation.segment_count(led_count.div_ceil(2))
+        } else {
+            self.segmentation.segment_count(led_count)
+        }
+    }
+
+    /// Number of LED colors produced per frame when the largest device has `led_count` LEDs.
+    pub fn output_colors(&self, led_count: usize) -> usize {
+        match (self.mirror_segments, self.segmentation) {
+            (false, _) => self.sampled_segments(led_count),
+            (true, SegmentationMode::Grid { rows, cols }) => 2 * rows * cols,
+            (true, _) => led_count,
+        }
+    }
+
     /// Return the path to the config file
     fn config_path() -> PathBuf {
         if let Some(proj_dirs) = ProjectDirs::from("com", "AlexanderBayerl", "KeyBloom") {
@@ -52,13 +845,292 @@ impl Config {
         }
     }
 
+    /// Return the directory holding the config file, profiles and reports
+    pub fn config_dir() -> PathBuf {
+        match Self::config_path().parent() {
+            Some(dir) => dir.to_path_buf(),
+            None => PathBuf::new(),
+        }
+    }
+
+    /// Return the directory for files KeyBloom writes while running, such as its log
+    pub fn data_dir() -> PathBuf {
+        match ProjectDirs::from("com", "AlexanderBayerl", "KeyBloom") {
+            Some(proj_dirs) => proj_dirs.data_dir().to_path_buf(),
+            None => PathBuf::new(),
+        }
+    }
+
+    /// Return the path to the file holding the named profile
+    fn profile_path(name: &str) -> PathBuf {
+        Self::config_dir().join("profiles").join(format!("{name}.toml"))
+    }
+
+    /// Return the file the named profile, or the default config, is stored in
+    pub fn source_path(profile: Option<&str>) -> PathBuf {
+        match profile {
+            Some(name) => Self::profile_path(name),
+            None => Self::config_path(),
+        }
+    }
+
+    /// Load a named profile, failing if it doesn't exist or can't be parsed
+    pub fn load_profile(name: &str) -> io::Result<Self> {
+        Self::load_from(&Self::profile_path(name))
+    }
+
+    /// Load the config file at `path`, failing if it can't be read or parsed
+    ///
+    /// A file from an older version is upgraded and rewritten in the current layout.
+    pub fn load_from(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let (config, migrated) = Self::parse(&content)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if migrated {
+            match config.write_to(path) {
+                Ok(()) => info!("Upgraded {} to config version {CONFIG_VERSION}.", path.display()),
+                Err(err) => warn!("Failed to rewrite the upgraded {}: {err}", path.display()),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Return the names of all saved profiles, sorted alphabetically
+    pub fn list_profiles() -> Vec<String> {
+        let Ok(entries) = fs::read_dir(Self::config_dir().join("profiles")) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Save configuration as the named profile
+    pub fn save_as(&self, name: &str) -> io::Result<()> {
+        self.write_to(&Self::profile_path(name))
+    }
+
+    /// Check every field against sane bounds.
+    ///
+    /// Returns one human-readable message per invalid field.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if self.devices.is_empty() {
+            errors.push("At least one device must be configured.".to_string());
+        }
+        if let CaptureTarget::Window { title } = &self.capture_target {
+            if title.trim().is_empty() {
+                errors.push("Capture window title must not be empty.".to_string());
+            }
+        }
+        let max_leds = self.devices.iter().map(|device| device.num_leds).max().unwrap_or(0);
+        for mapping in &self.monitor_mappings {
+            if mapping.led_count == 0 || mapping.first_led + mapping.led_count > max_leds {
+                errors.push(format!(
+                    "Monitor mapping for monitor {} must fill at least one LED and end within \
+                     the {max_leds} LEDs of the largest device.",
+                    mapping.monitor_index
+                ));
+            }
+        }
+        if self.devices.iter().any(|device| device.num_leds == 0) {
+            errors.push("Number of LEDs must be at least 1.".to_string());
+        }
+        if self
+            .devices
+            .iter()
+            .map(|device| device.brightness_scale)
+            .chain([self.device_brightness_scale])
+            .any(|scale| !(scale >= 0.0 && scale.is_finite()))
+        {
+            errors.push("Device brightness scales must be non-negative numbers.".to_string());
+        }
+        if self.devices.iter().any(|device| device.num_leds > self.max_leds) {
+            errors.push(format!(
+                "Number of LEDs must not exceed {} (raise max_leds if intended).",
+                self.max_leds
+            ));
+        }
+        if let SegmentationMode::Grid { rows, cols } = self.segmentation {
+            if rows == 0 || cols == 0 {
+                errors.push("Grid segmentation needs at least 1 row and 1 column.".to_string());
+            } else {
+                let needed = if self.mirror_segments { 2 } else { 1 } * rows * cols;
+                if self.devices.iter().any(|device| device.num_leds != needed) {
+                    errors.push(format!(
+                        "Grid segmentation of {rows}x{cols} needs {needed} LEDs per device."
+                    ));
+                }
+            }
+        }
+        if self.controlled_leds.as_ref().is_some_and(|leds| leds.is_empty()) {
+            errors.push("Controlled LEDs must list at least one LED index.".to_string());
+        }
+        if let SyncMode::Rainbow { speed } | SyncMode::Breathing { speed, .. } = self.mode {
+            if !(speed >= 0.0 && speed.is_finite()) {
+                errors.push("Effect speed must be a non-negative number.".to_string());
+            }
+        }
+        if matches!(self.extraction, ColorExtraction::DominantKMeans { k: 0 }) {
+            errors.push("Dominant color extraction needs at least 1 cluster.".to_string());
+        }
+        if self.transition_steps == 0 {
+            errors.push("Transition steps must be at least 1.".to_string());
+        }
+        if self.min_transition_steps == 0 || self.min_transition_steps > self.transition_steps {
+            errors.push(
+                "Minimum transition steps must be between 1 and the transition steps.".to_string(),
+            );
+        }
+        if self.max_write_failures == 0 {
+            errors.push("Max write failures must be at least 1.".to_string());
+        }
+        if self.sample_step == 0 {
+            errors.push("Sample step must be at least 1.".to_string());
+        }
+        if self.min_segment_samples > 100_000 {
+            errors.push("Minimum segment samples must be at most 100000.".to_string());
+        }
+        if matches!(self.downscale_to, Some((width, height)) if width == 0 || height == 0) {
+            errors.push("Downscale size must be at least 1x1.".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.color_change_threshold) {
+            errors.push("Color change threshold must be between 0.0 and 1.0.".to_string());
+        }
+        if self.target_fps == Some(0) {
+            errors.push("Target FPS must be at least 1.".to_string());
+        }
+        if self.idle_pause_secs == Some(0) {
+            errors.push("Idle pause must be at least 1 second.".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.idle_animation_amplitude) {
+            errors.push("Idle animation amplitude must be between 0.0 and 1.0.".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.min_brightness) {
+            errors.push("Minimum brightness must be between 0.0 and 1.0.".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.smoothing_alpha) {
+            errors.push("Smoothing alpha must be between 0.0 and 1.0.".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.spatial_smoothing) {
+            errors.push("Spatial smoothing must be between 0.0 and 1.0.".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.alpha_threshold) {
+            errors.push("Alpha threshold must be between 0.0 and 1.0.".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.scene_cut_threshold) {
+            errors.push("Scene cut threshold must be between 0.0 and 1.0.".to_string());
+        }
+        if !(self.brightness_factor >= 0.0 && self.brightness_factor.is_finite()) {
+            errors.push("Brightness factor must be a non-negative number.".to_string());
+        }
+        if !(self.saturation_factor >= 0.0 && self.saturation_factor.is_finite()) {
+            errors.push("Saturation factor must be a non-negative number.".to_string());
+        }
+        if !(self.brightness_gamma > 0.0 && self.brightness_gamma.is_finite()) {
+            errors.push("Brightness gamma must be greater than 0.".to_string());
+        }
+        if !(self.saturation_gamma > 0.0 && self.saturation_gamma.is_finite()) {
+            errors.push("Saturation gamma must be greater than 0.".to_string());
+        }
+        if [self.gamma_r, self.gamma_g, self.gamma_b]
+            .iter()
+            .any(|gamma| !(*gamma > 0.0 && gamma.is_finite()))
+        {
+            errors.push("Channel gamma values must be greater than 0.".to_string());
+        }
+        if !(self.gamma > 0.0 && self.gamma.is_finite()) {
+            errors.push("Gamma must be greater than 0.".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.saturation_protection)
+            || !(0.0..=1.0).contains(&self.saturation_protection_threshold)
+        {
+            errors.push("Saturation protection values must be between 0.0 and 1.0.".to_string());
+        }
+        if !(1000..=40000).contains(&self.white_balance_kelvin) {
+            errors.push("White balance must be between 1000 and 40000 K.".to_string());
+        }
+        if let Some(kelvin) = self.desaturate_whites {
+            if !(1000..=40000).contains(&kelvin) {
+                errors.push("Desaturate whites must be between 1000 and 40000 K.".to_string());
+            }
+        }
+        if self
+            .channel_gain
+            .iter()
+            .any(|gain| !(*gain >= 0.0 && gain.is_finite()))
+        {
+            errors.push("Channel gains must be non-negative numbers.".to_string());
+        }
+        if self.channel_offset.iter().any(|offset| !(-255..=255).contains(offset)) {
+            errors.push("Channel offsets must be between -255 and 255.".to_string());
+        }
+        if self.max_channel == 0 {
+            errors.push("Max channel must be greater than 0.".to_string());
+        }
+        if !(self.layout_aspect >= 0.0 && self.layout_aspect.is_finite()) {
+            errors.push("Layout aspect must be a non-negative number.".to_string());
+        }
+        if self.openrgb_host.trim().is_empty() {
+            errors.push("OpenRGB host must not be empty.".to_string());
+        }
+        if self.client_name.trim().is_empty() {
+            errors.push("Client name must not be empty.".to_string());
+        }
+        if self.openrgb_profile.as_ref().is_some_and(|name| name.trim().is_empty()) {
+            errors.push("OpenRGB profile name must not be empty.".to_string());
+        }
+        if let Some(mqtt) = &self.mqtt {
+            if mqtt.host.trim().is_empty() || mqtt.port == 0 {
+                errors.push("MQTT broker host and port must be set.".to_string());
+            }
+            if mqtt.topic.trim().is_empty() || mqtt.topic.contains(['+', '#']) {
+                errors.push("MQTT topic must be set and must not contain + or #.".to_string());
+            }
+            if mqtt.publish_interval_ms == 0 {
+                errors.push("MQTT publish interval must be greater than 0.".to_string());
+            }
+        }
+        if self.layout_file.is_some() && !self.monitor_mappings.is_empty() {
+            errors.push("A layout file can't be combined with monitor mappings.".to_string());
+        }
+        if self.connect_timeout_ms == 0 {
+            errors.push("Connect timeout must be greater than 0.".to_string());
+        }
+        if self.openrgb_port == 0 {
+            errors.push("OpenRGB port must be between 1 and 65535.".to_string());
+        }
+        if self.devices.iter().any(|device| device.device_name.trim().is_empty()) {
+            errors.push("Device name must not be empty.".to_string());
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     /// Load configuration or create a default one
     pub fn load() -> Self {
         let path = Self::config_path();
         if path.exists() {
-            match fs::read_to_string(&path) {
-                Ok(content) => toml::from_str(&content).unwrap_or_default(),
-                Err(_) => Self::default(),
+            match Self::load_from(&path) {
+                Ok(config) => config,
+                Err(err) => {
+                    // Keep the unreadable file around, since saving would overwrite it
+                    error!("Failed to load {}: {err}; using the defaults.", path.display());
+                    let backup = path.with_extension("toml.bak");
+                    if fs::copy(&path, &backup).is_ok() {
+                        warn!("A copy of the unreadable config was kept at {}.", backup.display());
+                    }
+                    Self::default()
+                }
             }
         } else {
             let config = Self::default();
@@ -70,17 +1142,107 @@ impl Config {
 
     /// Save configuration to disk
     pub fn save(&self) -> io::Result<()> {
-        let path = Self::config_path();
+        self.write_to(&Self::config_path())
+    }
+
+    /// Serialize the configuration to `path`, creating its directory if needed
+    fn write_to(&self, path: &Path) -> io::Result<()> {
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
         let content = toml::to_string_pretty(self).map_err(|err| {
-            eprintln!("Failed to serialize configuration: {}", err);
-            io::Error::new(io::ErrorKind::Other, "Serialization failed")
+            error!("Failed to serialize configuration: {}", err);
+            io::Error::other("Serialization failed")
         })?;
-        fs::write(&path, content).map_err(|err| {
-            eprintln!("Failed to save configuration to {}: {}", path.display(), err);
+        fs::write(path, content).map_err(|err| {
+            error!("Failed to save configuration to {}: {}", path.display(), err);
             err
         })
     }
 }
+
+/// Application state kept across runs, separate from the configuration itself.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Settings {
+    /// Profile restored at startup; `None` uses the default config file.
+    pub last_profile: Option<String>,
+}
+
+impl Settings {
+    /// Return the path to the settings file
+    fn path() -> PathBuf {
+        Config::config_dir().join("settings.toml")
+    }
+
+    /// Load the settings, falling back to the defaults if missing or unreadable
+    pub fn load() -> Self {
+        fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|content| toml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save the settings to disk
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = toml::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The default config with the primary device set to `num_leds` LEDs.
+    fn with_leds(num_leds: usize) -> Config {
+        let mut config = Config::default();
+        config.primary_device_mut().num_leds = num_leds;
+        config
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert_eq!(Config::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_absurd_led_counts() {
+        let errors = with_leds(1_000_000).validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("must not exceed 4096")), "{errors:?}");
+        assert!(with_leds(0).validate().is_err());
+        assert_eq!(with_leds(4096).validate(), Ok(()));
+
+        // The bound itself is configurable
+        let raised = Config {
+            max_leds: 10_000,
+            ..with_leds(5000)
+        };
+        assert_eq!(raised.validate(), Ok(()));
+    }
+
+    #[test]
+    fn grid_must_match_the_led_count() {
+        let grid = |num_leds| Config {
+            segmentation: SegmentationMode::Grid { rows: 3, cols: 4 },
+            ..with_leds(num_leds)
+        };
+        let errors = grid(10).validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("3x4 needs 12 LEDs")), "{errors:?}");
+        assert_eq!(grid(12).validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_huge_min_segment_samples() {
+        let huge = Config {
+            min_segment_samples: u64::MAX,
+            ..Config::default()
+        };
+        let errors = huge.validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("segment samples")), "{errors:?}");
+    }
+}
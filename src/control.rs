@@ -0,0 +1,242 @@
+//! Control API for driving a headless KeyBloom from other tools.
+//!
+//! When `control_port` is set, a TCP server on localhost accepts one JSON command per line
+//! and answers each with one JSON line:
+//!
+//! ```text
+//! {"cmd":"status"}
+//! {"cmd":"stop"}
+//! {"cmd":"start"}
+//! {"cmd":"toggle"}
+//! {"cmd":"set","field":"brightness_factor","value":3.0}
+//! ```
+//!
+//! Changed settings are applied by restarting the sync loop with the updated config, so
+//! every field (including devices and segmentation) can be changed at runtime.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
+
+use crate::color_utils::color_to_hex;
+use crate::config::Config;
+use crate::error::KeyBloomError;
+use crate::sync_loop::{lock_or_recover, start_sync_loop, SyncStatus};
+
+/// A command received on the control port.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    /// Report the current `SyncStatus`.
+    Status,
+    /// Start syncing if it was stopped.
+    Start,
+    /// Stop syncing but keep the process (and the control server) running.
+    Stop,
+    /// Stop syncing if it is running, otherwise start it.
+    Toggle,
+    /// Set one config field; the loop restarts with the new value.
+    Set { field: String, value: Value },
+}
+
+/// State shared between the supervised sync loop and whatever reconfigures it (the control
+/// server and the config file watcher).
+pub struct ControlState {
+    /// The config the next (re)start of the sync loop uses.
+    pub config: Mutex<Config>,
+    pub sync_status: Arc<Mutex<SyncStatus>>,
+    /// Stop signal of the currently running loop.
+    pub stop_signal: Arc<AtomicBool>,
+    /// Whether output is enabled. Turned off via the API, MQTT or the toggle hotkey, the
+    /// loop is stopped and the devices are left as they are.
+    enabled: AtomicBool,
+    /// Set once the process should exit.
+    shutdown: AtomicBool,
+    /// Wakes the supervisor after a command changed what should run.
+    changed: Notify,
+}
+
+impl ControlState {
+    pub fn new(config: Config, sync_status: Arc<Mutex<SyncStatus>>) -> Self {
+        Self {
+            config: Mutex::new(config),
+            sync_status,
+            stop_signal: Arc::new(AtomicBool::new(false)),
+            enabled: AtomicBool::new(true),
+            shutdown: AtomicBool::new(false),
+            changed: Notify::new(),
+        }
+    }
+
+    /// Stop the loop and make the supervisor return.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        self.stop_signal.store(true, Ordering::Relaxed);
+        self.changed.notify_one();
+    }
+
+    /// Whether output is enabled, as opposed to stopped via a command.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Turn output on or off, waking the supervisor to start or park the loop.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        self.wake(enabled);
+    }
+
+    /// Flip whether output is enabled, returning the new state.
+    pub fn toggle(&self) -> bool {
+        let enabled = !self.enabled.fetch_xor(true, Ordering::Relaxed);
+        self.wake(enabled);
+        enabled
+    }
+
+    /// Wake the supervisor to start the loop, or stop it, to match `enabled`.
+    fn wake(&self, enabled: bool) {
+        if enabled {
+            self.changed.notify_one();
+        } else {
+            self.interrupt();
+        }
+    }
+
+    /// Stop the running loop so the supervisor restarts (or parks) it.
+    fn interrupt(&self) {
+        self.stop_signal.store(true, Ordering::Relaxed);
+        self.changed.notify_one();
+    }
+
+    /// Restart the loop with `updated`, unless it matches the config already in use.
+    ///
+    /// Returns whether the config changed.
+    pub fn replace_config(&self, updated: Config) -> bool {
+        let mut config = lock_or_recover(&self.config);
+        if toml::to_string(&*config).ok() == toml::to_string(&updated).ok() {
+            return false;
+        }
+        *config = updated;
+        drop(config);
+        self.interrupt();
+        true
+    }
+
+    /// Execute `command` and build the JSON reply.
+    pub fn handle(&self, command: Command) -> Value {
+        match command {
+            Command::Status => json!({ "ok": true, "status": self.status_json() }),
+            Command::Start => {
+                self.set_enabled(true);
+                json!({ "ok": true })
+            }
+            Command::Stop => {
+                self.set_enabled(false);
+                json!({ "ok": true })
+            }
+            Command::Toggle => json!({ "ok": true, "running": self.toggle() }),
+            Command::Set { field, value } => {
+                let current = lock_or_recover(&self.config).clone();
+                match with_field(&current, &field, value) {
+                    Ok(updated) => {
+                        self.replace_config(updated);
+                        json!({ "ok": true })
+                    }
+                    Err(err) => json!({ "ok": false, "error": err }),
+                }
+            }
+        }
+    }
+
+    fn status_json(&self) -> Value {
+        let status = lock_or_recover(&self.sync_status);
+        json!({
+            "state": format!("{:?}", status.state),
+            "running": self.is_enabled(),
+            "frame_count": status.frame_count,
+            "fps": status.fps,
+            "target_fps": status.target_fps,
+            "last_error": status.last_error,
+            "avg_capture_ms": status.avg_capture_ms,
+            "avg_process_ms": status.avg_process_ms,
+            "colors": status.current_colors.iter().map(|&c| color_to_hex(c)).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Return a copy of `config` with `field` set to `value`, if the result is valid.
+fn with_field(config: &Config, field: &str, value: Value) -> Result<Config, String> {
+    let mut fields = serde_json::to_value(config).map_err(|err| err.to_string())?;
+    let Some(slot) = fields.get_mut(field) else {
+        return Err(format!("Unknown config field '{field}'"));
+    };
+    *slot = value;
+    let updated: Config = serde_json::from_value(fields).map_err(|err| err.to_string())?;
+    updated.validate().map_err(|errors| errors.join("; "))?;
+    Ok(updated)
+}
+
+/// Run the sync loop until shutdown, restarting it whenever a command asks to.
+pub async fn supervise(state: Arc<ControlState>) {
+    while !state.shutdown.load(Ordering::Relaxed) {
+        if !state.is_enabled() {
+            state.changed.notified().await;
+            continue;
+        }
+        let config = lock_or_recover(&state.config).clone();
+        state.stop_signal.store(false, Ordering::Relaxed);
+        let sync_status = Arc::clone(&state.sync_status);
+        let stop_signal = Arc::clone(&state.stop_signal);
+        match start_sync_loop(&config, sync_status, stop_signal).await {
+            Err(err) => {
+                // Wait for a new command instead of retrying a failing config in a tight loop
+                error!("Sync loop failed: {err}");
+                state.changed.notified().await;
+            }
+            // Modes like `off` finish on their own; rerunning them would only repeat the
+            // same writes, so wait until something changes
+            Ok(()) if !state.stop_signal.load(Ordering::Relaxed) => {
+                state.changed.notified().await;
+            }
+            Ok(()) => {}
+        }
+    }
+}
+
+/// Accept control connections on `127.0.0.1:port` until the process exits.
+pub async fn serve(port: u16, state: Arc<ControlState>) -> Result<(), KeyBloomError> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    info!("Control API listening on 127.0.0.1:{port}");
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, &state).await {
+                warn!("Control connection from {peer} failed: {err}");
+            }
+        });
+    }
+}
+
+/// Answer each command line on `stream` until the client disconnects.
+async fn handle_connection(stream: TcpStream, state: &ControlState) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Command>(&line) {
+            Ok(command) => state.handle(command),
+            Err(err) => json!({ "ok": false, "error": format!("Invalid command: {err}") }),
+        };
+        writer.write_all(format!("{reply}\n").as_bytes()).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,256 @@
+//! Diagnostics report for bug reports.
+//!
+//! Gathers the effective configuration, the detected monitors and OpenRGB devices and the
+//! tail of the application log into one text file that can be attached to an issue.
+
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use openrgb::OpenRGBError;
+use xcap::Monitor;
+
+use crate::config::Config;
+use crate::error::KeyBloomError;
+use crate::logging::log_file_path;
+use crate::sync_loop::{connect_client, with_connect_timeout};
+
+/// Number of application log lines included in the report.
+const LOG_TAIL_LINES: usize = 20;
+
+/// Written in place of credentials in the report.
+const REDACTED: &str = "<redacted>";
+
+/// Everything collected for a diagnostics report.
+///
+/// Each probe that can fail keeps its error message instead, so one missing piece (e.g. no
+/// OpenRGB server running) doesn't prevent the rest of the report.
+pub struct Diagnostics {
+    /// The effective configuration, serialized as TOML.
+    pub config: Result<String, String>,
+    /// One line per detected monitor.
+    pub monitors: Result<Vec<String>, String>,
+    /// One line per OpenRGB controller.
+    pub devices: Result<Vec<String>, String>,
+    /// The last lines of the application log.
+    pub log_tail: Result<Vec<String>, String>,
+}
+
+/// Where the pieces of a report come from.
+pub trait Probe {
+    /// One line per detected monitor.
+    fn monitors(&self) -> Result<Vec<String>, String>;
+    /// One line per controller of the OpenRGB server configured in `config`.
+    fn devices(&self, config: &Config) -> impl Future<Output = Result<Vec<String>, String>>;
+    /// The application log file.
+    fn log_file(&self) -> PathBuf;
+}
+
+/// Probes the monitors, the OpenRGB server and the log file of this machine.
+pub struct SystemProbe;
+
+impl Probe for SystemProbe {
+    fn monitors(&self) -> Result<Vec<String>, String> {
+        describe_monitors()
+    }
+
+    fn devices(&self, config: &Config) -> impl Future<Output = Result<Vec<String>, String>> {
+        describe_devices(config)
+    }
+
+    fn log_file(&self) -> PathBuf {
+        log_file_path()
+    }
+}
+
+impl Diagnostics {
+    /// Probe the system described by `config` through `probe`.
+    ///
+    /// OpenRGB is only queried; no device mode or color is changed.
+    pub async fn collect(config: &Config, probe: &impl Probe) -> Self {
+        Self {
+            config: toml::to_string_pretty(&redacted(config)).map_err(|err| err.to_string()),
+            monitors: probe.monitors(),
+            devices: probe.devices(config).await,
+            log_tail: log_tail(&probe.log_file()),
+        }
+    }
+}
+
+/// The last `LOG_TAIL_LINES` lines of the log at `path`.
+fn log_tail(path: &Path) -> Result<Vec<String>, String> {
+    let content = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let lines: Vec<&str> = content.lines().collect();
+    let start = lines.len().saturating_sub(LOG_TAIL_LINES);
+    Ok(lines[start..].iter().map(|line| line.to_string()).collect())
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "KeyBloom {} diagnostics", env!("CARGO_PKG_VERSION"))?;
+        writeln!(f, "OS: {} ({})", std::env::consts::OS, std::env::consts::ARCH)?;
+
+        writeln!(f, "\n== Configuration ==")?;
+        match &self.config {
+            Ok(config) => write!(f, "{config}")?,
+            Err(err) => writeln!(f, "unavailable: {err}")?,
+        }
+
+        write_section(f, "Monitors", &self.monitors)?;
+        write_section(f, "OpenRGB devices", &self.devices)?;
+        write_section(f, "Application log (most recent)", &self.log_tail)
+    }
+}
+
+/// Write a titled list section, or the reason it couldn't be collected.
+fn write_section(
+    f: &mut fmt::Formatter<'_>,
+    title: &str,
+    lines: &Result<Vec<String>, String>,
+) -> fmt::Result {
+    writeln!(f, "\n== {title} ==")?;
+    match lines {
+        Ok(lines) if lines.is_empty() => writeln!(f, "(none)"),
+        Ok(lines) => lines.iter().try_for_each(|line| writeln!(f, "{line}")),
+        Err(err) => writeln!(f, "unavailable: {err}"),
+    }
+}
+
+/// Describe every monitor xcap can see.
+fn describe_monitors() -> Result<Vec<String>, String> {
+    let monitors = Monitor::all().map_err(|err| err.to_string())?;
+    Ok(monitors
+        .iter()
+        .enumerate()
+        .map(|(i, monitor)| {
+            format!(
+                "{i}: {} ({}x{} at {},{}, scale {}{})",
+                monitor.name(),
+                monitor.width(),
+                monitor.height(),
+                monitor.x(),
+                monitor.y(),
+                monitor.scale_factor(),
+                if monitor.is_primary() { ", primary" } else { "" }
+            )
+        })
+        .collect())
+}
+
+/// `config` with the MQTT credentials masked, since reports end up in public bug reports.
+fn redacted(config: &Config) -> Config {
+    let mut config = config.clone();
+    if let Some(mqtt) = config.mqtt.as_mut() {
+        for secret in [&mut mqtt.username, &mut mqtt.password].into_iter().flatten() {
+            *secret = REDACTED.to_string();
+        }
+    }
+    config
+}
+
+/// Describe every controller reported by the configured OpenRGB server.
+async fn describe_devices(config: &Config) -> Result<Vec<String>, String> {
+    let client = connect_client(config).await.map_err(|err| err.to_string())?;
+    with_connect_timeout(config, async {
+        let count = client.get_controller_count().await?;
+        let mut devices = Vec::new();
+        for id in 0..count {
+            match client.get_controller(id).await {
+                Ok(controller) => devices.push(format!(
+                    "{id}: {} ({:?}, {} LEDs, {} zones)",
+                    controller.name,
+                    controller.r#type,
+                    controller.leds.len(),
+                    controller.zones.len()
+                )),
+                Err(err) => devices.push(format!("{id}: unavailable: {err}")),
+            }
+        }
+        Ok::<_, OpenRGBError>(devices)
+    })
+    .await
+    .map_err(|err| err.to_string())
+}
+
+/// Collect a diagnostics report and write it next to the config file.
+///
+/// Returns the path of the written report.
+pub async fn write_report(config: &Config) -> Result<PathBuf, KeyBloomError> {
+    let report = Diagnostics::collect(config, &SystemProbe).await;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let dir = Config::config_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("diagnostics-{timestamp}.txt"));
+    fs::write(&path, report.to_string())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MqttConfig;
+
+    #[test]
+    fn redacted_masks_mqtt_credentials() {
+        let config = Config {
+            mqtt: Some(MqttConfig {
+                username: Some("keybloom".to_string()),
+                password: Some("hunter2".to_string()),
+                ..MqttConfig::default()
+            }),
+            ..Config::default()
+        };
+        let report = toml::to_string_pretty(&redacted(&config)).unwrap();
+        assert!(!report.contains("hunter2"));
+        assert!(!report.contains("username = \"keybloom\""));
+        assert_eq!(report.matches(REDACTED).count(), 2);
+        // The config in use is left alone
+        assert_eq!(config.mqtt.unwrap().password.as_deref(), Some("hunter2"));
+    }
+
+    /// Canned monitors and devices, and a log file of the test's own.
+    struct StubProbe {
+        log_file: PathBuf,
+    }
+
+    impl Probe for StubProbe {
+        fn monitors(&self) -> Result<Vec<String>, String> {
+            Ok(vec!["0: DP-1 (2560x1440 at 0,0, scale 1, primary)".to_string()])
+        }
+
+        async fn devices(&self, _config: &Config) -> Result<Vec<String>, String> {
+            Err("Connection refused".to_string())
+        }
+
+        fn log_file(&self) -> PathBuf {
+            self.log_file.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn report_assembles_every_section() {
+        let log_file = std::env::temp_dir().join(format!("keybloom-{}-log", std::process::id()));
+        let lines: Vec<String> = (1..=30).map(|i| format!("log line {i}")).collect();
+        fs::write(&log_file, lines.join("\n")).unwrap();
+        let probe = StubProbe { log_file };
+
+        let report = Diagnostics::collect(&Config::default(), &probe).await.to_string();
+        fs::remove_file(&probe.log_file).unwrap();
+        assert!(report.contains("== Configuration ==\n"), "{report}");
+        assert!(report.contains("== Monitors ==\n0: DP-1 (2560x1440"), "{report}");
+        assert!(report.contains("== OpenRGB devices ==\nunavailable: Connection refused"));
+        // Only the most recent lines of the log
+        assert!(report.contains("log line 11\n") && report.ends_with("log line 30\n"));
+        assert!(!report.contains("log line 10\n"), "{report}");
+
+        // A missing log doesn't keep the rest of the report from being written
+        let report = Diagnostics::collect(&Config::default(), &probe).await.to_string();
+        assert!(report.contains("== Application log (most recent) ==\nunavailable: "));
+        assert!(report.contains("== Monitors ==\n0: DP-1"), "{report}");
+    }
+}
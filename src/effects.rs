@@ -0,0 +1,38 @@
+//! Procedural lighting effects that don't need the screen.
+//!
+//! Each function renders one frame of colors for a point in time, so the effect loop only
+//! has to call it once per `Config::frame_interval` and send the result.
+
+use std::f32::consts::TAU;
+
+use openrgb::data::Color;
+use palette::{Hsv, IntoColor, Srgb};
+
+use crate::color_utils::srgb_to_color;
+
+/// A hue gradient across the LEDs that scrolls `speed` times per second.
+pub fn rainbow(led_count: usize, speed: f32, seconds: f32) -> Vec<Color> {
+    let offset = (seconds * speed).fract();
+    (0..led_count)
+        .map(|i| {
+            let hue = (i as f32 / led_count as f32 + offset).fract() * 360.0;
+            let rgb: Srgb<f32> = Hsv::new(hue, 1.0, 1.0).into_color();
+            srgb_to_color(rgb)
+        })
+        .collect()
+}
+
+/// `color` on every LED, fading fully out and back in `speed` times per second.
+pub fn breathing(color: [u8; 3], led_count: usize, speed: f32, seconds: f32) -> Vec<Color> {
+    let level = 0.5 - 0.5 * (seconds * speed * TAU).cos();
+    let scale = |channel: u8| (channel as f32 * level).round() as u8;
+    let [r, g, b] = color;
+    vec![
+        Color {
+            r: scale(r),
+            g: scale(g),
+            b: scale(b),
+        };
+        led_count
+    ]
+}
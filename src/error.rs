@@ -0,0 +1,176 @@
+//! Error types shared by the sync loop and the TUI.
+//!
+//! `KeyBloomError` lets callers tell apart connection problems, a missing device,
+//! screen capture failures and so on, instead of matching on formatted strings.
+
+use openrgb::OpenRGBError;
+use std::path::PathBuf;
+use thiserror::Error;
+use xcap::XCapError;
+
+/// Errors that can stop KeyBloom from syncing.
+#[derive(Error, Debug)]
+pub enum KeyBloomError {
+    /// The OpenRGB server could not be reached.
+    #[error("Failed to connect to OpenRGB server at {host}:{port}: {source}")]
+    Connect {
+        host: String,
+        port: u16,
+        #[source]
+        source: OpenRGBError,
+    },
+
+    /// The OpenRGB server didn't answer within `connect_timeout_ms`.
+    #[error(
+        "Connection to OpenRGB server at {host}:{port} timed out after {timeout_ms} ms. Check \
+         openrgb_host and openrgb_port, and that the OpenRGB SDK server is running."
+    )]
+    ConnectTimeout { host: String, port: u16, timeout_ms: u64 },
+
+    /// No OpenRGB controller matched the configured device name.
+    #[error("No device named '{0}' found. Check your OpenRGB server.")]
+    DeviceNotFound(String),
+
+    /// Screen capture failed.
+    #[error("Screen capture failed: {0}")]
+    Capture(#[from] XCapError),
+
+    /// Capture through the Wayland screenshot portal failed, usually for lack of permission.
+    #[error(
+        "Screen capture through the Wayland portal failed: {0}. Screen capture permission may \
+         be required: allow KeyBloom in the portal prompt or your compositor's settings, or \
+         run it under XWayland (see capture_backend)."
+    )]
+    WaylandCapture(XCapError),
+
+    /// The capture succeeded but returned an empty image.
+    #[error(
+        "Screen capture returned an empty image. The display may be locked or capture may not \
+         be permitted; set capture_fallback_image to keep tuning colors meanwhile."
+    )]
+    EmptyCapture,
+
+    /// Wallpaper mode couldn't find the desktop wallpaper.
+    #[error(
+        "Could not find the desktop wallpaper. Set wallpaper_image to the image to show instead."
+    )]
+    NoWallpaper,
+
+    /// No monitor could be found to capture, e.g. right after resume or in a headless session.
+    #[error("No monitors found to capture. Is a display connected?")]
+    NoMonitors,
+
+    /// The configured monitor index doesn't exist.
+    #[error("Monitor {index} not found; available monitors: {}", .available.join(", "))]
+    MonitorNotFound { index: usize, available: Vec<String> },
+
+    /// The OpenRGB server rejected a request or the connection broke.
+    #[error("OpenRGB error: {0}")]
+    Backend(#[from] OpenRGBError),
+
+    /// The configuration failed validation.
+    #[error("Invalid configuration: {}", .0.join(" "))]
+    Config(Vec<String>),
+
+    /// The calibration LUT file could not be parsed.
+    #[error("Invalid LUT file {}: {reason}", path.display())]
+    Lut { path: PathBuf, reason: String },
+
+    /// The `layout_file` could not be parsed or doesn't fit the devices.
+    #[error("Invalid layout file {}: {reason}", path.display())]
+    LayoutFile { path: PathBuf, reason: String },
+
+    /// An image file (e.g. the simulation test image) could not be loaded.
+    #[error("Failed to load image: {0}")]
+    Image(#[from] image::ImageError),
+
+    /// The compute thread pool could not be created.
+    #[error("Failed to build compute thread pool: {0}")]
+    ComputePool(#[from] rayon::ThreadPoolBuildError),
+
+    /// Terminal or file I/O failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::led_layout::LedLayout;
+    use crate::lut::Lut3d;
+    use crate::sync_loop::{connect_client, start_sync_loop, with_connect_timeout, SyncStatus};
+    use std::fs;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    /// Write `content` to `name` in the temp directory, unique to this test process.
+    fn temp_file(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("keybloom-{}-{name}", std::process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// A config pointing at a local port.
+    fn local_config(port: u16) -> Config {
+        Config {
+            openrgb_host: "127.0.0.1".to_string(),
+            openrgb_port: port,
+            connect_timeout_ms: 200,
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_config() {
+        let config = Config {
+            devices: Vec::new(),
+            ..Config::default()
+        };
+        let status = Arc::new(Mutex::new(SyncStatus::default()));
+        let err = start_sync_loop(&config, status, Arc::new(AtomicBool::new(false))).await;
+        assert!(matches!(err, Err(KeyBloomError::Config(_))), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn refused_connection() {
+        // Grab a free port, then close it again so nothing listens there
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        let err = connect_client(&local_config(port)).await.err().unwrap();
+        assert!(matches!(err, KeyBloomError::Connect { port: p, .. } if p == port), "{err}");
+    }
+
+    #[tokio::test]
+    async fn silent_server_times_out() {
+        // Accepts the connection but never answers the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let err = connect_client(&local_config(port)).await.err().unwrap();
+        assert!(matches!(err, KeyBloomError::ConnectTimeout { timeout_ms: 200, .. }), "{err}");
+    }
+
+    #[tokio::test]
+    async fn backend_error() {
+        let request = async { Err::<(), _>(OpenRGBError::ProtocolError("bad packet".into())) };
+        let err = with_connect_timeout(&Config::default(), request).await.unwrap_err();
+        assert!(matches!(err, KeyBloomError::Backend(_)), "{err}");
+    }
+
+    #[test]
+    fn bad_layout_and_lut_files() {
+        let layout = temp_file("layout.toml", "[[leds]]\nindex = 0\nrect = [0.0, 0.0, 2.0, 1.0]\n");
+        let err = LedLayout::load(&layout, 1).err().unwrap();
+        assert!(matches!(&err, KeyBloomError::LayoutFile { path, .. } if *path == layout), "{err}");
+        fs::remove_file(layout).unwrap();
+
+        let lut = temp_file("lut.cube", "LUT_3D_SIZE 2\n0 0 0\n");
+        let err = Lut3d::load(&lut).err().unwrap();
+        assert!(matches!(&err, KeyBloomError::Lut { path, .. } if *path == lut), "{err}");
+        fs::remove_file(lut).unwrap();
+
+        let missing = std::env::temp_dir().join("keybloom-missing-layout.toml");
+        let err = LedLayout::load(&missing, 1).err().unwrap();
+        assert!(matches!(err, KeyBloomError::Io(_)), "{err}");
+    }
+}
@@ -0,0 +1,146 @@
+//! Dominant color extraction.
+//!
+//! Instead of averaging a segment (which turns busy, colorful scenes into gray), a small
+//! k-means clustering is run over the segment's sampled pixels and the centroid of the
+//! largest cluster is used.
+
+use image::RgbaImage;
+use rayon::prelude::*;
+
+use crate::sync_loop::{
+    is_sampled, read_pixel, row_pixels, row_stride, SegmentLayout, SegmentSums, OPAQUE_WEIGHT,
+};
+
+/// Maximum number of assignment/update rounds per segment.
+const MAX_ITERATIONS: usize = 8;
+
+/// A sampled pixel in linear light, scaled to 0-65535 per channel.
+type Sample = [f32; 3];
+
+/// Find the dominant color of each segment of `layout`.
+///
+/// Pixels are sampled and linearized exactly like the averaging path, so the result is
+/// returned as `SegmentSums` (the dominant centroid weighted by its cluster size) and flows
+/// through the same color pipeline. Clustering ignores alpha beyond `alpha_threshold`.
+pub fn dominant_segment_colors(
+    frame: &RgbaImage,
+    layout: &SegmentLayout,
+    sampling_step: usize,
+    k: usize,
+    linear: &[u16; 256],
+    alpha_threshold: f32,
+) -> Vec<SegmentSums> {
+    collect_segment_samples(frame, layout, sampling_step, linear, alpha_threshold)
+        .par_iter()
+        .map(|samples| {
+            let (centroid, size) = dominant_cluster(samples, k);
+            let size_u64 = size as u64 * OPAQUE_WEIGHT;
+            let scale = |channel: f32| (channel.round() as u64) * size_u64;
+            (scale(centroid[0]), scale(centroid[1]), scale(centroid[2]), size_u64)
+        })
+        .collect()
+}
+
+/// Gather the linearized samples of every segment.
+fn collect_segment_samples(
+    frame: &RgbaImage,
+    layout: &SegmentLayout,
+    sampling_step: usize,
+    linear: &[u16; 256],
+    alpha_threshold: f32,
+) -> Vec<Vec<Sample>> {
+    let width = frame.width() as usize;
+    let height = frame.height() as usize;
+    let raw = frame.as_raw();
+    let stride = row_stride(frame);
+
+    let mut segments = vec![Vec::new(); layout.segments];
+    for row in (0..height).step_by(sampling_step) {
+        let Some(row_slice) = row_pixels(raw, row, stride, width) else {
+            continue;
+        };
+        for x in (0..width).step_by(sampling_step) {
+            let [r, g, b, a] = read_pixel(row_slice, x * 4);
+            if !is_sampled(a, alpha_threshold) {
+                continue;
+            }
+            if let Some(idx) = layout.index(x, row, width, height) {
+                segments[idx].push([
+                    linear[r as usize] as f32,
+                    linear[g as usize] as f32,
+                    linear[b as usize] as f32,
+                ]);
+            }
+        }
+    }
+    segments
+}
+
+/// Cluster `samples` into up to `k` groups and return the largest group's centroid and size.
+///
+/// Centroids start at evenly spaced samples, which keeps the result deterministic from
+/// frame to frame so the LEDs don't flicker between equally sized clusters.
+fn dominant_cluster(samples: &[Sample], k: usize) -> (Sample, usize) {
+    if samples.is_empty() {
+        return ([0.0; 3], 0);
+    }
+    let k = k.clamp(1, samples.len());
+    let mut centroids: Vec<Sample> = (0..k).map(|i| samples[i * samples.len() / k]).collect();
+    let mut assignments = vec![0usize; samples.len()];
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for (sample, assigned) in samples.iter().zip(assignments.iter_mut()) {
+            let nearest = nearest_centroid(sample, &centroids);
+            if nearest != *assigned {
+                *assigned = nearest;
+                changed = true;
+            }
+        }
+
+        let mut sums = vec![[0.0f32; 3]; k];
+        let mut counts = vec![0usize; k];
+        for (sample, &cluster) in samples.iter().zip(&assignments) {
+            for (sum, value) in sums[cluster].iter_mut().zip(sample) {
+                *sum += value;
+            }
+            counts[cluster] += 1;
+        }
+        for ((centroid, sum), &count) in centroids.iter_mut().zip(&sums).zip(&counts) {
+            if count > 0 {
+                *centroid = sum.map(|channel| channel / count as f32);
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    let mut counts = vec![0usize; k];
+    for &cluster in &assignments {
+        counts[cluster] += 1;
+    }
+    let (largest, &size) = counts
+        .iter()
+        .enumerate()
+        .max_by_key(|&(_, count)| *count)
+        .unwrap_or((0, &0));
+    (centroids[largest], size)
+}
+
+/// Index of the centroid closest to `sample` (squared Euclidean distance).
+fn nearest_centroid(sample: &Sample, centroids: &[Sample]) -> usize {
+    centroids
+        .iter()
+        .map(|centroid| {
+            centroid
+                .iter()
+                .zip(sample)
+                .map(|(c, s)| (c - s) * (c - s))
+                .sum::<f32>()
+        })
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(i, _)| i)
+}
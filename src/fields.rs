@@ -0,0 +1,452 @@
+//! The config fields editable from the TUI menu.
+//!
+//! Each field is described once in `FIELDS`: its label, description, nudge step and how to
+//! read it from and write it to a `Config`. The menu is built from this table, so adding a
+//! field only means adding a `ConfigField` variant and its entry here.
+
+use std::str::FromStr;
+
+use crate::color_picker::{format_color, parse_color};
+use crate::config::{Config, SyncMode};
+
+/// A config field that can be edited from the menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigField {
+    NumLeds,
+    TransitionSteps,
+    TransitionDelay,
+    FrameDelay,
+    ColorChangeThreshold,
+    BrightnessFactor,
+    SaturationFactor,
+    DebounceDuration,
+    OpenRgbHost,
+    OpenRgbPort,
+    DeviceName,
+    MonitorIndex,
+    SampleStep,
+    Mode,
+    ChannelGain,
+    ChannelOffset,
+    AspectFillColor,
+}
+
+/// How a `ConfigField` is shown and edited.
+pub struct FieldSpec {
+    pub field: ConfigField,
+    pub label: &'static str,
+    pub description: &'static str,
+    /// Step of the nudge keys; `None` for text fields. Whole-number steps keep the value
+    /// an integer.
+    pub nudge_step: Option<f64>,
+    /// Format the current value for the edit box.
+    get: fn(&Config) -> String,
+    /// Apply the edited text; input that doesn't parse leaves the value unchanged.
+    set: fn(&mut Config, &str),
+    /// The color the edited text would set, for the preview swatch; `None` for fields that
+    /// aren't color-valued.
+    preview: Option<PreviewFn>,
+}
+
+/// Reads the color an edit would set from the config and the edited text.
+type PreviewFn = fn(&Config, &str) -> Option<[u8; 3]>;
+
+/// Every editable field, in menu order.
+pub const FIELDS: [FieldSpec; 17] = [
+    FieldSpec {
+        field: ConfigField::NumLeds,
+        label: "Number of LEDs",
+        description: "Set the number of LEDs on your (first) device.",
+        nudge_step: Some(1.0),
+        get: |config| config.primary_device().num_leds.to_string(),
+        set: |config, input| {
+            let device = config.primary_device_mut();
+            device.num_leds = parse_or(input, device.num_leds);
+        },
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::TransitionSteps,
+        label: "Transition Steps",
+        description: "Define how many steps the color transition should take.",
+        nudge_step: Some(1.0),
+        get: |config| config.transition_steps.to_string(),
+        set: |config, input| config.transition_steps = parse_or(input, config.transition_steps),
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::TransitionDelay,
+        label: "Transition Delay (ms)",
+        description: "Specify the delay (ms) between each transition step.",
+        nudge_step: Some(10.0),
+        get: |config| config.transition_delay_ms.to_string(),
+        set: |config, input| {
+            config.transition_delay_ms = parse_or(input, config.transition_delay_ms)
+        },
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::FrameDelay,
+        label: "Frame Delay (ms)",
+        description: "Set the delay (ms) between each frame capture.",
+        nudge_step: Some(10.0),
+        get: |config| config.frame_delay_ms.to_string(),
+        set: |config, input| config.frame_delay_ms = parse_or(input, config.frame_delay_ms),
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::ColorChangeThreshold,
+        label: "Color Change Threshold",
+        description: "Threshold for significant color changes (0.0-1.0).",
+        nudge_step: Some(0.01),
+        get: |config| config.color_change_threshold.to_string(),
+        set: |config, input| {
+            config.color_change_threshold = parse_or(input, config.color_change_threshold)
+        },
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::BrightnessFactor,
+        label: "Brightness Factor",
+        description: "Factor to adjust overall brightness (larger = brighter).",
+        nudge_step: Some(0.1),
+        get: |config| config.brightness_factor.to_string(),
+        set: |config, input| config.brightness_factor = parse_or(input, config.brightness_factor),
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::SaturationFactor,
+        label: "Saturation Factor",
+        description: "Factor to adjust color saturation (larger = more vibrant).",
+        nudge_step: Some(0.1),
+        get: |config| config.saturation_factor.to_string(),
+        set: |config, input| config.saturation_factor = parse_or(input, config.saturation_factor),
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::DebounceDuration,
+        label: "Debounce Duration (ms)",
+        description: "Minimum duration (ms) between transitions to prevent rapid changes.",
+        nudge_step: Some(10.0),
+        get: |config| config.debounce_duration_ms.to_string(),
+        set: |config, input| {
+            config.debounce_duration_ms = parse_or(input, config.debounce_duration_ms)
+        },
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::OpenRgbHost,
+        label: "OpenRGB Host",
+        description: "Hostname or IP of the OpenRGB server.",
+        nudge_step: None,
+        get: |config| config.openrgb_host.clone(),
+        set: |config, input| config.openrgb_host = input.to_string(),
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::OpenRgbPort,
+        label: "OpenRGB Port",
+        description: "Port number of the OpenRGB server.",
+        nudge_step: None,
+        get: |config| config.openrgb_port.to_string(),
+        set: |config, input| config.openrgb_port = parse_or(input, config.openrgb_port),
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::DeviceName,
+        label: "Device Name",
+        description: "Name of the (first) OpenRGB device to control.",
+        nudge_step: None,
+        get: |config| config.primary_device().device_name.clone(),
+        set: |config, input| config.primary_device_mut().device_name = input.to_string(),
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::MonitorIndex,
+        label: "Monitor Index",
+        description: "Index of the monitor to capture (0-based).",
+        nudge_step: Some(1.0),
+        get: |config| config.monitor_index.to_string(),
+        set: |config, input| config.monitor_index = parse_or(input, config.monitor_index),
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::SampleStep,
+        label: "Sample Step",
+        description: "Sample every Nth pixel in each direction (larger = faster, less precise).",
+        nudge_step: Some(1.0),
+        get: |config| config.sample_step.to_string(),
+        set: |config, input| config.sample_step = parse_or(input, config.sample_step),
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::Mode,
+        label: "Mode",
+        description: "'sync' follows the screen, a color like #ff8800 or '255 136 0' shows it \
+                      statically, 'wallpaper', 'external' (colors from stdin), 'rainbow', \
+                      'breathing #ff8800', or 'off'.",
+        nudge_step: None,
+        get: |config| format_mode(config.mode),
+        set: |config, input| config.mode = parse_mode(input, config.mode).unwrap_or(config.mode),
+        preview: Some(|config, input| match parse_mode(input, config.mode)? {
+            SyncMode::Static { color } | SyncMode::Breathing { color, .. } => Some(color),
+            _ => None,
+        }),
+    },
+    FieldSpec {
+        field: ConfigField::ChannelGain,
+        label: "Channel Gain (R G B)",
+        description: "Per-channel gain to calibrate the LEDs, e.g. '1.2 1 1' for a weak red. \
+                      Check with 'Show White'.",
+        nudge_step: None,
+        get: |config| format_triple(config.channel_gain),
+        set: |config, input| {
+            config.channel_gain = parse_triple(input).unwrap_or(config.channel_gain)
+        },
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::ChannelOffset,
+        label: "Channel Offset (R G B)",
+        description: "Per-channel offset (-255 to 255) added after the gain, e.g. '0 -10 -10'.",
+        nudge_step: None,
+        get: |config| format_triple(config.channel_offset),
+        set: |config, input| {
+            config.channel_offset = parse_triple(input).unwrap_or(config.channel_offset)
+        },
+        preview: None,
+    },
+    FieldSpec {
+        field: ConfigField::AspectFillColor,
+        label: "Aspect Fill Color",
+        description: "Color of LEDs outside the frame in 'fit' aspect mode, \
+                      e.g. #000000 or '0 0 0'.",
+        nudge_step: None,
+        get: |config| format_color(config.aspect_fill_color),
+        set: |config, input| {
+            config.aspect_fill_color = parse_color(input).unwrap_or(config.aspect_fill_color)
+        },
+        preview: Some(|_, input| parse_color(input)),
+    },
+];
+
+impl ConfigField {
+    /// This field's entry in `FIELDS`.
+    pub fn spec(self) -> &'static FieldSpec {
+        FIELDS
+            .iter()
+            .find(|spec| spec.field == self)
+            .expect("every ConfigField has an entry in FIELDS")
+    }
+
+    /// The field's current value, as shown in the edit box.
+    pub fn get(self, config: &Config) -> String {
+        (self.spec().get)(config)
+    }
+
+    /// Set the field from edited text, keeping the old value if it doesn't parse.
+    pub fn set(self, config: &mut Config, input: &str) {
+        (self.spec().set)(config, input)
+    }
+
+    /// Whether the field takes a color, so editing it shows a preview swatch.
+    pub fn has_preview(self) -> bool {
+        self.spec().preview.is_some()
+    }
+
+    /// The color `input` would set, if the field takes a color and `input` parses to one.
+    pub fn preview(self, config: &Config, input: &str) -> Option<[u8; 3]> {
+        self.spec().preview.and_then(|preview| preview(config, input))
+    }
+
+    /// Step the field by its nudge step, `direction` times; no-op for text fields.
+    ///
+    /// Returns the new value as text.
+    pub fn nudge(self, config: &mut Config, direction: f64) -> Option<String> {
+        let step = self.spec().nudge_step?;
+        let current: f64 = self.get(config).parse().ok()?;
+        let value = (current + step * direction).max(0.0);
+        // Round to the step's precision so repeated nudges don't accumulate float noise
+        let text = if step.fract() == 0.0 {
+            (value.round() as u64).to_string()
+        } else {
+            ((value * 1000.0).round() / 1000.0).to_string()
+        };
+        self.set(config, &text);
+        Some(self.get(config))
+    }
+}
+
+/// Parse `input`, falling back to `current` if it isn't valid.
+fn parse_or<T: FromStr>(input: &str, current: T) -> T {
+    input.parse().unwrap_or(current)
+}
+
+/// Format an `[r, g, b]` triple as space-separated values.
+fn format_triple<T: ToString>(values: [T; 3]) -> String {
+    values.map(|value| value.to_string()).join(" ")
+}
+
+/// Parse three values separated by spaces or commas.
+fn parse_triple<T: FromStr + Copy>(input: &str) -> Option<[T; 3]> {
+    let values: Vec<T> = input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    values.try_into().ok()
+}
+
+/// Describe `mode` the way `parse_mode` reads it.
+fn format_mode(mode: SyncMode) -> String {
+    match mode {
+        SyncMode::ScreenSync => "sync".to_string(),
+        SyncMode::Static { color } => format_color(color),
+        SyncMode::Off => "off".to_string(),
+        SyncMode::Wallpaper => "wallpaper".to_string(),
+        SyncMode::External => "external".to_string(),
+        SyncMode::Rainbow { .. } => "rainbow".to_string(),
+        SyncMode::Breathing { color, .. } => format!("breathing {}", format_color(color)),
+    }
+}
+
+/// Parse the mode names and hex colors accepted by the Mode field.
+///
+/// Animated modes keep the speed of `current` when it is the same mode, so re-submitting
+/// the field doesn't reset a speed set in the config file. A bare `breathing` keeps the
+/// current breathing color, or starts from white.
+fn parse_mode(input: &str, current: SyncMode) -> Option<SyncMode> {
+    let input = input.trim().to_lowercase();
+    match input.as_str() {
+        "sync" => Some(SyncMode::ScreenSync),
+        "off" => Some(SyncMode::Off),
+        "wallpaper" => Some(SyncMode::Wallpaper),
+        "external" => Some(SyncMode::External),
+        "rainbow" => Some(SyncMode::Rainbow {
+            speed: match current {
+                SyncMode::Rainbow { speed } => speed,
+                _ => SyncMode::RAINBOW_SPEED,
+            },
+        }),
+        _ if input.starts_with("breathing") => {
+            let (current_color, speed) = match current {
+                SyncMode::Breathing { color, speed } => (color, speed),
+                _ => ([255, 255, 255], SyncMode::BREATHING_SPEED),
+            };
+            let color_input = input["breathing".len()..].trim();
+            let color = if color_input.is_empty() {
+                current_color
+            } else {
+                parse_color(color_input)?
+            };
+            Some(SyncMode::Breathing { color, speed })
+        }
+        _ => parse_color(&input).map(|color| SyncMode::Static { color }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BREATHING_RED: SyncMode = SyncMode::Breathing {
+        color: [255, 0, 0],
+        speed: 0.5,
+    };
+
+    #[test]
+    fn breathing_with_bad_hex_is_rejected() {
+        assert_eq!(parse_mode("breathing #zzzzzz", SyncMode::Off), None);
+        assert_eq!(parse_mode("breathing 12", BREATHING_RED), None);
+
+        let mut config = Config {
+            mode: BREATHING_RED,
+            ..Config::default()
+        };
+        ConfigField::Mode.set(&mut config, "breathing nope");
+        assert_eq!(config.mode, BREATHING_RED);
+    }
+
+    #[test]
+    fn breathing_parses_its_color() {
+        assert_eq!(
+            parse_mode("Breathing #00ff00", SyncMode::Off),
+            Some(SyncMode::Breathing {
+                color: [0, 255, 0],
+                speed: SyncMode::BREATHING_SPEED,
+            })
+        );
+        assert_eq!(
+            parse_mode("breathing", SyncMode::Off),
+            Some(SyncMode::Breathing {
+                color: [255, 255, 255],
+                speed: SyncMode::BREATHING_SPEED,
+            })
+        );
+    }
+
+    #[test]
+    fn resubmitting_keeps_the_speed() {
+        let rainbow = SyncMode::Rainbow { speed: 0.7 };
+        assert_eq!(parse_mode("rainbow", rainbow), Some(rainbow));
+        assert_eq!(parse_mode("breathing", BREATHING_RED), Some(BREATHING_RED));
+        assert_eq!(
+            parse_mode("breathing #0000ff", BREATHING_RED),
+            Some(SyncMode::Breathing {
+                color: [0, 0, 255],
+                speed: 0.5,
+            })
+        );
+
+        // Switching modes starts from the default speed
+        assert_eq!(
+            parse_mode("rainbow", BREATHING_RED),
+            Some(SyncMode::Rainbow {
+                speed: SyncMode::RAINBOW_SPEED,
+            })
+        );
+    }
+
+    #[test]
+    fn format_round_trips() {
+        for mode in [
+            SyncMode::ScreenSync,
+            SyncMode::Off,
+            SyncMode::Static {
+                color: [18, 52, 86],
+            },
+            BREATHING_RED,
+        ] {
+            assert_eq!(parse_mode(&format_mode(mode), mode), Some(mode));
+        }
+    }
+
+    #[test]
+    fn fill_color_accepts_hex_and_channels() {
+        let mut config = Config::default();
+        ConfigField::AspectFillColor.set(&mut config, "#102030");
+        assert_eq!(config.aspect_fill_color, [16, 32, 48]);
+        ConfigField::AspectFillColor.set(&mut config, "1, 2, 3");
+        assert_eq!(config.aspect_fill_color, [1, 2, 3]);
+        ConfigField::AspectFillColor.set(&mut config, "300 0 0");
+        assert_eq!(config.aspect_fill_color, [1, 2, 3]);
+        assert_eq!(ConfigField::AspectFillColor.get(&config), "#010203");
+    }
+
+    #[test]
+    fn preview_shows_the_color_an_edit_would_set() {
+        let config = Config::default();
+        let fill = ConfigField::AspectFillColor;
+        assert!(fill.has_preview());
+        assert_eq!(fill.preview(&config, "#f80"), Some([255, 136, 0]));
+        assert_eq!(fill.preview(&config, "#f8"), None);
+
+        let mode = ConfigField::Mode;
+        assert_eq!(mode.preview(&config, "255 136 0"), Some([255, 136, 0]));
+        assert_eq!(mode.preview(&config, "breathing #00f"), Some([0, 0, 255]));
+        assert_eq!(mode.preview(&config, "rainbow"), None);
+
+        assert!(!ConfigField::DeviceName.has_preview());
+        assert_eq!(ConfigField::DeviceName.preview(&config, "#ffffff"), None);
+    }
+}
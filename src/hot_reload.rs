@@ -0,0 +1,66 @@
+//! Reloading the config file while syncing.
+//!
+//! Hand edits of the TOML are picked up without a restart: the file is re-read whenever it
+//! changes and, if it still parses and validates, the sync loop restarts with it. Edits
+//! that don't are logged and ignored, so a half-typed value never stops the LEDs.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::control::ControlState;
+
+/// Watch `path` and hand every valid new version of it to `state`.
+///
+/// `adjust` is applied to each reloaded config before validation, e.g. to keep command
+/// line overrides in effect. Watching stops when the returned watcher is dropped.
+pub fn watch(
+    path: PathBuf,
+    state: Arc<ControlState>,
+    adjust: impl Fn(&mut Config) + Send + 'static,
+) -> notify::Result<RecommendedWatcher> {
+    // Editors often replace the file instead of writing it, so watch its directory
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        match event {
+            Ok(event) if touches(&event, &path) => reload(&path, &state, &adjust),
+            Ok(_) => {}
+            Err(err) => warn!("Config watcher error: {err}"),
+        }
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+/// Whether `event` may have changed the contents of `path`.
+fn touches(event: &Event, path: &Path) -> bool {
+    matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+        && event.paths.iter().any(|p| p == path)
+}
+
+/// Re-read `path` and apply it if it is a valid config.
+fn reload(path: &Path, state: &ControlState, adjust: &impl Fn(&mut Config)) {
+    // A file truncated mid-write would otherwise parse as all defaults
+    if fs::metadata(path).is_ok_and(|meta| meta.len() == 0) {
+        return;
+    }
+    let mut config = match Config::load_from(path) {
+        Ok(config) => config,
+        Err(err) => {
+            warn!("Ignoring edited config {}: {err}", path.display());
+            return;
+        }
+    };
+    adjust(&mut config);
+    if let Err(errors) = config.validate() {
+        warn!("Ignoring edited config {}: {}", path.display(), errors.join("; "));
+        return;
+    }
+    if state.replace_config(config) {
+        info!("Reloaded {}", path.display());
+    }
+}
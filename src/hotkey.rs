@@ -0,0 +1,112 @@
+//! Global hotkey that turns output on or off from anywhere.
+//!
+//! With `toggle_hotkey = "ctrl+alt+k"`, a headless KeyBloom flips its enabled flag whenever
+//! the key is pressed, even while its terminal isn't focused. The OS hook needs a build
+//! with the `global-hotkey` feature and an X11 session; elsewhere the setting is ignored
+//! with a warning, and output can still be toggled over the control port or MQTT.
+
+// Without the OS hook nothing dispatches actions, but the logic is still built and tested
+#![cfg_attr(not(all(feature = "global-hotkey", target_os = "linux")), allow(dead_code))]
+
+use std::sync::Arc;
+
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::control::ControlState;
+
+/// What a hotkey press asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyAction {
+    ToggleOutput,
+}
+
+/// The action for a hotkey event with `event_id`.
+///
+/// Only presses of the registered toggle key (`toggle_id`) act; releases and other keys
+/// are ignored, so holding the key down toggles once.
+pub fn action_for(event_id: u32, pressed: bool, toggle_id: u32) -> Option<HotkeyAction> {
+    (pressed && event_id == toggle_id).then_some(HotkeyAction::ToggleOutput)
+}
+
+/// Carry out `action` on the headless control state.
+pub fn dispatch(action: HotkeyAction, state: &ControlState) {
+    match action {
+        HotkeyAction::ToggleOutput => {
+            let enabled = state.toggle();
+            info!("Hotkey: output turned {}.", if enabled { "on" } else { "off" });
+        }
+    }
+}
+
+/// Listen for the configured `toggle_hotkey`, if any.
+///
+/// Failing to install the hook is only logged, so syncing still starts.
+pub fn spawn(config: &Config, state: Arc<ControlState>) {
+    let Some(hotkey) = &config.toggle_hotkey else {
+        return;
+    };
+    if let Err(err) = install(hotkey, state) {
+        warn!("Toggle hotkey '{hotkey}' is not available: {err}");
+    }
+}
+
+/// Register `hotkey` with X11 and dispatch its presses on a background thread.
+#[cfg(all(feature = "global-hotkey", target_os = "linux"))]
+fn install(hotkey: &str, state: Arc<ControlState>) -> Result<(), String> {
+    use global_hotkey::hotkey::HotKey;
+    use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+
+    let hotkey: HotKey = hotkey.parse().map_err(|err| format!("{err}"))?;
+    let manager = GlobalHotKeyManager::new().map_err(|err| err.to_string())?;
+    manager.register(hotkey).map_err(|err| err.to_string())?;
+    info!("Press {hotkey} to turn output on or off.");
+
+    std::thread::spawn(move || {
+        // Dropping the manager would unregister the key
+        let _manager = manager;
+        while let Ok(event) = GlobalHotKeyEvent::receiver().recv() {
+            let pressed = event.state == HotKeyState::Pressed;
+            if let Some(action) = action_for(event.id, pressed, hotkey.id()) {
+                dispatch(action, &state);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(all(feature = "global-hotkey", target_os = "linux")))]
+fn install(_hotkey: &str, _state: Arc<ControlState>) -> Result<(), String> {
+    Err(if cfg!(feature = "global-hotkey") {
+        "global hotkeys are only supported on Linux with X11".to_string()
+    } else {
+        "KeyBloom was built without the `global-hotkey` feature".to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use std::sync::Mutex;
+
+    #[test]
+    fn only_presses_of_the_toggle_key_act() {
+        assert_eq!(action_for(7, true, 7), Some(HotkeyAction::ToggleOutput));
+        assert_eq!(action_for(7, false, 7), None);
+        assert_eq!(action_for(8, true, 7), None);
+    }
+
+    #[test]
+    fn toggle_flips_output_and_stops_the_loop() {
+        let state = ControlState::new(Config::default(), Arc::new(Mutex::new(Default::default())));
+        assert!(state.is_enabled());
+
+        dispatch(HotkeyAction::ToggleOutput, &state);
+        assert!(!state.is_enabled());
+        assert!(state.stop_signal.load(Ordering::Relaxed));
+
+        dispatch(HotkeyAction::ToggleOutput, &state);
+        assert!(state.is_enabled());
+    }
+}
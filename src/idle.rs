@@ -0,0 +1,78 @@
+//! Pausing while the user is away.
+//!
+//! There is no portable way to ask the system how long input has been idle, so `IdlePause`
+//! treats an unchanged screen as idleness instead: once every captured frame has looked
+//! the same for `idle_pause_secs`, the sync loop stops processing frames and only checks
+//! for a change every `IDLE_POLL_INTERVAL`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::time::{Duration, Instant};
+
+use image::RgbaImage;
+use tracing::info;
+
+/// How often the screen is checked for a change while paused.
+pub const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Distance in bytes between the pixels compared; odd, so it walks across channels and
+/// columns instead of hitting the same ones in every row.
+const FINGERPRINT_STRIDE: usize = 4099;
+
+/// Detects a static screen and decides when to pause.
+pub struct IdlePause {
+    after: Duration,
+    /// Fingerprint of the last frame of each capture slot.
+    fingerprints: Vec<Option<u64>>,
+    last_change: Instant,
+    paused: bool,
+}
+
+impl IdlePause {
+    /// Pause once the screen has been unchanged for `after`.
+    pub fn new(after: Duration) -> Self {
+        Self {
+            after,
+            fingerprints: Vec::new(),
+            last_change: Instant::now(),
+            paused: false,
+        }
+    }
+
+    /// Compare the newest frame of capture slot `slot` against its previous one.
+    pub fn observe(&mut self, slot: usize, frame: &RgbaImage) {
+        if self.fingerprints.len() <= slot {
+            self.fingerprints.resize(slot + 1, None);
+        }
+        let fingerprint = Some(fingerprint(frame));
+        if self.fingerprints[slot] != fingerprint {
+            self.fingerprints[slot] = fingerprint;
+            self.last_change = Instant::now();
+        }
+    }
+
+    /// Whether frames should be skipped because the screen has been static long enough.
+    pub fn is_paused(&mut self) -> bool {
+        let idle = self.last_change.elapsed() >= self.after;
+        if idle != self.paused {
+            self.paused = idle;
+            if idle {
+                info!("Screen unchanged for {}s; pausing until it changes.", self.after.as_secs());
+            } else {
+                info!("Screen changed; resuming.");
+            }
+        }
+        idle
+    }
+}
+
+/// A cheap hash of a sparse sample of `frame`'s pixels.
+fn fingerprint(frame: &RgbaImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write_u32(frame.width());
+    hasher.write_u32(frame.height());
+    for &byte in frame.as_raw().iter().step_by(FINGERPRINT_STRIDE) {
+        hasher.write_u8(byte);
+    }
+    hasher.finish()
+}
@@ -0,0 +1,190 @@
+//! End-to-end latency measurement.
+//!
+//! `run_latency_test` runs the real capture → compute → device-write pipeline a number of
+//! times while the user flashes the screen between black and white, timing each stage. The
+//! averages are combined with the configured frame delay and the duration of a black-to-white
+//! transition to estimate how long a change on screen takes to reach the LEDs.
+
+use crate::config::Config;
+use crate::error::KeyBloomError;
+use crate::output::OutputStage;
+use crate::sync_loop::*;
+use openrgb::data::Color;
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+/// Number of pipeline iterations measured by the latency test.
+const LATENCY_SAMPLES: u32 = 20;
+
+/// The color the user flashes on screen, starting from black.
+const FLASH_COLOR: Color = Color { r: 255, g: 255, b: 255 };
+
+/// Time spent in each pipeline stage for one frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageTimings {
+    pub capture: Duration,
+    pub process: Duration,
+    pub write: Duration,
+}
+
+/// Accumulated stage timings plus the fixed delays added by the configuration.
+#[derive(Debug, Default)]
+pub struct LatencyReport {
+    samples: u32,
+    /// Frames whose LED colors differed from the frame before, i.e. that saw the flash.
+    changed: u32,
+    totals: StageTimings,
+    /// Average wait before a screen change is picked up by the next capture.
+    capture_wait: Duration,
+    /// Time a transition from black to `FLASH_COLOR` takes to settle.
+    transition: Duration,
+}
+
+impl LatencyReport {
+    /// Create an empty report for the given configuration.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            capture_wait: config.frame_interval() / 2,
+            transition: transition_duration(&[Color::default()], &[FLASH_COLOR], config),
+            ..Self::default()
+        }
+    }
+
+    /// Add the timings of one measured frame, and whether its colors changed.
+    pub fn record(&mut self, timings: StageTimings, changed: bool) {
+        self.samples += 1;
+        self.changed += changed as u32;
+        self.totals.capture += timings.capture;
+        self.totals.process += timings.process;
+        self.totals.write += timings.write;
+    }
+
+    /// Average time per stage over all recorded frames.
+    pub fn average(&self) -> StageTimings {
+        if self.samples == 0 {
+            return StageTimings::default();
+        }
+        StageTimings {
+            capture: self.totals.capture / self.samples,
+            process: self.totals.process / self.samples,
+            write: self.totals.write / self.samples,
+        }
+    }
+
+    /// Estimated time from a change on screen to the LEDs settling on the new color.
+    pub fn total(&self) -> Duration {
+        let avg = self.average();
+        self.capture_wait + avg.capture + avg.process + avg.write + self.transition
+    }
+}
+
+impl fmt::Display for LatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let avg = self.average();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        writeln!(f, "Latency over {} frames ({} saw a change):", self.samples, self.changed)?;
+        writeln!(f, "  wait for capture  {:8.2} ms", ms(self.capture_wait))?;
+        writeln!(f, "  capture           {:8.2} ms", ms(avg.capture))?;
+        writeln!(f, "  process           {:8.2} ms", ms(avg.process))?;
+        writeln!(f, "  device write      {:8.2} ms", ms(avg.write))?;
+        writeln!(f, "  transition        {:8.2} ms", ms(self.transition))?;
+        write!(f, "  total             {:8.2} ms", ms(self.total()))?;
+        if self.changed == 0 {
+            write!(f, "\nThe screen never changed; flash it while measuring for a real estimate.")?;
+        }
+        Ok(())
+    }
+}
+
+/// Measure the pipeline latency against the configured monitor and device.
+pub async fn run_latency_test(config: &Config) -> Result<LatencyReport, KeyBloomError> {
+    let output = OutputStage::new(config)?;
+    let client = match connect_with_wait(config, &AtomicBool::new(false)).await {
+        Ok(Some(client)) => client,
+        Ok(None) => unreachable!("the latency test is never stopped while connecting"),
+        Err(err) => return Err(err),
+    };
+    let devices = resolve_devices(&client, config).await?;
+    let max_leds = devices.iter().map(|d| d.num_leds).max().unwrap_or(0);
+    let pipeline = ColorPipeline::new(config, max_leds)?;
+    let monitor = select_monitor(config)?;
+
+    println!(
+        "Measuring {LATENCY_SAMPLES} frames. Flash the screen between black and white, e.g. by \
+         toggling a full-screen white window, until it finishes..."
+    );
+    let mut report = LatencyReport::new(config);
+    let mut previous: Option<Vec<Color>> = None;
+    for _ in 0..LATENCY_SAMPLES {
+        let started = Instant::now();
+        let frame = monitor.capture_image()?;
+        let captured = Instant::now();
+
+        let frame = prepare_frame(frame, config, None);
+        let colors = pipeline.colors(&frame, config, None);
+        let processed = Instant::now();
+
+        write_frame(&client, &devices, &output, &colors, None).await?;
+        let written = Instant::now();
+
+        let timings = StageTimings {
+            capture: captured - started,
+            process: processed - captured,
+            write: written - processed,
+        };
+        report.record(timings, previous.is_some_and(|previous| previous != colors));
+        previous = Some(colors);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn totals_average_the_stages_and_add_the_delays() {
+        let config = Config {
+            target_fps: Some(50),
+            transition_steps: 20,
+            transition_delay_ms: 10,
+            ..Config::default()
+        };
+        let mut report = LatencyReport::new(&config);
+        assert_eq!(report.total(), ms(10 + 200));
+
+        report.record(StageTimings { capture: ms(4), process: ms(2), write: ms(6) }, false);
+        report.record(StageTimings { capture: ms(8), process: ms(4), write: ms(2) }, true);
+        let avg = report.average();
+        assert_eq!((avg.capture, avg.process, avg.write), (ms(6), ms(3), ms(4)));
+        // Half a 20 ms frame of waiting, the average stages, then the transition
+        assert_eq!(report.total(), ms(10 + 6 + 3 + 4 + 200));
+        assert_eq!(report.changed, 1);
+    }
+
+    #[test]
+    fn transition_includes_the_stagger_and_scaling() {
+        let config = Config {
+            transition_steps: 20,
+            transition_delay_ms: 10,
+            power_stagger_ms: 30,
+            ..Config::default()
+        };
+        // The last LED starts 3 steps after the first
+        assert_eq!(LatencyReport::new(&config).transition, ms(230));
+
+        // Scaling only shortens smaller changes; the black-to-white flash is the largest
+        let scaled = Config {
+            scale_transition_steps: true,
+            ..config
+        };
+        assert_eq!(LatencyReport::new(&scaled).transition, ms(230));
+        let gray = [Color { r: 128, g: 128, b: 128 }];
+        assert!(transition_duration(&[Color::default()], &gray, &scaled) < ms(230));
+    }
+}
@@ -0,0 +1,175 @@
+//! Per-LED screen rectangles from `layout_file`.
+//!
+//! For layouts that aren't a strip or a regular grid, every LED can be given its own
+//! rectangle of the screen, in normalized coordinates (0.0-1.0, origin top left):
+//!
+//! ```toml
+//! [[leds]]
+//! index = 0
+//! rect = [0.0, 0.0, 0.25, 0.5]   # x0, y0, x1, y1
+//! ```
+//!
+//! The same structure is accepted as JSON when the file name ends in `.json`. Each LED
+//! averages the pixels inside its rectangle; where rectangles overlap, a pixel counts for
+//! the lowest LED index only.
+
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::error::KeyBloomError;
+
+/// The contents of a layout file.
+#[derive(Deserialize)]
+struct LayoutFile {
+    leds: Vec<LedRect>,
+}
+
+#[derive(Deserialize)]
+struct LedRect {
+    index: usize,
+    rect: [f32; 4],
+}
+
+/// One normalized rectangle per LED, indexed by physical LED number.
+pub struct LedLayout {
+    rects: Vec<[f32; 4]>,
+}
+
+impl LedLayout {
+    /// Load the layout at `path` for devices with `led_count` LEDs.
+    ///
+    /// Fails unless every rectangle lies within 0..1 and every LED below `led_count` has
+    /// exactly one. Rectangles for LEDs past `led_count` are ignored.
+    pub fn load(path: &Path, led_count: usize) -> Result<Self, KeyBloomError> {
+        let content = fs::read_to_string(path)?;
+        let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        Self::parse(&content, is_json, led_count).map_err(|reason| KeyBloomError::LayoutFile {
+            path: path.to_path_buf(),
+            reason,
+        })
+    }
+
+    /// Parse and check the contents of a layout file.
+    fn parse(content: &str, is_json: bool, led_count: usize) -> Result<Self, String> {
+        let file: LayoutFile = if is_json {
+            serde_json::from_str(content).map_err(|err| err.to_string())?
+        } else {
+            toml::from_str(content).map_err(|err| err.to_string())?
+        };
+
+        let mut rects = vec![None; led_count];
+        let mut ignored = 0;
+        for LedRect { index, rect } in file.leds {
+            let [x0, y0, x1, y1] = rect;
+            let in_range = |v: f32| (0.0..=1.0).contains(&v);
+            if !(rect.into_iter().all(in_range) && x0 < x1 && y0 < y1) {
+                return Err(format!(
+                    "LED {index}: rect must lie within 0..1 with x0 < x1 and y0 < y1"
+                ));
+            }
+            match rects.get_mut(index) {
+                Some(Some(_)) => return Err(format!("LED {index} is listed more than once")),
+                Some(slot) => *slot = Some(rect),
+                None => ignored += 1,
+            }
+        }
+
+        let missing: Vec<String> = (0..led_count)
+            .filter(|&i| rects[i].is_none())
+            .map(|i| i.to_string())
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!("no rect for LEDs {}", missing.join(", ")));
+        }
+        if ignored > 0 {
+            warn!("Ignoring {ignored} layout rects past the {led_count} LEDs of the devices.");
+        }
+        Ok(Self {
+            rects: rects.into_iter().flatten().collect(),
+        })
+    }
+
+    /// The LED whose rectangle contains pixel `(x, y)` of a `width` x `height` frame.
+    #[inline]
+    pub fn index(&self, x: usize, y: usize, width: usize, height: usize) -> Option<usize> {
+        // Compare pixel centers, so edges shared by neighboring rects aren't counted twice
+        let fx = (x as f32 + 0.5) / width as f32;
+        let fy = (y as f32 + 0.5) / height as f32;
+        self.rects
+            .iter()
+            .position(|&[x0, y0, x1, y1]| fx >= x0 && fx < x1 && fy >= y0 && fy < y1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two LEDs splitting the screen into a left and a right half.
+    const HALVES: &str = r#"
+        [[leds]]
+        index = 0
+        rect = [0.0, 0.0, 0.5, 1.0]
+
+        [[leds]]
+        index = 1
+        rect = [0.5, 0.0, 1.0, 1.0]
+    "#;
+
+    #[test]
+    fn parses_toml_and_json() {
+        let layout = LedLayout::parse(HALVES, false, 2).unwrap();
+        assert_eq!(layout.rects, [[0.0, 0.0, 0.5, 1.0], [0.5, 0.0, 1.0, 1.0]]);
+
+        let json = r#"{"leds": [{"index": 0, "rect": [0.0, 0.0, 1.0, 1.0]}]}"#;
+        assert!(LedLayout::parse(json, true, 1).is_ok());
+    }
+
+    #[test]
+    fn rejects_out_of_range_and_inverted_rects() {
+        for rect in ["[0.0, 0.0, 1.5, 1.0]", "[-0.1, 0.0, 0.5, 1.0]", "[0.5, 0.0, 0.5, 1.0]"] {
+            let content = format!("[[leds]]\nindex = 0\nrect = {rect}\n");
+            let err = LedLayout::parse(&content, false, 1).err().unwrap();
+            assert!(err.contains("LED 0"), "{rect}: {err}");
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_and_missing_leds() {
+        let duplicate = format!("{HALVES}\n[[leds]]\nindex = 1\nrect = [0.0, 0.0, 1.0, 1.0]\n");
+        let err = LedLayout::parse(&duplicate, false, 2).err().unwrap();
+        assert!(err.contains("LED 1 is listed more than once"), "{err}");
+
+        let err = LedLayout::parse(HALVES, false, 4).err().unwrap();
+        assert_eq!(err, "no rect for LEDs 2, 3");
+    }
+
+    #[test]
+    fn ignores_rects_past_the_led_count() {
+        let layout = LedLayout::parse(HALVES, false, 1).unwrap();
+        assert_eq!(layout.rects.len(), 1);
+    }
+
+    #[test]
+    fn shared_edges_count_once() {
+        let layout = LedLayout::parse(HALVES, false, 2).unwrap();
+        // In a 10 px wide frame, pixel 4 is left of the shared edge at 0.5 and pixel 5 right
+        assert_eq!(layout.index(4, 0, 10, 10), Some(0));
+        assert_eq!(layout.index(5, 0, 10, 10), Some(1));
+        assert_eq!(layout.index(0, 9, 10, 10), Some(0));
+        assert_eq!(layout.index(9, 9, 10, 10), Some(1));
+        // In a 9 px wide frame the middle pixel's center lies on the edge and goes right
+        assert_eq!(layout.index(4, 0, 9, 9), Some(1));
+    }
+
+    #[test]
+    fn uncovered_pixels_map_nowhere() {
+        let corner = "[[leds]]\nindex = 0\nrect = [0.0, 0.0, 0.5, 0.5]\n";
+        let layout = LedLayout::parse(corner, false, 1).unwrap();
+        assert_eq!(layout.index(0, 0, 10, 10), Some(0));
+        assert_eq!(layout.index(7, 7, 10, 10), None);
+    }
+}
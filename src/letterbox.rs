@@ -0,0 +1,110 @@
+//! Letterbox (black bar) detection.
+//!
+//! Movies in a different aspect than the monitor are shown with black bars, which would
+//! otherwise be averaged into the edge segments and pull them toward black.
+
+use std::time::{Duration, Instant};
+
+use image::RgbaImage;
+
+/// Bars thinner than this fraction of the frame are treated as border noise.
+const MIN_BAR_FRACTION: f32 = 0.01;
+
+/// Pixels visited per row or column when checking whether it is black.
+const SCAN_SAMPLES: u32 = 64;
+
+/// The part of a frame showing actual content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Find the content rectangle inside any black bars around `frame`.
+///
+/// A row or column counts as black if none of its sampled channels exceed `black_level`.
+/// Returns `None` if the frame is entirely black or has no bars worth cropping.
+pub fn detect_content_rect(frame: &RgbaImage, black_level: u8) -> Option<ContentRect> {
+    let (width, height) = frame.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let is_black = |x: u32, y: u32| frame.get_pixel(x, y).0[..3].iter().all(|&c| c <= black_level);
+    let row_is_black = |y: u32| {
+        let step = (width / SCAN_SAMPLES).max(1);
+        (0..width).step_by(step as usize).all(|x| is_black(x, y))
+    };
+    let column_is_black = |x: u32| {
+        let step = (height / SCAN_SAMPLES).max(1);
+        (0..height).step_by(step as usize).all(|y| is_black(x, y))
+    };
+
+    // A fully black frame has no content to lock onto
+    let top = (0..height).find(|&y| !row_is_black(y))?;
+    let bottom = (0..height).rev().find(|&y| !row_is_black(y))?;
+    let left = (0..width).find(|&x| !column_is_black(x))?;
+    let right = (0..width).rev().find(|&x| !column_is_black(x))?;
+
+    // Ignore bars too thin to matter, e.g. a dark edge of the content itself
+    let min_rows = (height as f32 * MIN_BAR_FRACTION) as u32;
+    let min_columns = (width as f32 * MIN_BAR_FRACTION) as u32;
+    let (top, bottom) = if top.max(height - 1 - bottom) > min_rows {
+        (top, bottom)
+    } else {
+        (0, height - 1)
+    };
+    let (left, right) = if left.max(width - 1 - right) > min_columns {
+        (left, right)
+    } else {
+        (0, width - 1)
+    };
+
+    let rect = ContentRect {
+        x: left,
+        y: top,
+        width: right - left + 1,
+        height: bottom - top + 1,
+    };
+    (rect.width != width || rect.height != height).then_some(rect)
+}
+
+/// Crops frames to their content, re-detecting the bars only every `interval`.
+pub struct LetterboxCropper {
+    black_level: u8,
+    interval: Duration,
+    last_detection: Option<Instant>,
+    rect: Option<ContentRect>,
+}
+
+impl LetterboxCropper {
+    pub fn new(black_level: u8, interval: Duration) -> Self {
+        Self {
+            black_level,
+            interval,
+            last_detection: None,
+            rect: None,
+        }
+    }
+
+    /// Crop `frame` to the most recently detected content rectangle.
+    ///
+    /// The bars are re-detected once the interval has elapsed, or immediately if the
+    /// rectangle no longer fits the frame.
+    pub fn crop(&mut self, frame: RgbaImage) -> RgbaImage {
+        let (width, height) = frame.dimensions();
+        let stale = self.last_detection.is_none_or(|at| at.elapsed() >= self.interval);
+        let out_of_bounds = self
+            .rect
+            .is_some_and(|r| r.x + r.width > width || r.y + r.height > height);
+        if stale || out_of_bounds {
+            self.rect = detect_content_rect(&frame, self.black_level);
+            self.last_detection = Some(Instant::now());
+        }
+        match self.rect {
+            Some(r) => image::imageops::crop_imm(&frame, r.x, r.y, r.width, r.height).to_image(),
+            None => frame,
+        }
+    }
+}
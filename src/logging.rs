@@ -0,0 +1,62 @@
+//! Logging setup.
+//!
+//! While the TUI owns the terminal, anything written to stdout or stderr draws over the
+//! ratatui frame, so log records go to a file in the data directory instead. Headless and
+//! one-shot runs log to stderr as usual.
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use tracing_subscriber::EnvFilter;
+
+use crate::config::Config;
+
+/// Level used when neither `--log-level` nor `RUST_LOG` is given.
+const DEFAULT_LEVEL: &str = "info";
+
+/// Where log records are written.
+pub enum LogTarget {
+    Stderr,
+    /// `keybloom.log` in the data directory.
+    File,
+}
+
+/// Install the global subscriber.
+///
+/// `level` takes precedence over `RUST_LOG` and accepts the same directives (e.g. `debug` or
+/// `KeyBloom::sync_loop=trace`). Returns the path of the log file when logging to one.
+pub fn init(level: Option<&str>, target: LogTarget) -> io::Result<Option<PathBuf>> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LEVEL)),
+    };
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match target {
+        LogTarget::Stderr => {
+            builder.with_writer(io::stderr).init();
+            Ok(None)
+        }
+        LogTarget::File => {
+            let (file, path) = open_log_file()?;
+            builder.with_writer(Mutex::new(file)).with_ansi(false).init();
+            Ok(Some(path))
+        }
+    }
+}
+
+/// Path of the log file written while the TUI is running.
+pub fn log_file_path() -> PathBuf {
+    Config::data_dir().join("keybloom.log")
+}
+
+/// Open the log file for appending, creating the data directory if needed.
+fn open_log_file() -> io::Result<(File, PathBuf)> {
+    fs::create_dir_all(Config::data_dir())?;
+    let path = log_file_path();
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    Ok((file, path))
+}
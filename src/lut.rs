@@ -0,0 +1,198 @@
+//! 3D lookup tables for calibrating LED output.
+//!
+//! Supports the common Adobe/Resolve `.cube` format: a `LUT_3D_SIZE` header, optional
+//! `DOMAIN_MIN`/`DOMAIN_MAX` lines and `size³` RGB entries with red varying fastest.
+//! Colors are mapped through the table with trilinear interpolation.
+
+use crate::error::KeyBloomError;
+use palette::Srgb;
+use std::fs;
+use std::path::Path;
+
+/// A 3D color lookup table loaded from a `.cube` file.
+pub struct Lut3d {
+    /// Number of entries along each axis.
+    size: usize,
+    /// `size³` output colors, red index varying fastest, then green, then blue.
+    table: Vec<[f32; 3]>,
+    /// Input value mapped to the first entry on each axis.
+    domain_min: [f32; 3],
+    /// Input value mapped to the last entry on each axis.
+    domain_max: [f32; 3],
+}
+
+impl Lut3d {
+    /// Load and parse a `.cube` file.
+    pub fn load(path: &Path) -> Result<Self, KeyBloomError> {
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content).map_err(|reason| KeyBloomError::Lut {
+            path: path.to_path_buf(),
+            reason,
+        })
+    }
+
+    /// Parse the contents of a `.cube` file.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let keyword = parts.next().unwrap_or_default();
+            match keyword {
+                "TITLE" | "LUT_1D_INPUT_RANGE" => {}
+                "LUT_1D_SIZE" => return Err("1D LUTs are not supported".to_string()),
+                "LUT_3D_SIZE" => {
+                    let n: usize = parts
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .ok_or_else(|| format!("line {}: invalid LUT_3D_SIZE", line_no + 1))?;
+                    if n < 2 {
+                        return Err(format!("line {}: LUT_3D_SIZE must be at least 2", line_no + 1));
+                    }
+                    size = Some(n);
+                }
+                "DOMAIN_MIN" => domain_min = parse_triplet(parts, line_no)?,
+                "DOMAIN_MAX" => domain_max = parse_triplet(parts, line_no)?,
+                _ => table.push(parse_triplet(line.split_whitespace(), line_no)?),
+            }
+        }
+
+        let size = size.ok_or("missing LUT_3D_SIZE")?;
+        if table.len() != size.pow(3) {
+            return Err(format!(
+                "expected {} entries for a size {size} LUT, found {}",
+                size.pow(3),
+                table.len()
+            ));
+        }
+        Ok(Self {
+            size,
+            table,
+            domain_min,
+            domain_max,
+        })
+    }
+
+    /// Map a color through the table using trilinear interpolation.
+    pub fn apply(&self, srgb: Srgb<f32>) -> Srgb<f32> {
+        let max_index = (self.size - 1) as f32;
+        let input = [srgb.red, srgb.green, srgb.blue];
+
+        // Position of the input on each axis, in table index units
+        let mut base = [0usize; 3];
+        let mut frac = [0.0f32; 3];
+        for axis in 0..3 {
+            let range = (self.domain_max[axis] - self.domain_min[axis]).max(f32::EPSILON);
+            let pos = ((input[axis] - self.domain_min[axis]) / range).clamp(0.0, 1.0) * max_index;
+            base[axis] = (pos.floor() as usize).min(self.size - 2);
+            frac[axis] = pos - base[axis] as f32;
+        }
+
+        let mut out = [0.0f32; 3];
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let weight: f32 = (0..3)
+                .map(|axis| {
+                    if offset[axis] == 1 {
+                        frac[axis]
+                    } else {
+                        1.0 - frac[axis]
+                    }
+                })
+                .product();
+            let entry = self.entry(
+                base[0] + offset[0],
+                base[1] + offset[1],
+                base[2] + offset[2],
+            );
+            for (o, e) in out.iter_mut().zip(entry) {
+                *o += e * weight;
+            }
+        }
+        Srgb::new(
+            out[0].clamp(0.0, 1.0),
+            out[1].clamp(0.0, 1.0),
+            out[2].clamp(0.0, 1.0),
+        )
+    }
+
+    /// The table entry at the given red/green/blue indices.
+    fn entry(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.table[r + g * self.size + b * self.size * self.size]
+    }
+}
+
+/// Parse exactly three floats from the remaining fields of a line.
+fn parse_triplet<'a>(
+    mut parts: impl Iterator<Item = &'a str>,
+    line_no: usize,
+) -> Result<[f32; 3], String> {
+    let mut values = [0.0f32; 3];
+    for value in &mut values {
+        *value = parts
+            .next()
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| format!("line {}: expected three numbers", line_no + 1))?;
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `.cube` file of the given size whose entries are `map` of each grid point.
+    fn cube(size: usize, map: impl Fn([f32; 3]) -> [f32; 3]) -> String {
+        let max = (size - 1) as f32;
+        let mut content = format!("TITLE \"test\"\nLUT_3D_SIZE {size}\n");
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let [r, g, b] = map([r as f32 / max, g as f32 / max, b as f32 / max]);
+                    content.push_str(&format!("{r} {g} {b}\n"));
+                }
+            }
+        }
+        content
+    }
+
+    fn assert_close(actual: Srgb<f32>, expected: [f32; 3]) {
+        let actual = [actual.red, actual.green, actual.blue];
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-4, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn identity_lut_leaves_colors_unchanged() {
+        for size in [2, 5, 17] {
+            let lut = Lut3d::parse(&cube(size, |rgb| rgb)).unwrap();
+            for rgb in [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [0.2, 0.55, 0.9], [0.33, 0.0, 0.71]] {
+                assert_close(lut.apply(Srgb::new(rgb[0], rgb[1], rgb[2])), rgb);
+            }
+        }
+    }
+
+    #[test]
+    fn known_lut_remaps_colors() {
+        // Invert red and swap green with blue; linear maps interpolate exactly
+        let lut = Lut3d::parse(&cube(3, |[r, g, b]| [1.0 - r, b, g])).unwrap();
+        assert_close(lut.apply(Srgb::new(0.2, 0.5, 0.9)), [0.8, 0.9, 0.5]);
+        assert_close(lut.apply(Srgb::new(1.0, 0.0, 0.25)), [0.0, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(Lut3d::parse("0 0 0\n").is_err());
+        assert!(Lut3d::parse("LUT_3D_SIZE 1\n0 0 0\n").is_err());
+        assert!(Lut3d::parse("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(Lut3d::parse("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+    }
+}
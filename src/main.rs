@@ -1,28 +1,310 @@
 //! Main entry point for KeyBloom.
 //!
-//! This file sets up the tokio runtime and loads or creates a default configuration,
-//! then launches our TUI menu.
+//! This file sets up the tokio runtime, parses the command line and loads or creates a
+//! default configuration, then launches our TUI menu (or syncs directly when headless).
 
+mod benchmark;
+mod capture;
+mod cli;
+mod color_log;
+mod color_picker;
 mod color_utils;
 mod config;
+mod control;
+mod diagnostics;
+mod effects;
+mod error;
+mod extract;
+mod fields;
+mod hot_reload;
+mod hotkey;
+mod idle;
+mod latency;
+mod led_layout;
+mod letterbox;
+mod logging;
+mod lut;
+mod mqtt;
+mod once;
+mod output;
+mod simulate;
 mod sync_loop;
 mod ui;
+mod wallpaper;
+mod white_point;
 
-use crate::config::Config;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use clap::Parser;
+use xcap::{Monitor, Window};
+use tracing::{error, warn};
+
+use crate::benchmark::run_benchmark;
+use crate::cli::{Cli, Command};
+use crate::config::{Config, Settings};
+use crate::control::ControlState;
+use crate::error::KeyBloomError;
+use crate::latency::run_latency_test;
+use crate::logging::LogTarget;
+use crate::once::run_once;
+use crate::sync_loop::{connect_client, show_test_pattern, with_connect_timeout, SyncStatus};
 use crate::ui::show_menu;
 
+/// How long `--test-leds` keeps the pattern up before exiting.
+const TEST_PATTERN_HOLD: Duration = Duration::from_secs(5);
+
 // Define a new error type that implements Send + Sync + 'static
 type AnyError = Box<dyn std::error::Error + Send + Sync + 'static>;
 
 #[tokio::main]
 async fn main() -> Result<(), AnyError> {
-    // Load or create default config
-    let mut config = Config::load();
+    let cli = Cli::parse();
+
+    // The TUI owns the terminal, so log to a file unless running without it
+    let one_shot = cli.latency_test
+        || cli.once
+        || cli.test_leds
+        || cli.benchmark.is_some()
+        || cli.command.is_some();
+    let log_target = if cli.headless || one_shot {
+        LogTarget::Stderr
+    } else {
+        LogTarget::File
+    };
+    logging::init(cli.log_level.as_deref(), log_target)
+        .map_err(|err| format!("Failed to set up logging: {err}"))?;
+
+    // Load the requested profile, or load/create the default config
+    let mut profile = cli.profile.clone();
+    let mut config = match &profile {
+        Some(name) => Config::load_profile(name)
+            .map_err(|err| format!("Failed to load profile '{name}': {err}"))?,
+        // Otherwise restore the profile that was active last time, if it still exists
+        None => match Settings::load().last_profile {
+            Some(name) => match Config::load_profile(&name) {
+                Ok(config) => {
+                    profile = Some(name);
+                    config
+                }
+                Err(err) => {
+                    warn!("Failed to restore profile '{name}': {err}");
+                    Config::load()
+                }
+            },
+            None => Config::load(),
+        },
+    };
+    cli.apply_overrides(&mut config);
+
+    match cli.command {
+        Some(Command::ListMonitors) => return list_monitors(),
+        Some(Command::ListWindows) => return list_windows(),
+        Some(Command::ListDevices) => return list_devices(&config).await,
+        None => {}
+    }
+
+    // Measure the achievable frame rate without touching any device
+    if let Some(seconds) = cli.benchmark {
+        let report = run_benchmark(&config, Duration::from_secs(seconds))
+            .map_err(|err| format!("Benchmark failed: {err}"))?;
+        if cli.json {
+            println!("{}", report.to_json());
+        } else {
+            println!("{report}");
+        }
+        return Ok(());
+    }
+
+    // Measure capture-to-LED latency instead of launching the menu
+    if cli.latency_test {
+        match run_latency_test(&config).await {
+            Ok(report) => println!("{report}"),
+            Err(err) => error!("Latency test failed: {err}"),
+        }
+        return Ok(());
+    }
+
+    // Show the LED test pattern to check num_leds and the LED order
+    if cli.test_leds {
+        let lit = show_test_pattern(&config)
+            .await
+            .map_err(|err| format!("Failed to show the test pattern: {err}"))?;
+        println!(
+            "Test pattern on {lit}: LED 0 red, 1 green, 2 blue, the rest dim white \
+             (reverse_led_order = {}, led_offset = {}).",
+            config.reverse_led_order, config.led_offset
+        );
+        tokio::time::sleep(TEST_PATTERN_HOLD).await;
+        return Ok(());
+    }
+
+    // Apply the current screen once and exit, e.g. from a script
+    if cli.once {
+        run_once(&config)
+            .await
+            .map_err(|err| format!("Failed to apply the screen colors: {err}"))?;
+        return Ok(());
+    }
+
+    if cli.headless {
+        let source = Config::source_path(profile.as_deref());
+        return run_headless(&config, source, &cli).await;
+    }
 
     // Launch the TUI menu (which can handle "Save and Sync" and the sync screen)
-    if let Err(err) = show_menu(&mut config).await {
-        eprintln!("Error running TUI menu: {err}");
+    if let Err(err) = show_menu(&mut config, profile).await {
+        error!("Error running TUI menu: {err}");
+    }
+
+    Ok(())
+}
+
+/// Run the sync loop directly until SIGINT/SIGTERM.
+///
+/// The loop is restarted whenever the config file at `source` changes or, if enabled, a
+/// control command (over the control port, MQTT or the toggle hotkey) asks for it.
+async fn run_headless(config: &Config, source: PathBuf, cli: &Cli) -> Result<(), AnyError> {
+    let sync_status = Arc::new(Mutex::new(SyncStatus::default()));
+    let state = Arc::new(ControlState::new(config.clone(), sync_status));
+
+    if let Some(port) = config.control_port {
+        let server_state = Arc::clone(&state);
+        tokio::spawn(async move {
+            if let Err(err) = control::serve(port, server_state).await {
+                error!("Control API stopped: {err}");
+            }
+        });
+    }
+
+    if let Some(mqtt_config) = config.mqtt.clone() {
+        tokio::spawn(mqtt::run(mqtt_config, Arc::clone(&state)));
     }
+    hotkey::spawn(config, Arc::clone(&state));
+
+    // Keep command line overrides in effect across reloads
+    let overrides = cli.clone();
+    let _watcher = hot_reload::watch(source, Arc::clone(&state), move |config| {
+        overrides.apply_overrides(config)
+    })
+    .inspect_err(|err| warn!("Not watching the config file for changes: {err}"))
+    .ok();
+
+    let signal_state = Arc::clone(&state);
+    tokio::spawn(async move {
+        wait_for_shutdown_signal().await;
+        signal_state.shutdown();
+    });
+
+    control::supervise(state).await;
+    Ok(())
+}
+
+/// Print every monitor xcap can capture, with the index used by `monitor_index`.
+fn list_monitors() -> Result<(), AnyError> {
+    let monitors = Monitor::all().map_err(|err| format!("Failed to list monitors: {err}"))?;
+    let rows = monitors
+        .iter()
+        .enumerate()
+        .map(|(i, monitor)| {
+            vec![
+                i.to_string(),
+                monitor.name().to_string(),
+                format!("{}x{}", monitor.width(), monitor.height()),
+                format!("{},{}", monitor.x(), monitor.y()),
+                if monitor.is_primary() { "yes" } else { "" }.to_string(),
+            ]
+        })
+        .collect();
+    print_table(&["INDEX", "NAME", "RESOLUTION", "POSITION", "PRIMARY"], rows);
+    Ok(())
+}
 
+/// Print every window xcap can capture, with the title to use in `capture_target`.
+fn list_windows() -> Result<(), AnyError> {
+    let windows = Window::all().map_err(|err| format!("Failed to list windows: {err}"))?;
+    let rows = windows
+        .iter()
+        .map(|window| {
+            vec![
+                window.app_name().to_string(),
+                window.title().to_string(),
+                format!("{}x{}", window.width(), window.height()),
+                format!("{},{}", window.x(), window.y()),
+                if window.is_minimized() { "yes" } else { "" }.to_string(),
+            ]
+        })
+        .collect();
+    print_table(&["APP", "TITLE", "SIZE", "POSITION", "MINIMIZED"], rows);
     Ok(())
 }
+
+/// Print every controller on the configured OpenRGB server.
+async fn list_devices(config: &Config) -> Result<(), AnyError> {
+    let failed = |err: KeyBloomError| format!("Failed to list OpenRGB devices: {err}");
+    let client = connect_client(config).await.map_err(failed)?;
+    let rows = with_connect_timeout(config, async {
+        let mut rows = Vec::new();
+        for id in 0..client.get_controller_count().await? {
+            let controller = client.get_controller(id).await?;
+            rows.push(vec![
+                id.to_string(),
+                controller.name,
+                format!("{:?}", controller.r#type),
+                controller.leds.len().to_string(),
+            ]);
+        }
+        Ok::<_, openrgb::OpenRGBError>(rows)
+    })
+    .await
+    .map_err(failed)?;
+    print_table(&["ID", "NAME", "TYPE", "LEDS"], rows);
+    Ok(())
+}
+
+/// Print `rows` under `headers` with every column padded to its widest cell.
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let format_row = |cells: Vec<String>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{cell:<width$}"))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    println!("{}", format_row(headers.iter().map(|h| h.to_string()).collect()));
+    for row in rows {
+        println!("{}", format_row(row));
+    }
+}
+
+/// Resolve once the process receives SIGINT (Ctrl+C) or, on Unix, SIGTERM.
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+            }
+            Err(err) => {
+                warn!("Could not listen for SIGTERM: {err}");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
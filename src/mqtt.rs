@@ -0,0 +1,365 @@
+//! MQTT integration, e.g. for Home Assistant.
+//!
+//! When `mqtt` is configured, a headless KeyBloom connects to the broker and periodically
+//! publishes (retained, QoS 0):
+//!
+//! ```text
+//! <topic>/color   #ff8800   color of the first LED
+//! <topic>/state   ON        whether syncing is running
+//! ```
+//!
+//! It also subscribes to `<topic>/set`, where `ON` and `OFF` start and stop syncing. The
+//! client runs in its own task and speaks just enough MQTT 3.1.1 for this; a missing or
+//! failing broker is retried in the background and never holds up the sync loop.
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::time::{interval, sleep, timeout, MissedTickBehavior};
+use tracing::{info, warn};
+
+use crate::color_utils::color_to_hex;
+use crate::config::MqttConfig;
+use crate::control::{Command, ControlState};
+use crate::sync_loop::lock_or_recover;
+
+/// How long connecting (including the broker's CONNACK) may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Keep-alive announced to the broker; a ping is sent at half this interval.
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+/// First delay before reconnecting; doubles per failed attempt up to `MAX_RETRY_DELAY`.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(2);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+/// Largest packet accepted from the broker; everything KeyBloom subscribes to is tiny.
+const MAX_PACKET_LEN: usize = 64 * 1024;
+
+/// MQTT control packet types (upper nibble of the fixed header).
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xC0;
+
+/// Stay connected to the broker until the process exits, reconnecting after failures.
+pub async fn run(config: MqttConfig, state: Arc<ControlState>) {
+    let mut retry_delay = MIN_RETRY_DELAY;
+    loop {
+        match session(&config, &state).await {
+            Ok(()) => {
+                warn!("MQTT broker closed the connection; reconnecting.");
+                retry_delay = MIN_RETRY_DELAY;
+            }
+            Err(err) => warn!(
+                "MQTT connection to {}:{} failed: {err}; retrying in {}s.",
+                config.host,
+                config.port,
+                retry_delay.as_secs()
+            ),
+        }
+        sleep(retry_delay).await;
+        retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+    }
+}
+
+/// One connection: publish the status on every interval until the broker goes away.
+async fn session(config: &MqttConfig, state: &Arc<ControlState>) -> io::Result<()> {
+    let stream = timeout(CONNECT_TIMEOUT, TcpStream::connect((&config.host[..], config.port)))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??;
+    let (mut reader, mut writer) = stream.into_split();
+    timeout(CONNECT_TIMEOUT, handshake(config, &mut reader, &mut writer))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no CONNACK from broker"))??;
+
+    let command_topic = format!("{}/set", config.topic);
+    writer.write_all(&subscribe_packet(&command_topic)).await?;
+    info!("Connected to MQTT broker {}:{}.", config.host, config.port);
+
+    let mut incoming = tokio::spawn(read_commands(reader, command_topic, Arc::clone(state)));
+    let mut publish = interval(Duration::from_millis(config.publish_interval_ms));
+    publish.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut ping = interval(KEEP_ALIVE / 2);
+    ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let result = loop {
+        tokio::select! {
+            read = &mut incoming => break read.unwrap_or_else(|err| Err(io::Error::other(err))),
+            _ = publish.tick() => {
+                if let Err(err) = publish_status(config, state, &mut writer).await {
+                    break Err(err);
+                }
+            }
+            _ = ping.tick() => {
+                if let Err(err) = writer.write_all(&[PINGREQ, 0]).await {
+                    break Err(err);
+                }
+            }
+        }
+    };
+    incoming.abort();
+    result
+}
+
+/// Send CONNECT and wait for a successful CONNACK.
+async fn handshake(
+    config: &MqttConfig,
+    reader: &mut OwnedReadHalf,
+    writer: &mut OwnedWriteHalf,
+) -> io::Result<()> {
+    writer.write_all(&connect_packet(config)).await?;
+    let (header, body) = read_packet(reader).await?;
+    if header & 0xF0 != CONNACK || body.len() < 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "expected CONNACK"));
+    }
+    match body[1] {
+        0 => Ok(()),
+        4 | 5 => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "broker rejected the username or password",
+        )),
+        code => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("broker refused the connection (code {code})"),
+        )),
+    }
+}
+
+/// Publish the first LED's color and whether syncing is running.
+async fn publish_status(
+    config: &MqttConfig,
+    state: &ControlState,
+    writer: &mut OwnedWriteHalf,
+) -> io::Result<()> {
+    let color = lock_or_recover(&state.sync_status).current_colors.first().copied();
+    if let Some(color) = color {
+        let topic = format!("{}/color", config.topic);
+        writer.write_all(&publish_packet(&topic, color_to_hex(color).as_bytes())).await?;
+    }
+    let running = if state.is_enabled() { "ON" } else { "OFF" };
+    let topic = format!("{}/state", config.topic);
+    writer.write_all(&publish_packet(&topic, running.as_bytes())).await
+}
+
+/// Apply every `ON`/`OFF` published to `command_topic` until the connection closes.
+async fn read_commands(
+    mut reader: OwnedReadHalf,
+    command_topic: String,
+    state: Arc<ControlState>,
+) -> io::Result<()> {
+    loop {
+        let (header, body) = read_packet(&mut reader).await?;
+        if header & 0xF0 != PUBLISH {
+            continue; // SUBACK, PINGRESP
+        }
+        let Some((topic, payload)) = parse_publish(header, &body) else {
+            continue;
+        };
+        if topic != command_topic {
+            continue;
+        }
+        let payload = String::from_utf8_lossy(payload);
+        match payload.trim().to_ascii_uppercase().as_str() {
+            "ON" => {
+                info!("MQTT: starting sync.");
+                state.handle(Command::Start);
+            }
+            "OFF" => {
+                info!("MQTT: stopping sync.");
+                state.handle(Command::Stop);
+            }
+            other => warn!("Ignoring unknown MQTT command '{other}' (expected ON or OFF)."),
+        }
+    }
+}
+
+/// Read one packet, returning its first header byte and its body.
+async fn read_packet(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<(u8, Vec<u8>)> {
+    let header = reader.read_u8().await?;
+    // Remaining length: up to four 7-bit groups, least significant first
+    let mut length = 0usize;
+    for shift in (0..28).step_by(7) {
+        let byte = reader.read_u8().await?;
+        length |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            if length > MAX_PACKET_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("packet of {length} bytes exceeds the {MAX_PACKET_LEN} byte limit"),
+                ));
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await?;
+            return Ok((header, body));
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "malformed packet length"))
+}
+
+/// Split a PUBLISH body into its topic and payload.
+fn parse_publish(header: u8, body: &[u8]) -> Option<(&str, &[u8])> {
+    let topic_len = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+    let topic = std::str::from_utf8(body.get(2..2 + topic_len)?).ok()?;
+    // QoS 1 and 2 messages carry a packet id before the payload
+    let qos = (header >> 1) & 0x03;
+    let payload_start = 2 + topic_len + if qos > 0 { 2 } else { 0 };
+    Some((topic, body.get(payload_start..)?))
+}
+
+fn connect_packet(config: &MqttConfig) -> Vec<u8> {
+    let mut flags = 0x02; // clean session
+    let mut payload = Vec::new();
+    put_string(&mut payload, &config.client_id);
+    if let Some(username) = &config.username {
+        flags |= 0x80;
+        put_string(&mut payload, username);
+        if let Some(password) = &config.password {
+            flags |= 0x40;
+            put_string(&mut payload, password);
+        }
+    }
+    let mut body = Vec::new();
+    put_string(&mut body, "MQTT");
+    body.push(4); // protocol level 3.1.1
+    body.push(flags);
+    body.extend_from_slice(&(KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    body.extend_from_slice(&payload);
+    packet(CONNECT, &body)
+}
+
+fn subscribe_packet(topic: &str) -> Vec<u8> {
+    let mut body = 1u16.to_be_bytes().to_vec(); // packet id
+    put_string(&mut body, topic);
+    body.push(0); // QoS 0
+    packet(SUBSCRIBE, &body)
+}
+
+/// A retained QoS 0 PUBLISH.
+fn publish_packet(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_string(&mut body, topic);
+    body.extend_from_slice(payload);
+    packet(PUBLISH | 0x01, &body)
+}
+
+/// Prefix `body` with the fixed header: `header` and the variable-length body size.
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![header];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(body);
+    out
+}
+
+/// Append a length-prefixed UTF-8 string.
+fn put_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode a packet with a `len`-byte body and read it back.
+    async fn round_trip(len: usize) -> (Vec<u8>, u8, Vec<u8>) {
+        let body = vec![0xAB; len];
+        let encoded = packet(PUBLISH, &body);
+        let (header, decoded) = read_packet(&mut encoded.as_slice()).await.unwrap();
+        (encoded, header, decoded)
+    }
+
+    #[tokio::test]
+    async fn remaining_length_boundaries() {
+        for (len, length_bytes) in [
+            (0, vec![0x00]),
+            (127, vec![0x7F]),
+            (128, vec![0x80, 0x01]),
+            (16_383, vec![0xFF, 0x7F]),
+            (16_384, vec![0x80, 0x80, 0x01]),
+        ] {
+            let (encoded, header, decoded) = round_trip(len).await;
+            assert_eq!(encoded[1..1 + length_bytes.len()], length_bytes[..], "length {len}");
+            assert_eq!(encoded.len(), 1 + length_bytes.len() + len);
+            assert_eq!(header, PUBLISH);
+            assert_eq!(decoded.len(), len);
+        }
+    }
+
+    #[tokio::test]
+    async fn read_packet_rejects_oversized_and_malformed_lengths() {
+        // Claims about 256 MB without sending it
+        let huge = [PUBLISH, 0xFF, 0xFF, 0xFF, 0x7F];
+        let err = read_packet(&mut huge.as_slice()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let endless = [PUBLISH, 0x80, 0x80, 0x80, 0x80, 0x01];
+        let err = read_packet(&mut endless.as_slice()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parse_publish_qos0() {
+        let encoded = publish_packet("keybloom/set", b"ON");
+        let (topic, payload) = parse_publish(encoded[0], &encoded[2..]).unwrap();
+        assert_eq!(topic, "keybloom/set");
+        assert_eq!(payload, b"ON");
+    }
+
+    #[test]
+    fn parse_publish_qos1_skips_packet_id() {
+        let mut body = Vec::new();
+        put_string(&mut body, "keybloom/set");
+        body.extend_from_slice(&7u16.to_be_bytes());
+        body.extend_from_slice(b"OFF");
+        let (topic, payload) = parse_publish(PUBLISH | 0x02, &body).unwrap();
+        assert_eq!(topic, "keybloom/set");
+        assert_eq!(payload, b"OFF");
+    }
+
+    #[test]
+    fn parse_publish_rejects_truncated_topic() {
+        assert!(parse_publish(PUBLISH, &[0x00, 0x05, b'a']).is_none());
+        assert!(parse_publish(PUBLISH, &[]).is_none());
+    }
+
+    #[test]
+    fn connect_packet_with_credentials() {
+        let config = MqttConfig {
+            client_id: "kb".to_string(),
+            username: Some("user".to_string()),
+            password: Some("pw".to_string()),
+            ..MqttConfig::default()
+        };
+        let encoded = connect_packet(&config);
+        let mut expected = vec![0x00, 0x04, b'M', b'Q', b'T', b'T', 4, 0xC2, 0x00, 60];
+        expected.extend_from_slice(&[0x00, 0x02, b'k', b'b']);
+        expected.extend_from_slice(&[0x00, 0x04, b'u', b's', b'e', b'r']);
+        expected.extend_from_slice(&[0x00, 0x02, b'p', b'w']);
+        assert_eq!(encoded[0], CONNECT);
+        assert_eq!(encoded[1] as usize, expected.len());
+        assert_eq!(encoded[2..], expected[..]);
+    }
+
+    #[test]
+    fn subscribe_packet_layout() {
+        let encoded = subscribe_packet("kb/set");
+        assert_eq!(
+            encoded,
+            [SUBSCRIBE, 11, 0x00, 0x01, 0x00, 0x06, b'k', b'b', b'/', b's', b'e', b't', 0x00]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! One-shot mode: capture the screen once, set the LEDs and exit.
+//!
+//! `run_once` does the same device setup as the sync loop (discovery and custom mode), then
+//! captures a single frame and transitions the devices from the colors they currently show
+//! to the frame's colors. With `transition_steps = 1` the LEDs snap to the new colors.
+
+use std::sync::atomic::AtomicBool;
+
+use openrgb::data::Color;
+use tracing::info;
+
+use crate::capture::FrameSource;
+use crate::color_utils::color_to_hex;
+use crate::config::Config;
+use crate::error::KeyBloomError;
+use crate::output::OutputStage;
+use crate::sync_loop::*;
+
+/// Capture one frame and apply its colors to the configured devices.
+///
+/// The configured `mode` is ignored: the screen is always captured. With `simulate` the
+/// colors are printed instead of being sent. Returns the colors that were applied.
+pub async fn run_once(config: &Config) -> Result<Vec<Color>, KeyBloomError> {
+    config.validate().map_err(KeyBloomError::Config)?;
+
+    if config.simulate {
+        let frame = FrameSource::open(config)?.capture()?;
+        let colors = compute_segment_colors(&frame, config)?;
+        let hex: Vec<String> = colors.iter().map(|&c| color_to_hex(c)).collect();
+        println!("{}", hex.join(" "));
+        return Ok(colors);
+    }
+
+    let output = OutputStage::new(config)?;
+    let stop_signal = AtomicBool::new(false);
+    let client = match connect_with_wait(config, &stop_signal).await {
+        Ok(Some(client)) => client,
+        Ok(None) => unreachable!("a one-shot run is never stopped while connecting"),
+        Err(err) => return Err(err),
+    };
+    register_client(&client, config).await?;
+    let devices = resolve_devices(&client, config).await?;
+    let max_leds = devices.iter().map(|d| d.num_leds).max().unwrap_or(0);
+
+    let mut source = FrameSource::open(config)?;
+    info!("Applying one frame of {}.", source.describe());
+    let frame = prepare_frame(source.capture()?, config, None);
+    let target = ColorPipeline::new(config, max_leds)?.colors(&frame, config, None);
+
+    // Start the transition from what the primary device shows right now
+    let shown = match devices.first() {
+        Some(device) => client.get_controller(device.id).await?.colors,
+        None => Vec::new(),
+    };
+    let mut current = resample_colors(&shown, target.len());
+    if current.len() != target.len() {
+        current = target.clone();
+    }
+    let mut step_buffer = current.clone();
+    let sink = FrameSink {
+        client: &client,
+        devices: &devices,
+        output: &output,
+    };
+    smooth_transition(&sink, &mut current, &target, config, &mut step_buffer, &stop_signal)
+        .await?;
+    Ok(target)
+}
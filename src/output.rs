@@ -0,0 +1,89 @@
+//! Output-stage corrections applied to colors just before they are sent to the device.
+//!
+//! The sync loop keeps working with the uncorrected colors for transitions and the TUI
+//! preview; `OutputStage` only changes what the hardware receives. It also owns the
+//! optional color log, so the log matches exactly what was sent.
+
+use crate::color_log::ColorLog;
+use crate::color_utils::*;
+use crate::config::Config;
+use crate::error::KeyBloomError;
+use crate::lut::Lut3d;
+use openrgb::data::Color;
+use tracing::warn;
+
+/// Device calibration built once from the configuration when syncing starts.
+pub struct OutputStage {
+    /// Optional calibration LUT loaded from `Config::lut_path`.
+    lut: Option<Lut3d>,
+    /// Per-channel output gamma.
+    gamma: [f32; 3],
+    /// Per-channel gain and offset from `Config::channel_gain` and `Config::channel_offset`.
+    channel_gain: [f32; 3],
+    channel_offset: [i16; 3],
+    /// Color temperature near-white LEDs are pulled towards, from `Config::desaturate_whites`.
+    desaturate_whites: Option<u16>,
+    /// Highest value any channel is sent at, from `Config::max_channel`.
+    max_channel: u8,
+    /// Optional log of every frame sent, from `Config::color_log`.
+    log: Option<ColorLog>,
+}
+
+impl OutputStage {
+    /// Build the output stage, loading the calibration LUT and opening the color log
+    /// if they are configured.
+    pub fn new(config: &Config) -> Result<Self, KeyBloomError> {
+        let lut = match &config.lut_path {
+            Some(path) => Some(Lut3d::load(path)?),
+            None => None,
+        };
+        let log = match &config.color_log {
+            Some(path) => Some(ColorLog::open(
+                path,
+                config.color_log_format,
+                config.color_log_flush,
+            )?),
+            None => None,
+        };
+        Ok(Self {
+            lut,
+            gamma: [config.gamma_r, config.gamma_g, config.gamma_b],
+            channel_gain: config.channel_gain,
+            channel_offset: config.channel_offset,
+            desaturate_whites: config.desaturate_whites,
+            max_channel: config.max_channel,
+            log,
+        })
+    }
+
+    /// Record the colors sent to `device` in the color log, if one is configured.
+    ///
+    /// Logging failures are reported but never interrupt syncing.
+    pub fn log_sent(&self, device: &str, colors: &[Color]) {
+        if let Some(log) = &self.log {
+            if let Err(e) = log.record(device, colors) {
+                warn!("Failed to write color log: {e}");
+            }
+        }
+    }
+
+    /// Apply the output corrections to a frame of LED colors.
+    pub fn apply(&self, colors: &[Color]) -> Vec<Color> {
+        colors.iter().map(|&color| self.apply_one(color)).collect()
+    }
+
+    /// Apply the output corrections to a single LED color.
+    fn apply_one(&self, color: Color) -> Color {
+        let color = match &self.lut {
+            Some(lut) => srgb_to_color(lut.apply(color_to_srgb(color))),
+            None => color,
+        };
+        let color = match self.desaturate_whites {
+            Some(kelvin) => warm_whites(color, kelvin),
+            None => color,
+        };
+        let color = apply_channel_gamma(color, self.gamma);
+        let color = apply_channel_calibration(color, self.channel_gain, self.channel_offset);
+        clamp_max_channel(color, self.max_channel)
+    }
+}
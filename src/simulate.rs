@@ -0,0 +1,85 @@
+//! Simulation mode: run the color pipeline without any OpenRGB device.
+//!
+//! Frames come from the configured capture target or, on machines without a display, from a fixed
+//! test image. The computed segment colors are printed to stdout instead of being sent to
+//! a device, which makes the pipeline easy to try out before wiring up hardware.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use crate::capture::FrameSource;
+use crate::color_utils::color_to_hex;
+use crate::config::Config;
+use crate::error::KeyBloomError;
+use crate::idle::{IdlePause, IDLE_POLL_INTERVAL};
+use crate::sync_loop::{
+    lock_or_recover, prepare_frame, sleep_unless_stopped, ColorPipeline, SyncState, SyncStatus,
+};
+
+/// Run the capture and color pipeline, printing each frame's segment colors.
+///
+/// Transitions and debouncing only shape how colors are sent to a device, so the printed
+/// colors are the per-frame targets. Runs until the stop signal is raised.
+pub async fn run_simulation(
+    config: &Config,
+    sync_status: Arc<Mutex<SyncStatus>>,
+    stop_signal: Arc<AtomicBool>,
+) -> Result<(), KeyBloomError> {
+    let mut source = FrameSource::open(config)?;
+    let max_leds = config.devices.iter().map(|d| d.num_leds).max().unwrap_or(0);
+    let pipeline = ColorPipeline::new(config, max_leds)?;
+    let frame_delay = config.frame_interval();
+    let mut idle = config
+        .idle_pause_secs
+        .map(|secs| IdlePause::new(Duration::from_secs(secs)));
+
+    info!(
+        "Simulating {} segments; no OpenRGB device is used.",
+        pipeline.segment_count
+    );
+    lock_or_recover(&sync_status).state = SyncState::Running;
+
+    while !stop_signal.load(Ordering::Relaxed) {
+        let loop_start = Instant::now();
+        let frame = match source.capture() {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Capture error: {e}");
+                if !sleep_unless_stopped(frame_delay, &stop_signal).await {
+                    break;
+                }
+                continue;
+            }
+        };
+        if let Some(idle) = idle.as_mut() {
+            idle.observe(0, &frame);
+            if idle.is_paused() {
+                if !sleep_unless_stopped(IDLE_POLL_INTERVAL, &stop_signal).await {
+                    break;
+                }
+                continue;
+            }
+        }
+        // Same as compute_segment_colors, without reloading the layout file every frame
+        let frame = prepare_frame(frame, config, None);
+        let colors = if frame.width() == 0 || frame.height() == 0 {
+            Vec::new()
+        } else {
+            pipeline.colors(&frame, config, None)
+        };
+        let hex: Vec<String> = colors.iter().map(|&c| color_to_hex(c)).collect();
+        println!("{}", hex.join(" "));
+        lock_or_recover(&sync_status).update(colors);
+
+        let remaining = frame_delay.saturating_sub(loop_start.elapsed());
+        if !sleep_unless_stopped(remaining, &stop_signal).await {
+            break;
+        }
+    }
+
+    lock_or_recover(&sync_status).state = SyncState::Stopped;
+    Ok(())
+}
@@ -3,42 +3,154 @@
 //! The `start_sync_loop` function handles the following:
 //! 1. Connect to the OpenRGB server.
 //! 2. Identify the chosen device (keyboard, etc.).
-//! 3. Capture the screen from the selected monitor.
+//! 3. Capture the selected monitor (or window).
 //! 4. Compute average colors across screen segments.
 //! 5. Transition the keyboard LEDs smoothly to those colors.
 //!
 //! The loop continues until aborted from outside (e.g., by calling `handle.abort()`).
 
 use crate::color_utils::*;
-use crate::config::Config;
+use crate::config::{
+    Accumulator, AspectMode, CaptureTarget, ColorExtraction, Config, LedEdges, Region,
+    SaturationMode, SegmentationMode, SyncMode,
+};
+use crate::effects;
+use crate::capture::{self, FrameSource};
+use crate::extract::dominant_segment_colors;
+use crate::idle::{IdlePause, IDLE_POLL_INTERVAL};
+use crate::led_layout::LedLayout;
+use crate::letterbox::LetterboxCropper;
+use crate::error::KeyBloomError;
+use crate::output::OutputStage;
+use crate::simulate::run_simulation;
+use crate::wallpaper;
+use crate::white_point::WhitePointEstimator;
+use image::imageops::FilterType;
 use image::RgbaImage;
 use openrgb::{data::Color, OpenRGB, OpenRGBError};
-use palette::Srgb;
+use palette::{FromColor, Hsv, Srgb};
 use rayon::prelude::*; // For parallel iterators
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::time::sleep;
+use tracing::{error, info, warn};
 use xcap::Monitor;
-use std::sync::{Mutex, atomic::{AtomicBool, Ordering}};
+use std::sync::{Mutex, MutexGuard, atomic::{AtomicBool, Ordering}};
 
-// Define a new error type that implements Send + Sync + 'static
-type AnyError = Box<dyn std::error::Error + Send + Sync + 'static>;
+/// Lifecycle state of the sync loop, as shown on the sync screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncState {
+    /// Connecting to OpenRGB and discovering devices.
+    #[default]
+    Starting,
+    /// Capturing and updating the devices.
+    Running,
+    /// The OpenRGB connection dropped and is being re-established.
+    Reconnecting,
+    /// The loop has exited.
+    Stopped,
+    /// The loop has exited because the OpenRGB server didn't answer in time.
+    TimedOut,
+}
 
 /// Represents the synchronization status shared between the sync loop and the UI.
 #[derive(Default)]
 pub struct SyncStatus {
-    pub is_running: bool,
+    pub state: SyncState,
     pub current_colors: Vec<Color>,
     pub frame_count: usize,
     pub last_update: Option<Instant>,
+    /// Rolling average of captured frames per second.
+    pub fps: f32,
+    /// The frame rate asked for with `target_fps`, to compare `fps` against.
+    pub target_fps: Option<u32>,
+    /// Rolling average time (ms) spent grabbing a frame.
+    pub avg_capture_ms: f32,
+    /// Rolling average time (ms) spent turning a frame into target colors.
+    pub avg_process_ms: f32,
+    /// Why the capture differs from what was configured (e.g. a missing window), if it does.
+    pub capture_notice: Option<String>,
+    /// Why the last run stopped with an error, e.g. a device that wasn't found.
+    pub last_error: Option<String>,
+}
+
+/// Lock `mutex`, recovering its data if a thread panicked while holding it.
+///
+/// The shared state here is plain data that stays usable after a panic elsewhere, so one
+/// failing thread shouldn't take down every other thread that reads it. The poison is
+/// cleared after recovering, so each panic is only reported once.
+pub fn lock_or_recover<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        warn!("A thread panicked while holding a lock; continuing with its last state.");
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// The rayon pool shared by every running sync loop, with the thread count it was built for.
+static COMPUTE_POOL: Mutex<Option<(usize, Arc<rayon::ThreadPool>)>> = Mutex::new(None);
+
+/// The shared pool for color averaging with `threads` threads, or `None` for 0 (use the
+/// global pool).
+///
+/// Sync loops running at the same time share one pool, so `compute_threads` bounds the
+/// CPU spent on averaging overall rather than per loop. Asking for a different size
+/// replaces the pool for later callers; loops still holding the old one keep using it.
+pub fn shared_compute_pool(
+    threads: usize,
+) -> Result<Option<Arc<rayon::ThreadPool>>, KeyBloomError> {
+    if threads == 0 {
+        return Ok(None);
+    }
+    let mut shared = lock_or_recover(&COMPUTE_POOL);
+    if let Some((size, pool)) = shared.as_ref() {
+        if *size == threads {
+            return Ok(Some(Arc::clone(pool)));
+        }
+    }
+    let pool = Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("keybloom-compute-{i}"))
+            .build()?,
+    );
+    *shared = Some((threads, Arc::clone(&pool)));
+    Ok(Some(pool))
 }
 
+/// Weight of the newest sample in the rolling timing averages.
+const TIMING_SMOOTHING: f32 = 0.1;
+
 impl SyncStatus {
     pub fn update(&mut self, colors: Vec<Color>) {
         self.current_colors = colors;
         self.frame_count += 1;
         self.last_update = Some(Instant::now());
     }
+
+    /// Fold one frame's timings into the rolling averages.
+    ///
+    /// `frame_interval` is the time since the previous frame started, if there was one.
+    pub fn record_timing(
+        &mut self,
+        frame_interval: Option<Duration>,
+        capture: Duration,
+        process: Duration,
+    ) {
+        let blend = |average: &mut f32, sample: f32| {
+            *average = if *average == 0.0 {
+                sample
+            } else {
+                *average + TIMING_SMOOTHING * (sample - *average)
+            };
+        };
+        if let Some(interval) = frame_interval.filter(|i| !i.is_zero()) {
+            blend(&mut self.fps, 1.0 / interval.as_secs_f32());
+        }
+        blend(&mut self.avg_capture_ms, capture.as_secs_f32() * 1000.0);
+        blend(&mut self.avg_process_ms, process.as_secs_f32() * 1000.0);
+    }
 }
 
 /// The main synchronization loop.
@@ -47,251 +159,2895 @@ impl SyncStatus {
 /// selects the desired monitor for screen capture, and continuously updates the device LEDs
 /// based on the average color of different vertical segments of the screen.
 ///
-/// It runs until externally aborted (e.g., via `handle.abort()`).
+/// It runs until externally aborted (e.g., via `handle.abort()`). If it fails, the error is
+/// also left in `SyncStatus::last_error` for the UI to show.
 pub async fn start_sync_loop(
     config: &Config,
     sync_status: Arc<Mutex<SyncStatus>>,
     stop_signal: Arc<AtomicBool>, // NEW
-) -> Result<(), AnyError> {
-    // 1) Connect to OpenRGB
-    let client = match OpenRGB::connect_to((&config.openrgb_host[..], config.openrgb_port)).await {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("Failed to connect to OpenRGB server: {e}");
-            return Ok(()); // Gracefully return
+) -> Result<(), KeyBloomError> {
+    let result = run_sync_loop(config, Arc::clone(&sync_status), stop_signal).await;
+    if let Err(err) = &result {
+        let mut status = lock_or_recover(&sync_status);
+        status.last_error = Some(err.to_string());
+        if status.state != SyncState::TimedOut {
+            status.state = SyncState::Stopped;
         }
+    }
+    result
+}
+
+/// The body of `start_sync_loop`.
+async fn run_sync_loop(
+    config: &Config,
+    sync_status: Arc<Mutex<SyncStatus>>,
+    stop_signal: Arc<AtomicBool>,
+) -> Result<(), KeyBloomError> {
+    {
+        let mut status = lock_or_recover(&sync_status);
+        status.last_error = None;
+    }
+    config.validate().map_err(KeyBloomError::Config)?;
+    {
+        let mut status = lock_or_recover(&sync_status);
+        status.state = SyncState::Starting;
+        status.target_fps = config.target_fps;
+    }
+
+    // Simulation runs the color pipeline without touching OpenRGB
+    if config.simulate {
+        return run_simulation(config, sync_status, stop_signal).await;
+    }
+
+    // Load output calibration up front so a bad LUT file fails before connecting
+    let output = OutputStage::new(config)?;
+
+    // Give OpenRGB a head start when launched at login
+    if !sleep_unless_stopped(Duration::from_millis(config.startup_delay_ms), &stop_signal).await {
+        return Ok(());
+    }
+
+    // 1) Connect to OpenRGB, waiting for the server to come up if configured
+    let mut client = match connect_with_wait(config, &stop_signal).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return Ok(()), // Stopped while waiting
+        Err(err) => return Err(report_timeout(err, &sync_status)),
     };
-    client.set_name("KeyBloom".to_string()).await?;
+    register_client(&client, config)
+        .await
+        .map_err(|err| report_timeout(err, &sync_status))?;
 
-    // 2) Find the specified device
-    let controller_count = client.get_controller_count().await?;
-    let mut keyboard_id: Option<u32> = None;
-    for i in 0..controller_count {
-        if let Ok(ctrl) = client.get_controller(i).await {
-            // You can refine this matching logic if needed
-            if ctrl.name.contains(&config.device_name)
-                || ctrl.name.to_lowercase().contains("keyboard")
-            {
-                keyboard_id = Some(i);
-                break;
-            }
+    // 2) Find the configured devices and put them into custom mode
+    let mut devices = resolve_devices(&client, config)
+        .await
+        .map_err(|err| report_timeout(err, &sync_status))?;
+
+    // The capture is averaged once at the finest device resolution and mapped onto each device
+    let max_leds = devices.iter().map(|d| d.num_leds).max().unwrap_or(0);
+
+    // Static, wallpaper and off modes don't need the screen at all
+    match config.mode {
+        SyncMode::ScreenSync => {}
+        SyncMode::Static { color: [r, g, b] } => {
+            let colors = vec![Color { r, g, b }; max_leds];
+            let sink = FrameSink {
+                client: &client,
+                devices: &devices,
+                output: &output,
+            };
+            info!("Showing a static color until stopped.");
+            return hold_colors(&sink, colors, &sync_status, &stop_signal).await;
         }
-    }
-    let kb_id = match keyboard_id {
-        Some(id) => id,
-        None => {
-            eprintln!(
-                "No device named '{}' found. Check your OpenRGB server.",
-                config.device_name
-            );
-            return Ok(()); // Gracefully return
+        SyncMode::Wallpaper => {
+            // The wallpaper goes through the same pipeline as a captured frame, once
+            let frame = prepare_frame(wallpaper::load(config)?, config, None);
+            let colors = ColorPipeline::new(config, max_leds)?.colors(&frame, config, None);
+            let sink = FrameSink {
+                client: &client,
+                devices: &devices,
+                output: &output,
+            };
+            info!("Showing the wallpaper colors until stopped.");
+            return hold_colors(&sink, colors, &sync_status, &stop_signal).await;
+        }
+        SyncMode::Off => {
+            let colors = vec![Color { r: 0, g: 0, b: 0 }; max_leds];
+            write_frame(&client, &devices, &output, &colors, None).await?;
+            lock_or_recover(&sync_status).state = SyncState::Stopped;
+            info!("LEDs turned off.");
+            return Ok(());
+        }
+        SyncMode::Rainbow { .. } | SyncMode::Breathing { .. } => {
+            return run_effect(config, client, devices, &output, sync_status, stop_signal).await;
+        }
+        SyncMode::External => {
+            return run_external(config, client, devices, &output, sync_status, stop_signal)
+                .await;
         }
+    }
+    let new_letterbox = || {
+        config.crop_letterbox.then(|| {
+            LetterboxCropper::new(
+                config.letterbox_black_level,
+                Duration::from_millis(config.letterbox_detect_interval_ms),
+            )
+        })
     };
 
-    // Attempt to set custom mode (if supported)
-    if let Err(e) = client.set_custom_mode(kb_id).await {
-        eprintln!("Could not set custom mode on device: {e}");
-    }
+    // 3) Select the mapped monitors, or the monitor (and window, if configured) to capture
+    capture::check_backend(config);
+    let mut slots = mapped_slots(config, max_leds, &new_letterbox);
+    let led_count = if slots.is_empty() {
+        let Some(monitor) = wait_for_monitor(config, &stop_signal).await? else {
+            lock_or_recover(&sync_status).state = SyncState::Stopped;
+            return Ok(());
+        };
+        // Check the capture region against the monitor once, warning about anything
+        // clipped. A window's size is only known per frame, so its region is clamped there.
+        if let (Some(region), CaptureTarget::Monitor) =
+            (config.capture_region, &config.capture_target)
+        {
+            warn_if_region_clipped(region, &monitor);
+        }
+        let pipeline = ColorPipeline::new(config, max_leds)?;
+        let led_count = pipeline.led_count;
+        slots.push(CaptureSlot {
+            source: FrameSource::for_target(config, monitor),
+            first_led: 0,
+            pipeline,
+            letterbox: new_letterbox(),
+        });
+        led_count
+    } else {
+        max_leds
+    };
 
-    // 3) Select monitor for screen capture
-    let monitors = Monitor::all().map_err(|e| format!("xcap error: {e}"))?;
-    let monitor = monitors
-        .get(config.monitor_index)
-        .unwrap_or_else(|| &monitors[0])
-        .clone();
+    // Pick the sample step for the largest captured monitor once, up front
+    let adapted_config;
+    let config = if config.adaptive_sampling && config.downscale_to.is_none() {
+        let (width, height) = slots
+            .iter()
+            .map(|slot| slot.source.dimensions())
+            .max_by_key(|&(width, height)| width as u64 * height as u64)
+            .unwrap_or((0, 0));
+        let sample_step = Config::adaptive_sample_step(width, height);
+        info!("Adaptive sampling: using a sample step of {sample_step} for {width}x{height}.");
+        adapted_config = Config {
+            sample_step,
+            ..config.clone()
+        };
+        &adapted_config
+    } else {
+        config
+    };
 
-    println!(
-        "\nSync started on monitor: {} ({}x{}), device: {}.\n",
-        monitor.name(),
-        monitor.width(),
-        monitor.height(),
-        config.device_name
+    let device_names: Vec<&str> = devices.iter().map(|d| d.name.as_str()).collect();
+    let sources: Vec<String> = slots.iter().map(|slot| slot.source.describe()).collect();
+    info!(
+        "Sync started on {}, devices: {}.",
+        sources.join(" and "),
+        device_names.join(", ")
     );
 
-    let mut current_colors = vec![Color { r: 0, g: 0, b: 0 }; config.num_leds];
+    lock_or_recover(&sync_status).state = SyncState::Running;
+
+    let mut leds = LedBuffers::new(led_count);
     let mut last_transition = Instant::now();
-    let mut step_buffer = vec![Color { r: 0, g: 0, b: 0 }; config.num_leds];
-    let color_threshold_sq = (config.color_change_threshold * 255.0).powi(2);
-    let width = monitor.width() as usize;
-    let height = monitor.height() as usize;
+    let mut white_point = config.auto_white_point.then(|| {
+        WhitePointEstimator::new(Duration::from_millis(config.white_point_time_constant_ms))
+    });
+    let mut previous_frame_start: Option<Instant> = None;
+    let mut debounce_targets = TargetAverager::default();
+    let mut smoother = TargetSmoother::new(config.smoothing_alpha);
+
+    // For efficiency, we skip (x, y) coordinates by config.sample_step (unless downscaled)
+    let sampling_step = config.sampling_step();
 
-    // For efficiency, we skip (x, y) coordinates by config.sample_step
-    let sampling_step = config.sample_step.max(1);
+    // A dedicated rayon pool bounds the CPU spent on averaging; otherwise use the global pool
+    let compute_pool = shared_compute_pool(config.compute_threads)?;
 
-    // Pre-allocate space for summation
-    let mut sums_accum = vec![(0u64, 0u64, 0u64, 0u64); config.num_leds];
+    let slot_count = slots.len() as u32;
+    let mut write_failures = 0;
+    let mut idle = config
+        .idle_pause_secs
+        .map(|secs| IdlePause::new(Duration::from_secs(secs)));
 
     // 4) Capture-and-update loop (runs until aborted)
     while !stop_signal.load(Ordering::Relaxed) { // MODIFIED
-        // Capture screen
+        // Capture every slot; a slot that fails keeps its LEDs at their current colors
         let loop_start = Instant::now();
-        let frame: RgbaImage = match monitor.capture_image() {
-            Ok(img) => img,
-            Err(e) => {
-                eprintln!("Capture error: {e}");
-                sleep(Duration::from_millis(config.frame_delay_ms)).await;
+        let frame_interval = previous_frame_start.map(|t| loop_start - t);
+        let frame_elapsed = frame_interval.unwrap_or(config.frame_interval());
+        let mut capture_time = Duration::ZERO;
+        let mut target_colors = leds.current.clone();
+        let mut captured_any = false;
+        for (index, slot) in slots.iter_mut().enumerate() {
+            let capture_start = Instant::now();
+            let captured = slot.source.capture();
+            capture_time += capture_start.elapsed();
+            let frame: RgbaImage = match captured {
+                Ok(img) => img,
+                Err(e) => {
+                    warn!("Capture error: {e}");
+                    if !matches!(slot.source, FrameSource::Image(_)) {
+                        if let Some(fallback) = capture::fallback_source(config) {
+                            slot.source = fallback;
+                        }
+                    }
+                    continue;
+                }
+            };
+            // While the screen is static, only look for a change; skip all processing
+            if let Some(idle) = idle.as_mut() {
+                idle.observe(index, &frame);
+                if idle.is_paused() {
+                    continue;
+                }
+            }
+            let frame = prepare_frame(frame, config, slot.letterbox.as_mut());
+            if frame.width() == 0 || frame.height() == 0 {
                 continue;
             }
-        };
 
-        // Reset accumulations
-        for accum in &mut sums_accum {
-            *accum = (0, 0, 0, 0);
-        }
-
-        // Compute average color in parallel
-        let final_sums = (0..height)
-            .into_par_iter()
-            .step_by(sampling_step)
-            .map(|row| {
-                let row_start = row * width * 4;
-                let row_slice = &frame.as_raw()[row_start..(row_start + width * 4)];
-
-                // Local partial sums for this row
-                let mut row_sums = vec![(0u64, 0u64, 0u64, 0u64); config.num_leds];
-
-                for x in (0..width).step_by(sampling_step) {
-                    let idx = x * 4;
-                    let r = row_slice[idx] as u64;
-                    let g = row_slice[idx + 1] as u64;
-                    let b = row_slice[idx + 2] as u64;
-                    let a = row_slice[idx + 3] as f32 / 255.0;
-
-                    if a >= 0.1 {
-                        let col_idx = (x * config.num_leds) / width;
-                        let (rr, gg, bb, count) = &mut row_sums[col_idx];
-                        *rr += r;
-                        *gg += g;
-                        *bb += b;
-                        *count += 1;
-                    }
-                }
-                row_sums
-            })
-            .reduce(
-                || vec![(0u64, 0u64, 0u64, 0u64); config.num_leds],
-                |mut acc, row_sums| {
-                    for (i, (r, g, b, c)) in row_sums.into_iter().enumerate() {
-                        let (rr, gg, bb, cc) = &mut acc[i];
-                        *rr += r;
-                        *gg += g;
-                        *bb += b;
-                        *cc += c;
-                    }
-                    acc
-                },
-            );
+            if let Some(estimator) = white_point.as_mut() {
+                // Every monitor contributes to the same estimate at the same overall rate
+                estimator.observe(&frame, sampling_step, frame_elapsed / slot_count);
+            }
 
-        sums_accum.copy_from_slice(&final_sums);
-
-        let target_srgb: Vec<Srgb<f32>> = sums_accum
-            .par_iter()
-            .map(|&(r_sum, g_sum, b_sum, count)| {
-                if count == 0 {
-                    Srgb::new(0.0, 0.0, 0.0)
-                } else {
-                    let count_f = count as f32;
-                    let r_f = (r_sum as f32 / count_f) / 255.0;
-                    let g_f = (g_sum as f32 / count_f) / 255.0;
-                    let b_f = (b_sum as f32 / count_f) / 255.0;
-                    let avg = Srgb::new(r_f, g_f, b_f);
-                    let bright = increase_brightness(avg, config.brightness_factor);
-                    adjust_saturation(bright, config.saturation_factor)
-                }
-            })
-            .collect();
+            // Compute segment colors in parallel, on the dedicated pool if one is configured
+            let compute = || slot.pipeline.colors(&frame, config, white_point.as_ref());
+            let colors = match &compute_pool {
+                Some(pool) => pool.install(compute),
+                None => compute(),
+            };
+            if let Some(range) = target_colors.get_mut(slot.first_led..) {
+                let filled = colors.len().min(range.len());
+                range[..filled].copy_from_slice(&colors[..filled]);
+            }
+            captured_any = true;
+        }
+        if idle.as_mut().is_some_and(|idle| idle.is_paused()) {
+            lock_or_recover(&sync_status).capture_notice =
+                Some("Paused: the screen hasn't changed for a while.".to_string());
+            previous_frame_start = None;
+            if !sleep_unless_stopped(IDLE_POLL_INTERVAL, &stop_signal).await {
+                break;
+            }
+            continue;
+        }
+        lock_or_recover(&sync_status).capture_notice =
+            slots.iter().find_map(|slot| slot.source.notice());
+        if !captured_any {
+            sleep(config.frame_interval()).await;
+            continue;
+        }
+        previous_frame_start = Some(loop_start);
 
-        let target_colors: Vec<Color> = target_srgb.iter().map(|&c| srgb_to_color(c)).collect();
+        // Damp frame-to-frame flashes before deciding whether to transition
+        let target_colors = smoother.apply(target_colors);
 
         {
-            let mut status = sync_status.lock().unwrap();
-            status.update(current_colors.clone());
+            let mut status = lock_or_recover(&sync_status);
+            status.update(leds.current.clone());
+            let process_time = loop_start.elapsed().saturating_sub(capture_time);
+            status.record_timing(frame_interval, capture_time, process_time);
         }
 
         // Check if color changed significantly
-        let significant_change = current_colors
+        let max_dist = leds
+            .current
             .iter()
             .zip(&target_colors)
-            .any(|(curr, targ)| {
-                let dr = targ.r as f32 - curr.r as f32;
-                let dg = targ.g as f32 - curr.g as f32;
-                let db = targ.b as f32 - curr.b as f32;
-                let dist_sq = dr * dr + dg * dg + db * db;
-                dist_sq > color_threshold_sq
-            });
+            .map(|(&curr, &targ)| color_distance(config.threshold_metric, curr, targ))
+            .fold(0.0f32, f32::max);
+        let significant_change = max_dist > config.color_change_threshold;
 
-        let debounce_passed =
-            last_transition.elapsed() >= Duration::from_millis(config.debounce_duration_ms);
+        let scene_cut = is_scene_cut(max_dist, config);
+
+        // Optionally remember the targets seen while the debounce holds us back, so the
+        // eventual transition goes to their average instead of a possibly transient frame
+        if config.average_debounce_targets && significant_change {
+            debounce_targets.add(&target_colors);
+        }
 
-        if significant_change && debounce_passed {
-            if let Err(e) = smooth_transition(
-                &client,
-                kb_id,
-                &mut current_colors,
+        // The breathing frame written this iteration, if any, which is what the LEDs show
+        let mut breathing = None;
+        let update = if transition_allowed(max_dist, last_transition.elapsed(), config) {
+            let averaged = debounce_targets.take_average();
+            let target_colors = match averaged {
+                Some(average) if !scene_cut => average,
+                _ => target_colors,
+            };
+            let sink = FrameSink {
+                client: &client,
+                devices: &devices,
+                output: &output,
+            };
+            let result = smooth_transition(
+                &sink,
+                &mut leds.current,
                 &target_colors,
                 config,
-                &mut step_buffer,
+                &mut leds.step,
+                &stop_signal,
             )
-            .await
-            {
-                eprintln!("Error updating keyboard LEDs: {e}");
-            }
+            .await;
             last_transition = Instant::now();
+            Some(result)
+        } else if let Some(frame) = idle_frame(&leds.current, last_transition.elapsed(), config)
+        {
+            let result = write_frame(&client, &devices, &output, &frame, None).await;
+            breathing = result.is_ok().then_some(frame);
+            Some(result)
+        } else {
+            None
+        };
+
+        match update {
+            Some(Ok(())) => write_failures = 0,
+            Some(Err(e)) => {
+                error!("Error updating keyboard LEDs: {e}");
+                write_failures += 1;
+                // Repeated failures are treated like a lost connection
+                if is_connection_error(&e) || write_failures >= config.max_write_failures {
+                    write_failures = 0;
+                    // Wait for the server to come back, then rediscover the devices
+                    match reconnect(config, &sync_status, &stop_signal).await {
+                        Some((new_client, new_devices)) => {
+                            client = new_client;
+                            devices = new_devices;
+                            // The devices may report a different LED count after reconnecting
+                            let max_leds = devices.iter().map(|d| d.num_leds).max().unwrap_or(0);
+                            let led_count = if config.monitor_mappings.is_empty() {
+                                let pipeline = ColorPipeline::new(config, max_leds)?;
+                                let led_count = pipeline.led_count;
+                                if let Some(slot) = slots.first_mut() {
+                                    slot.pipeline = pipeline;
+                                }
+                                led_count
+                            } else {
+                                max_leds
+                            };
+                            if leds.resize(led_count) {
+                                debounce_targets = TargetAverager::default();
+                            }
+                        }
+                        None => break, // Stopped while reconnecting
+                    }
+                }
+            }
+            None => {}
         }
 
         {
-            let mut status = sync_status.lock().unwrap();
-            status.update(current_colors.clone());
+            let mut status = lock_or_recover(&sync_status);
+            status.update(breathing.unwrap_or_else(|| leds.current.clone()));
         }
 
         let elapsed = loop_start.elapsed();
-        if let Some(remaining) = Duration::from_millis(config.frame_delay_ms).checked_sub(elapsed) {
+        if let Some(remaining) = config.frame_interval().checked_sub(elapsed) {
             sleep(remaining).await;
         }
     }
 
-    println!("Sync loop asked to stop. Exiting normally...");
+    lock_or_recover(&sync_status).state = SyncState::Stopped;
+    info!("Sync loop asked to stop. Exiting normally...");
     Ok(())
 }
 
-/// Smoothly transition `current` colors to `target` colors using HSV interpolation.
+/// Send `colors` once, then keep them shown until stopped.
+async fn hold_colors(
+    sink: &FrameSink<'_>,
+    colors: Vec<Color>,
+    sync_status: &Mutex<SyncStatus>,
+    stop_signal: &AtomicBool,
+) -> Result<(), KeyBloomError> {
+    write_frame(sink.client, sink.devices, sink.output, &colors, None).await?;
+    {
+        let mut status = lock_or_recover(sync_status);
+        status.state = SyncState::Running;
+        status.update(colors);
+    }
+    while sleep_unless_stopped(Duration::from_secs(1), stop_signal).await {}
+    lock_or_recover(sync_status).state = SyncState::Stopped;
+    Ok(())
+}
+
+/// Warn if `region` doesn't fit on `monitor` and will be clamped or ignored.
+fn warn_if_region_clipped(region: Region, monitor: &Monitor) {
+    match region.clamped(monitor.width(), monitor.height()) {
+        Some(clamped) if clamped != region => warn!(
+            "Capture region exceeds the {}x{} monitor; clamped to {}x{} at {},{}.",
+            monitor.width(),
+            monitor.height(),
+            clamped.width,
+            clamped.height,
+            clamped.x,
+            clamped.y
+        ),
+        Some(_) => {}
+        None => warn!("Capture region lies outside the monitor; using the whole screen."),
+    }
+}
+
+/// One captured screen and the LEDs its colors fill.
+struct CaptureSlot {
+    source: FrameSource,
+    /// First LED filled from this source.
+    first_led: usize,
+    pipeline: ColorPipeline,
+    letterbox: Option<LetterboxCropper>,
+}
+
+/// Open one capture slot per `monitor_mappings` entry whose monitor is connected.
 ///
-/// # Arguments
+/// Monitors are looked up once, so the handles are reused for every frame. Mappings for
+/// missing monitors are skipped with a warning; their LEDs stay dark.
+fn mapped_slots(
+    config: &Config,
+    max_leds: usize,
+    new_letterbox: &dyn Fn() -> Option<LetterboxCropper>,
+) -> Vec<CaptureSlot> {
+    if config.monitor_mappings.is_empty() {
+        return Vec::new();
+    }
+    let monitors = Monitor::all().unwrap_or_else(|err| {
+        warn!("Failed to list monitors: {err}");
+        Vec::new()
+    });
+    let slots: Vec<CaptureSlot> = config
+        .monitor_mappings
+        .iter()
+        .filter_map(|mapping| {
+            let Some(monitor) = monitors.get(mapping.monitor_index) else {
+                warn!(
+                    "Monitor {} from monitor_mappings is not connected; LEDs {}-{} stay dark.",
+                    mapping.monitor_index,
+                    mapping.first_led,
+                    mapping.first_led + mapping.led_count - 1
+                );
+                return None;
+            };
+            Some(CaptureSlot {
+                source: FrameSource::Monitor(monitor.clone()),
+                first_led: mapping.first_led,
+                // layout_file can't be combined with monitor_mappings
+                pipeline: ColorPipeline::segmented(config, mapping.led_count.min(max_leds)),
+                letterbox: new_letterbox(),
+            })
+        })
+        .collect();
+    if slots.is_empty() {
+        warn!("None of the mapped monitors are connected; capturing monitor_index instead.");
+    }
+    slots
+}
+
+/// Render the configured effect once per `Config::frame_interval` until stopped.
 ///
-/// * `openrgb_client` - A reference to the connected OpenRGB client.
-/// * `controller_id` - The numeric ID of the device being controlled.
-/// * `current` - A mutable reference to the slice of current LED colors.
-/// * `target` - A slice of target LED colors.
-/// * `config` - The application configuration.
-/// * `step_buffer` - A mutable buffer used to store intermediate colors during each step.
-async fn smooth_transition(
-    openrgb_client: &OpenRGB<tokio::net::TcpStream>,
-    controller_id: u32,
-    current: &mut [Color],
-    target: &[Color],
+/// Effects are already smooth from frame to frame, so each frame is written directly
+/// instead of being transitioned to.
+async fn run_effect(
     config: &Config,
-    step_buffer: &mut [Color],
-) -> Result<(), OpenRGBError> {
-    if current.len() != target.len() || current.is_empty() {
-        return Ok(());
+    mut client: OpenRGB<tokio::net::TcpStream>,
+    mut devices: Vec<TargetDevice>,
+    output: &OutputStage,
+    sync_status: Arc<Mutex<SyncStatus>>,
+    stop_signal: Arc<AtomicBool>,
+) -> Result<(), KeyBloomError> {
+    let frame_delay = config.frame_interval();
+    let started = Instant::now();
+    lock_or_recover(&sync_status).state = SyncState::Running;
+    info!("Showing the {:?} effect until stopped.", config.mode);
+
+    while !stop_signal.load(Ordering::Relaxed) {
+        let loop_start = Instant::now();
+        let led_count = devices.iter().map(|d| d.num_leds).max().unwrap_or(0);
+        let seconds = started.elapsed().as_secs_f32();
+        let colors = match config.mode {
+            SyncMode::Rainbow { speed } => effects::rainbow(led_count, speed, seconds),
+            SyncMode::Breathing { color, speed } => {
+                effects::breathing(color, led_count, speed, seconds)
+            }
+            _ => break,
+        };
+
+        if let Err(e) = write_frame(&client, &devices, output, &colors, None).await {
+            error!("Error updating keyboard LEDs: {e}");
+            if is_connection_error(&e) {
+                match reconnect(config, &sync_status, &stop_signal).await {
+                    Some((new_client, new_devices)) => {
+                        client = new_client;
+                        devices = new_devices;
+                    }
+                    None => break,
+                }
+            }
+        }
+        lock_or_recover(&sync_status).update(colors);
+
+        if !sleep_unless_stopped(frame_delay.saturating_sub(loop_start.elapsed()), &stop_signal)
+            .await
+        {
+            break;
+        }
     }
-    let curr_srgb: Vec<Srgb<f32>> = current.iter().map(|&c| color_to_srgb(c)).collect();
-    let targ_srgb: Vec<Srgb<f32>> = target.iter().map(|&c| color_to_srgb(c)).collect();
 
-    for step in 1..=config.transition_steps {
-        let t = step as f32 / config.transition_steps as f32;
+    lock_or_recover(&sync_status).state = SyncState::Stopped;
+    Ok(())
+}
 
-        step_buffer
-            .par_iter_mut()
-            .enumerate()
-            .for_each(|(i, buf)| {
-                let new_color = interpolate_color_hsv(curr_srgb[i], targ_srgb[i], t);
-                *buf = srgb_to_color(new_color);
-            });
+/// Transition to each frame of colors read from stdin until stopped.
+///
+/// Every line is one frame: `num_leds` hex colors separated by spaces or commas. Lines
+/// that don't parse or have the wrong number of colors are skipped with a warning. Once
+/// stdin closes, the last frame stays on the devices until stopped.
+async fn run_external(
+    config: &Config,
+    mut client: OpenRGB<tokio::net::TcpStream>,
+    mut devices: Vec<TargetDevice>,
+    output: &OutputStage,
+    sync_status: Arc<Mutex<SyncStatus>>,
+    stop_signal: Arc<AtomicBool>,
+) -> Result<(), KeyBloomError> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stop_check = tokio::time::interval(Duration::from_millis(100));
+    let mut leds = LedBuffers::new(devices.iter().map(|d| d.num_leds).max().unwrap_or(0));
+    lock_or_recover(&sync_status).state = SyncState::Running;
+    info!("Reading color frames from stdin until stopped.");
+
+    while !stop_signal.load(Ordering::Relaxed) {
+        let line = tokio::select! {
+            line = lines.next_line() => line?,
+            _ = stop_check.tick() => continue,
+        };
+        let Some(line) = line else {
+            info!("Color input closed; holding the last frame until stopped.");
+            while sleep_unless_stopped(Duration::from_secs(1), &stop_signal).await {}
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let target = match parse_color_frame(&line) {
+            Ok(colors) if colors.len() == leds.current.len() => colors,
+            Ok(colors) => {
+                warn!(
+                    "Skipping a frame of {} colors; expected {}.",
+                    colors.len(),
+                    leds.current.len()
+                );
+                continue;
+            }
+            Err(entry) => {
+                warn!("Skipping a frame with the invalid color '{entry}'.");
+                continue;
+            }
+        };
 
-        openrgb_client.update_leds(controller_id, step_buffer.to_vec()).await?;
-        current.copy_from_slice(step_buffer);
-        tokio::time::sleep(Duration::from_millis(config.transition_delay_ms)).await;
+        let sink = FrameSink {
+            client: &client,
+            devices: &devices,
+            output,
+        };
+        let result = smooth_transition(
+            &sink,
+            &mut leds.current,
+            &target,
+            config,
+            &mut leds.step,
+            &stop_signal,
+        )
+        .await;
+        if let Err(e) = result {
+            error!("Error updating keyboard LEDs: {e}");
+            if is_connection_error(&e) {
+                match reconnect(config, &sync_status, &stop_signal).await {
+                    Some((new_client, new_devices)) => {
+                        client = new_client;
+                        devices = new_devices;
+                        leds.resize(devices.iter().map(|d| d.num_leds).max().unwrap_or(0));
+                    }
+                    None => break,
+                }
+            }
+        }
+        lock_or_recover(&sync_status).update(leds.current.clone());
     }
+
+    lock_or_recover(&sync_status).state = SyncState::Stopped;
     Ok(())
 }
+
+/// Crop and scale a captured frame the way the sync loop samples it.
+///
+/// Applies the capture region, the compute resolution cap, letterbox cropping (if a cropper
+/// is given) and the layout aspect, in that order.
+pub fn prepare_frame(
+    frame: RgbaImage,
+    config: &Config,
+    letterbox: Option<&mut LetterboxCropper>,
+) -> RgbaImage {
+    let frame = match config.capture_region {
+        Some(region) => crop_to_region(frame, region),
+        None => frame,
+    };
+    let frame = match config.downscale_to {
+        Some((width, height)) => downscale(frame, width, height),
+        None => {
+            cap_compute_resolution(frame, config.max_compute_width, config.max_compute_height)
+        }
+    };
+    let frame = match letterbox {
+        Some(cropper) => cropper.crop(frame),
+        None => frame,
+    };
+    fit_to_layout(frame, config.aspect_mode, config.layout_aspect)
+}
+
+/// The per-frame "prepared image to LED colors" step, sized for one set of devices.
+pub struct ColorPipeline {
+    /// Number of screen segments sampled.
+    pub segment_count: usize,
+    /// Number of LED colors produced.
+    pub led_count: usize,
+    linear_table: [u16; 256],
+    /// The LEDs' own rectangles from `layout_file`, replacing the segmentation.
+    layout: Option<LedLayout>,
+}
+
+impl ColorPipeline {
+    /// Size the pipeline for devices with at most `max_leds` LEDs, loading `layout_file` if
+    /// one is configured.
+    pub fn new(config: &Config, max_leds: usize) -> Result<Self, KeyBloomError> {
+        let Some(path) = &config.layout_file else {
+            return Ok(Self::segmented(config, max_leds));
+        };
+        Ok(Self {
+            segment_count: max_leds,
+            led_count: max_leds,
+            linear_table: linearization_table(config.gamma),
+            layout: Some(LedLayout::load(path, max_leds)?),
+        })
+    }
+
+    /// Size the pipeline for `max_leds` LEDs using the configured segmentation only.
+    fn segmented(config: &Config, max_leds: usize) -> Self {
+        Self {
+            segment_count: config.sampled_segments(max_leds),
+            led_count: config.output_colors(max_leds),
+            linear_table: linearization_table(config.gamma),
+            layout: None,
+        }
+    }
+
+    /// Sample a prepared frame and turn it into LED colors in physical order.
+    pub fn colors(
+        &self,
+        frame: &RgbaImage,
+        config: &Config,
+        white_point: Option<&WhitePointEstimator>,
+    ) -> Vec<Color> {
+        let layout = self.layout.as_ref();
+        let sums = sample_segments(frame, config, self.segment_count, &self.linear_table, layout);
+        let colors = sums_to_colors(&sums, config, white_point);
+        // A layout file already names the physical LED of every rectangle
+        let colors = if layout.is_some() {
+            colors
+        } else {
+            map_to_leds(colors, config, self.led_count)
+        };
+        smooth_neighbours(&colors, config.spatial_smoothing, config.led_edges)
+    }
+}
+
+/// Compute the LED colors for one captured `frame`, as the sync loop would without any
+/// history (no letterbox detection, white point estimate, smoothing or transitions).
+///
+/// The LED count comes from the configured devices. Frames that crop to nothing yield no
+/// colors.
+pub fn compute_segment_colors(
+    frame: &RgbaImage,
+    config: &Config,
+) -> Result<Vec<Color>, KeyBloomError> {
+    let max_leds = config.devices.iter().map(|d| d.num_leds).max().unwrap_or(0);
+    let pipeline = ColorPipeline::new(config, max_leds)?;
+    let frame = prepare_frame(frame.clone(), config, None);
+    if frame.width() == 0 || frame.height() == 0 {
+        return Ok(Vec::new());
+    }
+    Ok(pipeline.colors(&frame, config, None))
+}
+
+/// Turn per-segment sums into the target LED colors.
+///
+/// Each segment's linear-light average is re-encoded with `config.gamma`, normalized against
+/// the estimated white point (if any), then shaped by the brightness and saturation curves,
+/// brightened, saturated, white balanced and lifted to the minimum brightness according to
+/// the configuration.
+pub fn sums_to_colors(
+    sums: &[SegmentSums],
+    config: &Config,
+    white_point: Option<&WhitePointEstimator>,
+) -> Vec<Color> {
+    let protection = SaturationProtection {
+        strength: config.saturation_protection,
+        low_saturation: config.saturation_protection_threshold,
+        hue_ranges: config.protected_hue_ranges.clone(),
+    };
+    sums.par_iter()
+        .map(|&(r_sum, g_sum, b_sum, count)| {
+            if count == 0 {
+                // Nothing sampled, e.g. outside a letterboxed frame
+                let [r, g, b] = config.aspect_fill_color;
+                color_to_srgb(Color { r, g, b })
+            } else {
+                // The sums hold linear light scaled to 0-65535; re-encode the average
+                let count_f = count as f32;
+                let encode =
+                    |sum: u64| linear_to_srgb(sum as f32 / count_f / 65535.0, config.gamma);
+                let mut avg = Srgb::new(encode(r_sum), encode(g_sum), encode(b_sum));
+                if let Some(estimator) = white_point {
+                    avg = estimator.apply(avg);
+                }
+                let avg = apply_curves(avg, config.brightness_gamma, config.saturation_gamma);
+                let adjusted = match config.saturation_mode {
+                    SaturationMode::Hsv if config.preserve_hue => adjust_brightness_saturation(
+                        avg,
+                        config.brightness_factor,
+                        config.saturation_factor,
+                        &protection,
+                    ),
+                    SaturationMode::Hsv => {
+                        let bright = increase_brightness(avg, config.brightness_factor);
+                        adjust_saturation(bright, config.saturation_factor, &protection)
+                    }
+                    SaturationMode::Oklch => {
+                        let bright = increase_brightness(avg, config.brightness_factor);
+                        let hsv = Hsv::from_color(bright);
+                        let factor = protection.effective_factor(&hsv, config.saturation_factor);
+                        adjust_saturation_oklch(bright, factor)
+                    }
+                };
+                let balanced = apply_white_balance(adjusted, config.white_balance_kelvin);
+                apply_min_brightness(balanced, config.min_brightness)
+            }
+        })
+        .map(srgb_to_color)
+        .collect()
+}
+
+/// Crop `frame` to `region`, clipping the region to the frame first.
+///
+/// Returns the frame untouched if the region lies entirely outside it.
+pub fn crop_to_region(frame: RgbaImage, region: Region) -> RgbaImage {
+    match region.clamped(frame.width(), frame.height()) {
+        Some(r) if (r.width, r.height) != frame.dimensions() => {
+            image::imageops::crop_imm(&frame, r.x, r.y, r.width, r.height).to_image()
+        }
+        _ => frame,
+    }
+}
+
+/// Downscale `frame` so it fits within `max_width` x `max_height`, preserving its aspect.
+///
+/// A limit of 0 leaves that dimension unbounded; frames already within the limits are
+/// returned untouched.
+pub fn cap_compute_resolution(frame: RgbaImage, max_width: u32, max_height: u32) -> RgbaImage {
+    let (width, height) = frame.dimensions();
+    let scale_for = |size: u32, max: u32| {
+        if max == 0 || size <= max {
+            1.0
+        } else {
+            max as f64 / size as f64
+        }
+    };
+    let scale = scale_for(width, max_width).min(scale_for(height, max_height));
+    if scale >= 1.0 {
+        return frame;
+    }
+    let new_width = ((width as f64 * scale).round() as u32).max(1);
+    let new_height = ((height as f64 * scale).round() as u32).max(1);
+    image::imageops::resize(&frame, new_width, new_height, FilterType::Triangle)
+}
+
+/// Resize `frame` to exactly `width` x `height`.
+///
+/// Each output pixel is the box average of the area of the screen it covers, which avoids
+/// the aliasing of a large `sample_step` at one cheap pass over the frame.
+pub fn downscale(frame: RgbaImage, width: u32, height: u32) -> RgbaImage {
+    if frame.dimensions() == (width, height) {
+        return frame;
+    }
+    image::imageops::thumbnail(&frame, width, height)
+}
+
+/// Reconcile the frame's aspect ratio with the LED layout's `layout_aspect` (width/height).
+///
+/// `Fill` crops the frame around its center to the layout aspect. `Fit` pads it with
+/// transparent pixels instead, which are skipped while sampling, so segments outside the
+/// frame end up empty and show the fill color. `Stretch`, or a `layout_aspect` of 0,
+/// leaves the frame untouched.
+pub fn fit_to_layout(frame: RgbaImage, mode: AspectMode, layout_aspect: f32) -> RgbaImage {
+    let (width, height) = frame.dimensions();
+    if layout_aspect <= 0.0 || width == 0 || height == 0 {
+        return frame;
+    }
+    let frame_aspect = width as f32 / height as f32;
+    // Size of the cropped or padded frame at the layout aspect
+    let (layout_width, layout_height) = match mode {
+        AspectMode::Stretch => return frame,
+        AspectMode::Fill if frame_aspect > layout_aspect => {
+            ((height as f32 * layout_aspect).round() as u32, height)
+        }
+        AspectMode::Fill => (width, (width as f32 / layout_aspect).round() as u32),
+        AspectMode::Fit if frame_aspect > layout_aspect => {
+            (width, (width as f32 / layout_aspect).round() as u32)
+        }
+        AspectMode::Fit => ((height as f32 * layout_aspect).round() as u32, height),
+    };
+    let (layout_width, layout_height) = (layout_width.max(1), layout_height.max(1));
+    if (layout_width, layout_height) == (width, height) {
+        return frame;
+    }
+    match mode {
+        AspectMode::Fill => {
+            let x = (width - layout_width) / 2;
+            let y = (height - layout_height) / 2;
+            image::imageops::crop_imm(&frame, x, y, layout_width, layout_height).to_image()
+        }
+        _ => {
+            let mut canvas = RgbaImage::new(layout_width, layout_height);
+            let x = (layout_width - width) / 2;
+            let y = (layout_height - height) / 2;
+            image::imageops::replace(&mut canvas, &frame, x as i64, y as i64);
+            canvas
+        }
+    }
+}
+
+/// Per-segment color sums: `(red, green, blue, weight)`, with channels in linear light
+/// scaled to 0-65535 (see `linearization_table`) and multiplied by each sample's weight.
+///
+/// A sample's weight is its alpha (`OPAQUE_WEIGHT` when fully opaque), so dividing a channel
+/// by the weight gives the alpha-weighted average.
+pub type SegmentSums = (u64, u64, u64, u64);
+
+/// Weight of one fully opaque sample in `SegmentSums`.
+pub const OPAQUE_WEIGHT: u64 = 255;
+
+/// Whether a pixel with alpha `a` is sampled at all under `alpha_threshold` (0.0-1.0).
+#[inline]
+pub fn is_sampled(a: u8, alpha_threshold: f32) -> bool {
+    a > 0 && a as f32 / 255.0 >= alpha_threshold
+}
+
+/// Mirroring applied to the frame before its pixels are mapped to segments.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flips {
+    /// Mirror left and right.
+    pub horizontal: bool,
+    /// Mirror top and bottom.
+    pub vertical: bool,
+}
+
+/// How the pixels of a frame are assigned to segments.
+#[derive(Clone, Copy)]
+pub struct SegmentLayout<'a> {
+    pub mode: SegmentationMode,
+    /// Number of segments (for `Vertical`/`Horizontal`, the number of LEDs).
+    pub segments: usize,
+    pub flips: Flips,
+    /// Per-LED rectangles from `layout_file`, used instead of `mode` when present.
+    pub leds: Option<&'a LedLayout>,
+}
+
+impl<'a> SegmentLayout<'a> {
+    /// The layout configured in `config`, with `segments` segments.
+    pub fn new(config: &Config, segments: usize, leds: Option<&'a LedLayout>) -> Self {
+        Self {
+            mode: config.segmentation,
+            segments,
+            flips: Flips {
+                horizontal: config.flip_horizontal,
+                vertical: config.flip_vertical,
+            },
+            leds,
+        }
+    }
+
+    /// Segment of pixel `(x, y)` in a `width` x `height` frame; see `led_index_for_pixel`.
+    #[inline]
+    pub fn index(&self, x: usize, y: usize, width: usize, height: usize) -> Option<usize> {
+        match self.leds {
+            Some(leds) if x < width && y < height => {
+                let x = if self.flips.horizontal { width - 1 - x } else { x };
+                let y = if self.flips.vertical { height - 1 - y } else { y };
+                leds.index(x, y, width, height).filter(|&index| index < self.segments)
+            }
+            Some(_) => None,
+            None => led_index_for_pixel(x, y, width, height, self.mode, self.segments, self.flips),
+        }
+    }
+}
+
+/// Index of the segment that pixel `(x, y)` of a `width` x `height` frame falls into.
+///
+/// The frame is mirrored according to `flips` first. `Vertical` splits it into `segments`
+/// columns left to right, `Horizontal` into rows top to bottom, and `Grid` into
+/// `rows` x `cols` cells numbered in row-major order. `Edge` walks the border clockwise
+/// from the top-left corner and `Radial` sweeps clockwise from 12 o'clock, each in
+/// `segments` equal parts. `segments` is the LED count, which only `Grid` carries itself.
+/// Returns `None` for pixels outside the frame or layouts without any segments.
+pub fn led_index_for_pixel(
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    layout: SegmentationMode,
+    segments: usize,
+    flips: Flips,
+) -> Option<usize> {
+    if x >= width || y >= height || segments == 0 {
+        return None;
+    }
+    let x = if flips.horizontal { width - 1 - x } else { x };
+    let y = if flips.vertical { height - 1 - y } else { y };
+    let index = match layout {
+        SegmentationMode::Vertical => (x * segments) / width,
+        SegmentationMode::Horizontal => (y * segments) / height,
+        SegmentationMode::Grid { rows, cols } => {
+            let row = (y * rows) / height;
+            let col = (x * cols) / width;
+            row * cols + col
+        }
+        SegmentationMode::Edge => (border_position(x, y, width, height) * segments)
+            .checked_div(2 * (width + height))
+            .unwrap_or(0),
+        SegmentationMode::Radial => {
+            // Measured from the pixel's center, so a square frame splits symmetrically
+            let dx = x as f32 + 0.5 - width as f32 / 2.0;
+            let dy = y as f32 + 0.5 - height as f32 / 2.0;
+            let turn = dx.atan2(-dy).rem_euclid(std::f32::consts::TAU) / std::f32::consts::TAU;
+            ((turn * segments as f32) as usize).min(segments - 1)
+        }
+    };
+    (index < segments).then_some(index)
+}
+
+/// Position along the frame's border, clockwise from the top-left corner, of the border
+/// point nearest to pixel `(x, y)`; the border is `2 * (width + height)` long.
+fn border_position(x: usize, y: usize, width: usize, height: usize) -> usize {
+    let (right, bottom) = (width - 1 - x, height - 1 - y);
+    let nearest = y.min(right).min(bottom).min(x);
+    if nearest == y {
+        x
+    } else if nearest == right {
+        width + y
+    } else if nearest == bottom {
+        width + height + right
+    } else {
+        2 * width + height + bottom
+    }
+}
+
+/// Sum the sampled pixels of `frame` into the segments of `layout`.
+///
+/// Every `sampling_step`-th pixel of every `sampling_step`-th row is visited and rows are
+/// processed in parallel on the current rayon pool. Channels are linearized through
+/// `linear` and weighted by alpha before being summed; pixels below `alpha_threshold` are
+/// skipped entirely.
+fn accumulate_segments(
+    frame: &RgbaImage,
+    layout: &SegmentLayout,
+    sampling_step: usize,
+    linear: &[u16; 256],
+    alpha_threshold: f32,
+) -> Vec<SegmentSums> {
+    let num_leds = layout.segments;
+    // Use the dimensions of the captured frame rather than the monitor's
+    let width = frame.width() as usize;
+    let height = frame.height() as usize;
+    let raw = frame.as_raw();
+    let stride = row_stride(frame);
+
+    (0..height)
+        .into_par_iter()
+        .step_by(sampling_step)
+        .map(|row| {
+            // Local partial sums for this row
+            let mut row_sums = vec![(0u64, 0u64, 0u64, 0u64); num_leds];
+
+            // A truncated buffer simply contributes nothing for the missing rows
+            let Some(row_slice) = row_pixels(raw, row, stride, width) else {
+                return row_sums;
+            };
+
+            for x in (0..width).step_by(sampling_step) {
+                let [r, g, b, a] = read_pixel(row_slice, x * 4);
+                if !is_sampled(a, alpha_threshold) {
+                    continue;
+                }
+                if let Some(idx) = layout.index(x, row, width, height) {
+                    let weight = a as u64;
+                    let (rr, gg, bb, total_weight) = &mut row_sums[idx];
+                    *rr += linear[r as usize] as u64 * weight;
+                    *gg += linear[g as usize] as u64 * weight;
+                    *bb += linear[b as usize] as u64 * weight;
+                    *total_weight += weight;
+                }
+            }
+            row_sums
+        })
+        .reduce(
+            || vec![(0u64, 0u64, 0u64, 0u64); num_leds],
+            |mut acc, row_sums| {
+                for (i, (r, g, b, c)) in row_sums.into_iter().enumerate() {
+                    let (rr, gg, bb, cc) = &mut acc[i];
+                    *rr += r;
+                    *gg += g;
+                    *bb += b;
+                    *cc += c;
+                }
+                acc
+            },
+        )
+}
+
+/// Sum the sampled pixels like `accumulate_segments`, but in floating point.
+///
+/// Channels are linearized with `gamma` at full precision instead of through the 16-bit
+/// table, which keeps the darkest tones from rounding to zero. Rows are summed in `f32`
+/// and combined in `f64`, so very large frames don't lose precision either. The totals
+/// are returned on the same 0-65535 scale as the integer path.
+fn accumulate_segments_f32(
+    frame: &RgbaImage,
+    layout: &SegmentLayout,
+    sampling_step: usize,
+    gamma: f32,
+    alpha_threshold: f32,
+) -> Vec<SegmentSums> {
+    let num_leds = layout.segments;
+    let linear: [f32; 256] = std::array::from_fn(|v| srgb_to_linear(v as f32 / 255.0, gamma));
+    let width = frame.width() as usize;
+    let height = frame.height() as usize;
+    let raw = frame.as_raw();
+    let stride = row_stride(frame);
+
+    let totals = (0..height)
+        .into_par_iter()
+        .step_by(sampling_step)
+        .map(|row| {
+            let mut row_sums = vec![([0.0f32; 3], 0u64); num_leds];
+            let Some(row_slice) = row_pixels(raw, row, stride, width) else {
+                return row_sums;
+            };
+            for x in (0..width).step_by(sampling_step) {
+                let [r, g, b, a] = read_pixel(row_slice, x * 4);
+                if !is_sampled(a, alpha_threshold) {
+                    continue;
+                }
+                if let Some(idx) = layout.index(x, row, width, height) {
+                    let (sum, total_weight) = &mut row_sums[idx];
+                    let weight = a as f32;
+                    sum[0] += linear[r as usize] * weight;
+                    sum[1] += linear[g as usize] * weight;
+                    sum[2] += linear[b as usize] * weight;
+                    *total_weight += a as u64;
+                }
+            }
+            row_sums
+        })
+        .fold(
+            || vec![([0.0f64; 3], 0u64); num_leds],
+            |mut acc, row_sums| {
+                for ((total, total_count), (sum, count)) in acc.iter_mut().zip(row_sums) {
+                    for (t, s) in total.iter_mut().zip(sum) {
+                        *t += s as f64;
+                    }
+                    *total_count += count;
+                }
+                acc
+            },
+        )
+        .reduce(
+            || vec![([0.0f64; 3], 0u64); num_leds],
+            |mut acc, partial| {
+                for ((total, total_count), (sum, count)) in acc.iter_mut().zip(partial) {
+                    for (t, s) in total.iter_mut().zip(sum) {
+                        *t += s;
+                    }
+                    *total_count += count;
+                }
+                acc
+            },
+        );
+
+    totals
+        .into_iter()
+        .map(|([r, g, b], count)| {
+            let scale = |sum: f64| (sum * 65535.0).round() as u64;
+            (scale(r), scale(g), scale(b), count)
+        })
+        .collect()
+}
+
+/// Accumulate segment sums, re-sampling at a finer step while any segment is undersampled.
+///
+/// Starting from `sample_step`, the step is halved until every segment has at least
+/// `min_segment_samples` samples or the step reaches 1; 0 disables the guard. Segments
+/// whose visited pixels were all transparent are empty on purpose (such as the `Fit`
+/// padding around a letterboxed or cropped frame) and don't count as undersampled, since
+/// a finer step would only find more padding. The configured `accumulator` selects the
+/// integer or floating-point summation.
+pub fn accumulate_with_min_samples(
+    frame: &RgbaImage,
+    config: &Config,
+    num_leds: usize,
+    linear: &[u16; 256],
+    leds: Option<&LedLayout>,
+) -> Vec<SegmentSums> {
+    let layout = SegmentLayout::new(config, num_leds, leds);
+    let mut step = config.sampling_step();
+    loop {
+        let sums = match config.accumulator {
+            Accumulator::U64 => {
+                accumulate_segments(frame, &layout, step, linear, config.alpha_threshold)
+            }
+            Accumulator::F32 => accumulate_segments_f32(
+                frame,
+                &layout,
+                step,
+                config.gamma,
+                config.alpha_threshold,
+            ),
+        };
+        // Partially transparent samples count as the fraction of a sample they contribute
+        let min_weight = config.min_segment_samples.saturating_mul(OPAQUE_WEIGHT);
+        if step <= 1 || sums.iter().all(|&(_, _, _, weight)| weight >= min_weight) {
+            return sums;
+        }
+        let visited = visited_samples(frame, &layout, step);
+        let undersampled = sums.iter().zip(visited).any(|(&(_, _, _, weight), visited)| {
+            weight < min_weight && (weight > 0 || visited < config.min_segment_samples)
+        });
+        if !undersampled {
+            return sums;
+        }
+        step /= 2;
+    }
+}
+
+/// Number of pixels of `frame` visited per segment of `layout` at `sampling_step`,
+/// whether or not they are opaque enough to be sampled.
+fn visited_samples(frame: &RgbaImage, layout: &SegmentLayout, sampling_step: usize) -> Vec<u64> {
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let mut visited = vec![0u64; layout.segments];
+    for y in (0..height).step_by(sampling_step) {
+        for x in (0..width).step_by(sampling_step) {
+            if let Some(index) = layout.index(x, y, width, height) {
+                visited[index] += 1;
+            }
+        }
+    }
+    visited
+}
+
+/// Sample `frame` into `num_leds` segments using the configured color extraction.
+///
+/// With `leds`, each segment is the rectangle of the LED with that index instead.
+pub fn sample_segments(
+    frame: &RgbaImage,
+    config: &Config,
+    num_leds: usize,
+    linear: &[u16; 256],
+    leds: Option<&LedLayout>,
+) -> Vec<SegmentSums> {
+    let sampling_step = config.sampling_step();
+    match config.extraction {
+        ColorExtraction::Average => {
+            accumulate_with_min_samples(frame, config, num_leds, linear, leds)
+        }
+        ColorExtraction::DominantKMeans { k } => {
+            let layout = SegmentLayout::new(config, num_leds, leds);
+            let threshold = config.alpha_threshold;
+            dominant_segment_colors(frame, &layout, sampling_step, k, linear, threshold)
+        }
+    }
+}
+
+/// Read the device's LED colors back and report any that differ from what was sent.
+///
+/// Returns the number of LEDs whose channels differ from `sent` by more than `tolerance`.
+async fn verify_device_colors(
+    openrgb_client: &OpenRGB<tokio::net::TcpStream>,
+    controller_id: u32,
+    sent: &[Color],
+    tolerance: u8,
+) -> Result<usize, OpenRGBError> {
+    let reported = openrgb_client.get_controller(controller_id).await?.colors;
+    let mismatches = count_color_mismatches(sent, &reported, tolerance);
+    if mismatches > 0 {
+        warn!(
+            "Write verification failed: {mismatches} of {} LEDs differ from the sent colors.",
+            sent.len()
+        );
+    }
+    Ok(mismatches)
+}
+
+/// Count the LEDs whose reported color differs from the sent one by more than `tolerance`
+/// on any channel. LEDs missing from `reported` count as mismatches.
+fn count_color_mismatches(sent: &[Color], reported: &[Color], tolerance: u8) -> usize {
+    sent.iter()
+        .enumerate()
+        .filter(|&(i, s)| match reported.get(i) {
+            Some(r) => {
+                s.r.abs_diff(r.r) > tolerance
+                    || s.g.abs_diff(r.g) > tolerance
+                    || s.b.abs_diff(r.b) > tolerance
+            }
+            None => true,
+        })
+        .count()
+}
+
+/// Bytes between the starts of consecutive rows of `frame`'s raw buffer.
+///
+/// An `RgbaImage` is always tightly packed, so this is `width * 4`. Its buffer may still be
+/// longer than the pixels (`ImageBuffer::from_raw` accepts trailing bytes), which is why the
+/// stride can't be derived from the buffer length. A capture backend that pads its rows
+/// has to repack them before building the image.
+pub fn row_stride(frame: &RgbaImage) -> usize {
+    frame.width() as usize * 4
+}
+
+/// Borrow the pixels of row `y` from a raw RGBA buffer whose rows are `stride` bytes apart.
+///
+/// Returns `None` if the buffer is too short to hold `width` pixels for that row.
+pub fn row_pixels(raw: &[u8], y: usize, stride: usize, width: usize) -> Option<&[u8]> {
+    let start = y.checked_mul(stride)?;
+    raw.get(start..start.checked_add(width * 4)?)
+}
+
+/// Read the RGBA bytes starting at `idx` from a row returned by `row_pixels`.
+///
+/// Debug builds always bounds-check. Release builds built with the `unchecked-pixels`
+/// feature skip the per-pixel checks, relying on `row_pixels` having validated the row length.
+#[inline(always)]
+pub fn read_pixel(row: &[u8], idx: usize) -> [u8; 4] {
+    #[cfg(all(feature = "unchecked-pixels", not(debug_assertions)))]
+    {
+        // SAFETY: callers only pass `idx = x * 4` with `x < width`, and `row_pixels`
+        // guarantees `row.len() == width * 4`.
+        unsafe {
+            [
+                *row.get_unchecked(idx),
+                *row.get_unchecked(idx + 1),
+                *row.get_unchecked(idx + 2),
+                *row.get_unchecked(idx + 3),
+            ]
+        }
+    }
+    #[cfg(not(all(feature = "unchecked-pixels", not(debug_assertions))))]
+    {
+        [row[idx], row[idx + 1], row[idx + 2], row[idx + 3]]
+    }
+}
+
+/// The per-LED buffers of the sync loop, kept the same length.
+struct LedBuffers {
+    /// Colors the devices currently show.
+    current: Vec<Color>,
+    /// Scratch buffer for the intermediate colors of a transition.
+    step: Vec<Color>,
+}
+
+impl LedBuffers {
+    fn new(led_count: usize) -> Self {
+        Self {
+            current: vec![Color { r: 0, g: 0, b: 0 }; led_count],
+            step: vec![Color { r: 0, g: 0, b: 0 }; led_count],
+        }
+    }
+
+    /// Resize both buffers to `led_count` LEDs, keeping the current colors resampled onto
+    /// the new count. Returns whether the count changed.
+    fn resize(&mut self, led_count: usize) -> bool {
+        if self.current.len() == led_count {
+            return false;
+        }
+        info!("LED count changed from {} to {led_count}.", self.current.len());
+        self.current = resample_colors(&self.current, led_count);
+        if self.current.len() != led_count {
+            // Nothing to resample from
+            self.current = vec![Color { r: 0, g: 0, b: 0 }; led_count];
+        }
+        self.step = vec![Color { r: 0, g: 0, b: 0 }; led_count];
+        true
+    }
+}
+
+/// Running per-LED average of the target colors observed during a debounce window.
+#[derive(Default)]
+struct TargetAverager {
+    sums: Vec<[u32; 3]>,
+    frames: u32,
+}
+
+impl TargetAverager {
+    /// Add one frame of target colors to the running sums.
+    fn add(&mut self, colors: &[Color]) {
+        if self.sums.len() != colors.len() {
+            self.sums = vec![[0; 3]; colors.len()];
+            self.frames = 0;
+        }
+        for (sum, color) in self.sums.iter_mut().zip(colors) {
+            sum[0] += color.r as u32;
+            sum[1] += color.g as u32;
+            sum[2] += color.b as u32;
+        }
+        self.frames += 1;
+    }
+
+    /// Return the average of the frames added so far and start a new window.
+    fn take_average(&mut self) -> Option<Vec<Color>> {
+        if self.frames == 0 {
+            return None;
+        }
+        let frames = self.frames;
+        let average = self
+            .sums
+            .iter()
+            .map(|sum| Color {
+                r: ((sum[0] + frames / 2) / frames) as u8,
+                g: ((sum[1] + frames / 2) / frames) as u8,
+                b: ((sum[2] + frames / 2) / frames) as u8,
+            })
+            .collect();
+        self.sums.clear();
+        self.frames = 0;
+        Some(average)
+    }
+}
+
+/// Arrange segment colors in the physical LED order, producing `led_count` colors.
+///
+/// Segments are mirrored first if `mirror_segments` is set, then the order is reversed if
+/// `reverse_led_order` is set, and finally rotated so the first LED color lands on LED
+/// `led_offset` (modulo the LED count).
+pub fn map_to_leds(colors: Vec<Color>, config: &Config, led_count: usize) -> Vec<Color> {
+    let mut colors = if config.mirror_segments && !colors.is_empty() {
+        (0..led_count)
+            .map(|i| colors[i.min(led_count - 1 - i).min(colors.len() - 1)])
+            .collect()
+    } else {
+        colors
+    };
+    if config.reverse_led_order {
+        colors.reverse();
+    }
+    if !colors.is_empty() {
+        let len = colors.len();
+        colors.rotate_right(config.led_offset % len);
+    }
+    colors
+}
+
+/// Blend each LED with its two physical neighbours by `amount` (0.0-1.0).
+///
+/// At 1.0 every LED becomes the plain average of itself and its neighbours. The end LEDs
+/// of a strip (`Clamp`) stand in for their missing neighbour; on a ring (`Wrap`) the last
+/// and first LED are each other's neighbours, so the seam is smoothed like any other pair.
+pub fn smooth_neighbours(colors: &[Color], amount: f32, edges: LedEdges) -> Vec<Color> {
+    let len = colors.len();
+    if amount <= 0.0 || len < 2 {
+        return colors.to_vec();
+    }
+    let neighbour_weight = amount.clamp(0.0, 1.0) / 3.0;
+    let own_weight = 1.0 - 2.0 * neighbour_weight;
+    (0..len)
+        .map(|i| {
+            let (prev, next) = match edges {
+                LedEdges::Clamp => (i.saturating_sub(1), (i + 1).min(len - 1)),
+                LedEdges::Wrap => ((i + len - 1) % len, (i + 1) % len),
+            };
+            let blend = |channel: fn(&Color) -> u8| {
+                let neighbours = channel(&colors[prev]) as f32 + channel(&colors[next]) as f32;
+                let own = channel(&colors[i]) as f32;
+                (own * own_weight + neighbours * neighbour_weight).round().clamp(0.0, 255.0) as u8
+            };
+            Color {
+                r: blend(|c| c.r),
+                g: blend(|c| c.g),
+                b: blend(|c| c.b),
+            }
+        })
+        .collect()
+}
+
+/// Scale `colors` by a slow sine "breath" of the given relative `amplitude`.
+///
+/// `phase` counts breathing cycles; the brightness swings between `1 - amplitude` and
+/// `1 + amplitude` of the held colors.
+pub fn idle_breathing(colors: &[Color], amplitude: f32, phase: f32) -> Vec<Color> {
+    let factor = 1.0 + amplitude * (phase * std::f32::consts::TAU).sin();
+    let scale = |channel: u8| (channel as f32 * factor).round().clamp(0.0, 255.0) as u8;
+    colors
+        .iter()
+        .map(|c| Color {
+            r: scale(c.r),
+            g: scale(c.g),
+            b: scale(c.b),
+        })
+        .collect()
+}
+
+/// The idle animation frame for `held` colors, once the scene has been static for
+/// `static_for` (the time since the last transition).
+///
+/// Returns `None` while the idle animation is disabled or its timeout hasn't passed. The
+/// breath starts at the held brightness when the timeout passes.
+pub fn idle_frame(held: &[Color], static_for: Duration, config: &Config) -> Option<Vec<Color>> {
+    if config.idle_animation_after_ms == 0 {
+        return None;
+    }
+    let idle_for = static_for.checked_sub(Duration::from_millis(config.idle_animation_after_ms))?;
+    let period_secs = config.idle_animation_period_ms.max(1) as f32 / 1000.0;
+    let phase = idle_for.as_secs_f32() / period_secs;
+    Some(idle_breathing(held, config.idle_animation_amplitude, phase))
+}
+
+/// Per-LED exponential moving average of the target colors across frames.
+struct TargetSmoother {
+    /// Weight of the newest frame (1.0 disables smoothing).
+    alpha: f32,
+    smoothed: Vec<[f32; 3]>,
+}
+
+impl TargetSmoother {
+    fn new(alpha: f32) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            smoothed: Vec::new(),
+        }
+    }
+
+    /// Blend `colors` into the running average and return the smoothed colors.
+    fn apply(&mut self, colors: Vec<Color>) -> Vec<Color> {
+        if self.alpha >= 1.0 {
+            return colors;
+        }
+        if self.smoothed.len() != colors.len() {
+            // Start from the first frame instead of fading in from black
+            self.smoothed = colors.iter().map(|c| [c.r as f32, c.g as f32, c.b as f32]).collect();
+        } else {
+            for (smoothed, color) in self.smoothed.iter_mut().zip(&colors) {
+                for (channel, value) in smoothed.iter_mut().zip([color.r, color.g, color.b]) {
+                    *channel += self.alpha * (value as f32 - *channel);
+                }
+            }
+        }
+        self.smoothed
+            .iter()
+            .map(|&[r, g, b]| Color {
+                r: r.round() as u8,
+                g: g.round() as u8,
+                b: b.round() as u8,
+            })
+            .collect()
+    }
+}
+
+/// An OpenRGB controller resolved from one of the configured devices.
+pub struct TargetDevice {
+    /// Controller id on the OpenRGB server.
+    pub id: u32,
+    /// Name of the controller as reported by OpenRGB.
+    pub name: String,
+    /// Number of LEDs driven, clamped to what the controller reports.
+    pub num_leds: usize,
+    /// With `controlled_leds`, the device's colors when syncing started; only the
+    /// controlled indices are overwritten.
+    pub base_colors: Option<Vec<Color>>,
+    /// LED indices updated from the screen, if not all of them.
+    pub controlled_leds: Option<Vec<usize>>,
+    /// Multiplier for the colors sent to this device, from the device's `brightness_scale`
+    /// and `Config::device_brightness_scale`.
+    pub brightness_scale: f32,
+}
+
+impl TargetDevice {
+    /// The colors to send to this device for one frame of LED `colors`: resampled onto its
+    /// `num_leds`, corrected by `output`, scaled and merged with the base colors.
+    fn frame(&self, colors: &[Color], output: &OutputStage) -> Vec<Color> {
+        let corrected = output.apply(&resample_colors(colors, self.num_leds));
+        let scaled = corrected
+            .into_iter()
+            .map(|color| scale_color(color, self.brightness_scale))
+            .collect();
+        self.merge(scaled)
+    }
+
+    /// The full set of colors to send for one frame of LED colors.
+    ///
+    /// Without `controlled_leds` that is the frame itself; otherwise the base colors with
+    /// only the controlled indices replaced.
+    fn merge(&self, frame: Vec<Color>) -> Vec<Color> {
+        let (Some(base), Some(leds)) = (&self.base_colors, &self.controlled_leds) else {
+            return frame;
+        };
+        let mut merged = base.clone();
+        for &i in leds {
+            if let (Some(slot), Some(&color)) = (merged.get_mut(i), frame.get(i)) {
+                *slot = color;
+            }
+        }
+        merged
+    }
+}
+
+/// Show `SyncState::TimedOut` if `err` is a connection timeout, and pass `err` on.
+fn report_timeout(err: KeyBloomError, sync_status: &Mutex<SyncStatus>) -> KeyBloomError {
+    if matches!(err, KeyBloomError::ConnectTimeout { .. }) {
+        lock_or_recover(sync_status).state = SyncState::TimedOut;
+    }
+    err
+}
+
+/// Resolve every configured device to a controller and switch it to custom mode.
+pub async fn resolve_devices(
+    client: &OpenRGB<tokio::net::TcpStream>,
+    config: &Config,
+) -> Result<Vec<TargetDevice>, KeyBloomError> {
+    let mut devices: Vec<TargetDevice> = Vec::with_capacity(config.devices.len());
+    for device in &config.devices {
+        let lookup = find_device(
+            client,
+            &device.device_name,
+            config.strict_device_match,
+            config.keyboard_fallback,
+        );
+        let (id, name, led_count) = with_connect_timeout(config, lookup).await?;
+        if devices.iter().any(|d| d.id == id) {
+            warn!(
+                "'{}' resolves to the already used controller '{name}'; skipping.",
+                device.device_name
+            );
+            continue;
+        }
+
+        // Attempt to set custom mode (if supported)
+        if let Err(e) = client.set_custom_mode(id).await {
+            warn!("Could not set custom mode on {name}: {e}");
+        }
+
+        // Remember the current colors of LEDs that aren't synced, to send them back unchanged
+        let base_colors = match &config.controlled_leds {
+            Some(_) => Some(with_connect_timeout(config, client.get_controller(id)).await?.colors),
+            None => None,
+        };
+
+        // Never send more colors than the device has; the configured value stays in the file
+        devices.push(TargetDevice {
+            id,
+            name,
+            num_leds: effective_led_count(device.num_leds, led_count),
+            base_colors,
+            controlled_leds: config.controlled_leds.clone(),
+            brightness_scale: device.brightness_scale * config.device_brightness_scale,
+        });
+    }
+    Ok(devices)
+}
+
+/// Find the controller whose name matches `device_name`.
+///
+/// By default a controller matches if its name contains `device_name`, or, with
+/// `keyboard_fallback`, the word "keyboard". With `strict` only a controller named exactly
+/// `device_name` matches. Returns the controller id, its name and the number of LEDs it
+/// reports.
+pub async fn find_device(
+    client: &OpenRGB<tokio::net::TcpStream>,
+    device_name: &str,
+    strict: bool,
+    keyboard_fallback: bool,
+) -> Result<(u32, String, usize), KeyBloomError> {
+    let controller_count = client.get_controller_count().await?;
+    let mut controllers = Vec::with_capacity(controller_count as usize);
+    for i in 0..controller_count {
+        if let Ok(ctrl) = client.get_controller(i).await {
+            controllers.push((i, ctrl));
+        }
+    }
+    let names = controllers.iter().map(|(_, ctrl)| ctrl.name.as_str());
+    let found = match_device_name(names, device_name, strict, keyboard_fallback)
+        .ok_or_else(|| KeyBloomError::DeviceNotFound(device_name.to_string()))?;
+    let (id, ctrl) = controllers.swap_remove(found);
+    Ok((id, ctrl.name, ctrl.leds.len()))
+}
+
+/// Pick the controller `find_device` uses from the controller `names`, in server order.
+///
+/// Returns the position of the first name matching `device_name`, else of the first
+/// keyboard if `keyboard_fallback` is set and `strict` isn't.
+fn match_device_name<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    device_name: &str,
+    strict: bool,
+    keyboard_fallback: bool,
+) -> Option<usize> {
+    let mut fallback = None;
+    for (i, name) in names.into_iter().enumerate() {
+        let matches = if strict { name == device_name } else { name.contains(device_name) };
+        if matches {
+            return Some(i);
+        }
+        let is_keyboard = name.to_lowercase().contains("keyboard");
+        if keyboard_fallback && !strict && is_keyboard && fallback.is_none() {
+            fallback = Some(i);
+        }
+    }
+    fallback
+}
+
+/// Connect to OpenRGB and return the number of LEDs the primary device reports.
+///
+/// Only reads from the server, so it is safe to call while nothing is syncing.
+pub async fn query_led_count(config: &Config) -> Result<usize, KeyBloomError> {
+    let client = connect_client(config).await?;
+    let lookup = find_device(
+        &client,
+        &config.primary_device().device_name,
+        config.strict_device_match,
+        config.keyboard_fallback,
+    );
+    let (_, _, led_count) = with_connect_timeout(config, lookup).await?;
+    Ok(led_count)
+}
+
+/// Connect to OpenRGB and put the configured devices into custom mode.
+async fn connect_devices(
+    config: &Config,
+) -> Result<(OpenRGB<tokio::net::TcpStream>, Vec<TargetDevice>), KeyBloomError> {
+    let client = connect_client(config).await?;
+    let devices = resolve_devices(&client, config).await?;
+    Ok((client, devices))
+}
+
+/// Connect to OpenRGB and set every LED of the configured devices to white, through the
+/// output stage, so the channel calibration can be judged.
+pub async fn show_white(config: &Config) -> Result<(), KeyBloomError> {
+    let output = OutputStage::new(config)?;
+    let (client, devices) = connect_devices(config).await?;
+    let max_leds = devices.iter().map(|d| d.num_leds).max().unwrap_or(0);
+    let white = vec![Color { r: 255, g: 255, b: 255 }; max_leds];
+    write_frame(&client, &devices, &output, &white, None).await?;
+    Ok(())
+}
+
+/// The LED test pattern for `led_count` LEDs: LED 0 red, 1 green, 2 blue, the rest dim
+/// white, arranged by `reverse_led_order` and `led_offset` like the synced colors.
+pub fn test_pattern(led_count: usize, config: &Config) -> Vec<Color> {
+    const MARKERS: [Color; 3] = [
+        Color { r: 255, g: 0, b: 0 },
+        Color { r: 0, g: 255, b: 0 },
+        Color { r: 0, g: 0, b: 255 },
+    ];
+    let dim_white = Color { r: 32, g: 32, b: 32 };
+    let mut colors: Vec<Color> = (0..led_count)
+        .map(|i| MARKERS.get(i).copied().unwrap_or(dim_white))
+        .collect();
+    if config.reverse_led_order {
+        colors.reverse();
+    }
+    if !colors.is_empty() {
+        let len = colors.len();
+        colors.rotate_right(config.led_offset % len);
+    }
+    colors
+}
+
+/// Connect to OpenRGB and light the test pattern on every configured device, bypassing
+/// capture and the output corrections.
+///
+/// Returns a description of the devices lit, e.g. "G213 (5 LEDs)".
+pub async fn show_test_pattern(config: &Config) -> Result<String, KeyBloomError> {
+    let (client, devices) = connect_devices(config).await?;
+    for device in &devices {
+        client
+            .update_leds(device.id, test_pattern(device.num_leds, config))
+            .await?;
+    }
+    let lit: Vec<String> = devices
+        .iter()
+        .map(|device| format!("{} ({} LEDs)", device.name, device.num_leds))
+        .collect();
+    Ok(lit.join(", "))
+}
+
+/// Connect to OpenRGB and return the names of all controllers it reports.
+pub async fn list_controller_names(config: &Config) -> Result<Vec<String>, KeyBloomError> {
+    let client = connect_client(config).await?;
+    with_connect_timeout(config, async {
+        let controller_count = client.get_controller_count().await?;
+        let mut names = Vec::with_capacity(controller_count as usize);
+        for i in 0..controller_count {
+            names.push(client.get_controller(i).await?.name);
+        }
+        Ok::<_, OpenRGBError>(names)
+    })
+    .await
+}
+
+/// Send one frame of segment colors to every device.
+///
+/// The colors are resampled to each device's LED count, passed through the output stage
+/// and scaled by the device's brightness. With `verify_tolerance` set, the device colors
+/// are read back afterwards.
+pub async fn write_frame(
+    openrgb_client: &OpenRGB<tokio::net::TcpStream>,
+    devices: &[TargetDevice],
+    output: &OutputStage,
+    colors: &[Color],
+    verify_tolerance: Option<u8>,
+) -> Result<(), OpenRGBError> {
+    for device in devices {
+        let sent = device.frame(colors, output);
+        openrgb_client.update_leds(device.id, sent.clone()).await?;
+        output.log_sent(&device.name, &sent);
+        if let Some(tolerance) = verify_tolerance {
+            verify_device_colors(openrgb_client, device.id, &sent, tolerance).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Map `colors` onto `len` LEDs, averaging the segments that fall onto each LED.
+///
+/// Lengths that already match are returned unchanged.
+pub fn resample_colors(colors: &[Color], len: usize) -> Vec<Color> {
+    if colors.len() == len || colors.is_empty() {
+        return colors.to_vec();
+    }
+    (0..len)
+        .map(|i| {
+            let start = i * colors.len() / len;
+            let end = ((i + 1) * colors.len() / len).max(start + 1).min(colors.len());
+            let span = &colors[start..end];
+            let n = span.len() as u32;
+            let (r, g, b) = span.iter().fold((0u32, 0u32, 0u32), |(r, g, b), c| {
+                (r + c.r as u32, g + c.g as u32, b + c.b as u32)
+            });
+            Color {
+                r: ((r + n / 2) / n) as u8,
+                g: ((g + n / 2) / n) as u8,
+                b: ((b + n / 2) / n) as u8,
+            }
+        })
+        .collect()
+}
+
+/// Select the monitor configured by `monitor_index`.
+pub fn select_monitor(config: &Config) -> Result<Monitor, KeyBloomError> {
+    let monitors = Monitor::all()?;
+    if monitors.is_empty() {
+        return Err(KeyBloomError::NoMonitors);
+    }
+    match monitors.get(config.monitor_index) {
+        Some(monitor) => Ok(monitor.clone()),
+        None => Err(KeyBloomError::MonitorNotFound {
+            index: config.monitor_index,
+            available: monitors
+                .iter()
+                .enumerate()
+                .map(|(i, monitor)| format!("{i}: {}", monitor.name()))
+                .collect(),
+        }),
+    }
+}
+
+/// Select the monitor to capture, retrying for a while if none are connected yet.
+///
+/// Displays can take a moment to reappear after resume. Returns `Ok(None)` if the stop
+/// signal is raised while waiting.
+async fn wait_for_monitor(
+    config: &Config,
+    stop_signal: &AtomicBool,
+) -> Result<Option<Monitor>, KeyBloomError> {
+    const ATTEMPTS: u32 = 5;
+    let mut backoff = Duration::from_millis(500);
+    let mut attempt = 1;
+    loop {
+        match select_monitor(config) {
+            Err(KeyBloomError::NoMonitors) if attempt < ATTEMPTS => {
+                warn!("No monitors found, retrying in {backoff:?}.");
+                if !sleep_unless_stopped(backoff, stop_signal).await {
+                    return Ok(None);
+                }
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result.map(Some),
+        }
+    }
+}
+
+/// Clamp the configured LED count to what the device reports, warning when it is reduced.
+///
+/// A device reporting no LEDs leaves the configured count untouched.
+pub fn effective_led_count(configured: usize, device: usize) -> usize {
+    if device > 0 && configured > device {
+        warn!(
+            "Configured num_leds ({configured}) exceeds the device's {device} LEDs; using {device}."
+        );
+        device
+    } else {
+        configured
+    }
+}
+
+/// Whether a change of `max_dist` is large enough to count as a scene cut.
+fn is_scene_cut(max_dist: f32, config: &Config) -> bool {
+    config.scene_cut_threshold > 0.0 && max_dist > config.scene_cut_threshold
+}
+
+/// Whether a change of `max_dist` may start a transition `since_last` after the previous one.
+///
+/// Changes above `color_change_threshold` wait out `debounce_duration_ms`, except scene cuts,
+/// which skip the debounce so large changes show up immediately.
+fn transition_allowed(max_dist: f32, since_last: Duration, config: &Config) -> bool {
+    let debounce = Duration::from_millis(config.debounce_duration_ms);
+    max_dist > config.color_change_threshold
+        && (is_scene_cut(max_dist, config) || since_last >= debounce)
+}
+
+/// Whether an OpenRGB error means the connection itself is gone.
+fn is_connection_error(error: &OpenRGBError) -> bool {
+    matches!(
+        error,
+        OpenRGBError::ConnectionError { .. } | OpenRGBError::CommunicationError { .. }
+    )
+}
+
+/// Re-establish the OpenRGB connection with exponential backoff (capped at 5 seconds).
+///
+/// While this runs the shared status reports `SyncState::Reconnecting`. Once connected, the
+/// devices are rediscovered and put back into custom mode. Returns `None` if the stop signal
+/// is raised first.
+async fn reconnect(
+    config: &Config,
+    sync_status: &Mutex<SyncStatus>,
+    stop_signal: &AtomicBool,
+) -> Option<(OpenRGB<tokio::net::TcpStream>, Vec<TargetDevice>)> {
+    const MAX_BACKOFF: Duration = Duration::from_secs(5);
+    lock_or_recover(sync_status).state = SyncState::Reconnecting;
+
+    let mut backoff = Duration::from_millis(250);
+    loop {
+        if !sleep_unless_stopped(backoff, stop_signal).await {
+            return None;
+        }
+        match connect_session(config).await {
+            Ok(session) => {
+                info!("Reconnected to OpenRGB server.");
+                lock_or_recover(sync_status).state = SyncState::Running;
+                return Some(session);
+            }
+            Err(e) => {
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                warn!("Reconnect failed, retrying in {backoff:?}: {e}");
+            }
+        }
+    }
+}
+
+/// Connect to OpenRGB once, register the client name and resolve the configured devices.
+async fn connect_session(
+    config: &Config,
+) -> Result<(OpenRGB<tokio::net::TcpStream>, Vec<TargetDevice>), KeyBloomError> {
+    let client = connect_client(config).await?;
+    register_client(&client, config).await?;
+    let devices = resolve_devices(&client, config).await?;
+    Ok((client, devices))
+}
+
+/// Announce `client_name` to the server and load `openrgb_profile`, if one is configured.
+///
+/// A missing or failing profile is only logged, so syncing still starts. Every request is
+/// bounded by `connect_timeout_ms`, like the device lookup.
+pub async fn register_client(
+    client: &OpenRGB<tokio::net::TcpStream>,
+    config: &Config,
+) -> Result<(), KeyBloomError> {
+    with_connect_timeout(config, client.set_name(config.client_name.clone())).await?;
+    let Some(profile) = &config.openrgb_profile else {
+        return Ok(());
+    };
+    match with_connect_timeout(config, client.get_profiles()).await {
+        Ok(profiles) if !profiles.contains(profile) => warn!(
+            "OpenRGB profile '{profile}' not found; available: {}",
+            profiles.join(", ")
+        ),
+        Ok(_) => match with_connect_timeout(config, client.load_profile(profile.clone())).await {
+            Ok(()) => info!("Loaded OpenRGB profile '{profile}'."),
+            Err(e) => warn!("Failed to load OpenRGB profile '{profile}': {e}"),
+        },
+        Err(e) => warn!("Cannot load OpenRGB profile '{profile}': {e}"),
+    }
+    Ok(())
+}
+
+/// Connect to the configured OpenRGB server, giving up after `connect_timeout_ms`.
+pub async fn connect_client(
+    config: &Config,
+) -> Result<OpenRGB<tokio::net::TcpStream>, KeyBloomError> {
+    let connect = OpenRGB::connect_to((&config.openrgb_host[..], config.openrgb_port));
+    with_connect_timeout(config, connect).await.map_err(|err| match err {
+        KeyBloomError::Backend(source) => KeyBloomError::Connect {
+            host: config.openrgb_host.clone(),
+            port: config.openrgb_port,
+            source,
+        },
+        err => err,
+    })
+}
+
+/// Run a request to the OpenRGB server, failing with `KeyBloomError::ConnectTimeout` if it
+/// takes longer than `connect_timeout_ms`.
+pub async fn with_connect_timeout<T, E: Into<KeyBloomError>>(
+    config: &Config,
+    request: impl std::future::Future<Output = Result<T, E>>,
+) -> Result<T, KeyBloomError> {
+    match tokio::time::timeout(Duration::from_millis(config.connect_timeout_ms), request).await {
+        Ok(result) => result.map_err(Into::into),
+        Err(_) => Err(KeyBloomError::ConnectTimeout {
+            host: config.openrgb_host.clone(),
+            port: config.openrgb_port,
+            timeout_ms: config.connect_timeout_ms,
+        }),
+    }
+}
+
+/// Connect to the configured OpenRGB server, retrying for up to `connect_wait_ms`.
+///
+/// Returns `Ok(None)` if the stop signal is raised while waiting, and the last
+/// connection error once the wait window has elapsed.
+pub async fn connect_with_wait(
+    config: &Config,
+    stop_signal: &AtomicBool,
+) -> Result<Option<OpenRGB<tokio::net::TcpStream>>, KeyBloomError> {
+    let wait = Duration::from_millis(config.connect_wait_ms);
+    retry_within(wait, CONNECT_RETRY_DELAY, stop_signal, || connect_client(config)).await
+}
+
+/// Delay between connection attempts while waiting for the server.
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Call `attempt` until it succeeds, retrying every `retry_delay` for up to `wait`.
+///
+/// Returns `Ok(None)` if `stop_signal` is raised between attempts, and the last error
+/// once `wait` has elapsed.
+async fn retry_within<T, E, F: std::future::Future<Output = Result<T, E>>>(
+    wait: Duration,
+    retry_delay: Duration,
+    stop_signal: &AtomicBool,
+    mut attempt: impl FnMut() -> F,
+) -> Result<Option<T>, E> {
+    let deadline = Instant::now() + wait;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(Some(value)),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => {
+                if !sleep_unless_stopped(retry_delay, stop_signal).await {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+/// Sleep for `duration`, waking early if the stop signal is raised.
+///
+/// Returns `false` if the sleep was cut short by the stop signal.
+pub async fn sleep_unless_stopped(duration: Duration, stop_signal: &AtomicBool) -> bool {
+    let poll_interval = Duration::from_millis(50);
+    let deadline = Instant::now() + duration;
+    loop {
+        if stop_signal.load(Ordering::Relaxed) {
+            return false;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        sleep(remaining.min(poll_interval)).await;
+    }
+}
+
+/// Number of interpolation steps for a transition from `current` to `target`.
+///
+/// Without `scale_transition_steps` this is always `transition_steps`. Otherwise the count
+/// grows with the largest per-LED RGB distance, reaching `transition_steps` for a jump from
+/// black to white and never dropping below `min_transition_steps`.
+pub fn transition_step_count(current: &[Color], target: &[Color], config: &Config) -> usize {
+    let max_steps = config.transition_steps.max(1);
+    if !config.scale_transition_steps {
+        return max_steps;
+    }
+    let max_dist_sq = current
+        .iter()
+        .zip(target)
+        .map(|(c, t)| {
+            let dr = c.r as f32 - t.r as f32;
+            let dg = c.g as f32 - t.g as f32;
+            let db = c.b as f32 - t.b as f32;
+            dr * dr + dg * dg + db * db
+        })
+        .fold(0.0f32, f32::max);
+    let fraction = (max_dist_sq / (3.0 * 255.0 * 255.0)).sqrt();
+    let steps = (fraction * max_steps as f32).ceil() as usize;
+    steps.clamp(config.min_transition_steps.clamp(1, max_steps), max_steps)
+}
+
+/// How many steps LED `index` of `count` starts its ramp after the first one.
+///
+/// On a strip the lag grows linearly across the array up to `stagger_steps` for the last
+/// LED, so brightness changes don't hit every LED (and the supply) at the same instant. On
+/// a ring it grows with the distance from LED 0 either way round, peaking opposite it, so
+/// the last LED ramps right alongside its neighbour LED 0.
+fn stagger_lag(index: usize, count: usize, stagger_steps: f32, edges: LedEdges) -> f32 {
+    if count <= 1 {
+        return 0.0;
+    }
+    match edges {
+        LedEdges::Clamp => stagger_steps * index as f32 / (count - 1) as f32,
+        LedEdges::Wrap => {
+            let distance = index.min(count - index);
+            stagger_steps * distance as f32 / (count / 2) as f32
+        }
+    }
+}
+
+/// How long a transition from `current` to `target` takes until the last LED settles.
+///
+/// Counts the steps from `transition_step_count` plus the extra steps the last LED lags
+/// behind under `power_stagger_ms`, each `transition_delay_ms` long.
+pub fn transition_duration(current: &[Color], target: &[Color], config: &Config) -> Duration {
+    let steps = transition_step_count(current, target, config);
+    let steps = steps + stagger_steps(config).ceil() as usize;
+    Duration::from_millis(config.transition_delay_ms) * steps as u32
+}
+
+/// How many transition steps `power_stagger_ms` spreads the LEDs' ramps across.
+fn stagger_steps(config: &Config) -> f32 {
+    if config.transition_delay_ms > 0 {
+        config.power_stagger_ms as f32 / config.transition_delay_ms as f32
+    } else {
+        0.0
+    }
+}
+
+/// One transition between two frames, with each LED's ramp lagged by its stagger.
+struct TransitionSpan<'a> {
+    from: &'a [Srgb<f32>],
+    to: &'a [Srgb<f32>],
+    /// Steps each LED's own ramp takes.
+    steps: usize,
+    /// Lag of the last LED's ramp behind the first, in steps.
+    stagger_steps: f32,
+}
+
+impl TransitionSpan<'_> {
+    /// Write the colors of step `step` (1-based) into `out`.
+    fn fill_step(&self, step: usize, out: &mut [Color], config: &Config) {
+        let led_count = out.len();
+        out.par_iter_mut().enumerate().for_each(|(i, buf)| {
+            let lag = stagger_lag(i, led_count, self.stagger_steps, config.led_edges);
+            let t = ease((step as f32 - lag) / self.steps as f32, config.easing);
+            let new_color =
+                interpolate_color(config.interpolation_space, self.from[i], self.to[i], t);
+            *buf = srgb_to_color(new_color);
+        });
+    }
+}
+
+/// The connected devices and output corrections a transition writes its frames to.
+pub struct FrameSink<'a> {
+    pub client: &'a OpenRGB<tokio::net::TcpStream>,
+    pub devices: &'a [TargetDevice],
+    pub output: &'a OutputStage,
+}
+
+/// Smoothly transition `current` colors to `target` colors, interpolating in the configured
+/// `interpolation_space`.
+///
+/// # Arguments
+///
+/// * `sink` - The connected devices the intermediate frames are written to.
+/// * `current` - A mutable reference to the slice of current LED colors.
+/// * `target` - A slice of target LED colors.
+/// * `config` - The application configuration.
+/// * `step_buffer` - A mutable buffer used to store intermediate colors during each step.
+/// * `stop_signal` - Checked between steps; once raised, the transition ends where it is.
+///
+/// `current` only advances when a frame was actually written, so after an error (or a
+/// stop) it still holds the colors the devices show.
+pub async fn smooth_transition(
+    sink: &FrameSink<'_>,
+    current: &mut [Color],
+    target: &[Color],
+    config: &Config,
+    step_buffer: &mut [Color],
+    stop_signal: &AtomicBool,
+) -> Result<(), OpenRGBError> {
+    if current.len() != target.len() || current.is_empty() {
+        return Ok(());
+    }
+    let curr_srgb: Vec<Srgb<f32>> = current.iter().map(|&c| color_to_srgb(c)).collect();
+    let targ_srgb: Vec<Srgb<f32>> = target.iter().map(|&c| color_to_srgb(c)).collect();
+    let mut throttle = WriteThrottle::new(Duration::from_millis(config.update_interval_ms));
+    let steps = transition_step_count(current, target, config);
+
+    // Staggered LEDs start later, so keep stepping until the last one has finished
+    let stagger_steps = stagger_steps(config);
+    let total_steps = steps + stagger_steps.ceil() as usize;
+
+    for step in 1..=total_steps {
+        let span = TransitionSpan {
+            from: &curr_srgb,
+            to: &targ_srgb,
+            steps,
+            stagger_steps,
+        };
+        span.fill_step(step, step_buffer, config);
+
+        // Coalesce intermediate steps into at most one device frame per write interval,
+        // but always send the final step so the device settles on the target.
+        if throttle.should_write(Instant::now(), step == total_steps) {
+            let verify = config.verify_writes.then_some(config.verify_tolerance);
+            write_frame(sink.client, sink.devices, sink.output, step_buffer, verify).await?;
+            current.copy_from_slice(step_buffer);
+        }
+        let delay = Duration::from_millis(config.transition_delay_ms);
+        if !sleep_unless_stopped(delay, stop_signal).await {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Limits device writes to one per `interval`, so transition steps are coalesced into
+/// whole-device frames at a fixed rate.
+struct WriteThrottle {
+    interval: Duration,
+    last_write: Option<Instant>,
+}
+
+impl WriteThrottle {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_write: None,
+        }
+    }
+
+    /// Whether to write a frame at `now`, recording it if so. `force` always writes, for
+    /// the final step of a transition.
+    fn should_write(&mut self, now: Instant, force: bool) -> bool {
+        let due = self.last_write.is_none_or(|at| now.duration_since(at) >= self.interval);
+        if force || due {
+            self.last_write = Some(now);
+        }
+        force || due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DeviceConfig, Easing};
+    use image::Rgba;
+
+    const RED: Color = Color { r: 255, g: 0, b: 0 };
+    const GREEN: Color = Color { r: 0, g: 255, b: 0 };
+    const BLUE: Color = Color { r: 0, g: 0, b: 255 };
+    const WHITE: Color = Color { r: 255, g: 255, b: 255 };
+    const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+
+    /// A config for `num_leds` LEDs that passes colors through unchanged.
+    fn neutral_config(num_leds: usize) -> Config {
+        Config {
+            devices: vec![DeviceConfig {
+                num_leds,
+                ..DeviceConfig::default()
+            }],
+            brightness_factor: 1.0,
+            saturation_factor: 1.0,
+            ..Config::default()
+        }
+    }
+
+    /// A `width` x `height` frame colored by `color_at(x, y)`.
+    fn frame(width: u32, height: u32, color_at: impl Fn(u32, u32) -> Color) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let Color { r, g, b } = color_at(x, y);
+            Rgba([r, g, b, 255])
+        })
+    }
+
+    #[test]
+    fn left_red_right_blue() {
+        let image = frame(64, 32, |x, _| if x < 32 { RED } else { BLUE });
+        let colors = compute_segment_colors(&image, &neutral_config(2)).unwrap();
+        assert_eq!(colors, [RED, BLUE]);
+    }
+
+    #[test]
+    fn grid_colors_in_row_major_order() {
+        let config = Config {
+            segmentation: SegmentationMode::Grid { rows: 2, cols: 2 },
+            ..neutral_config(4)
+        };
+        let image = frame(64, 32, |x, y| match (x < 32, y < 16) {
+            (true, true) => RED,
+            (false, true) => GREEN,
+            (true, false) => BLUE,
+            (false, false) => WHITE,
+        });
+        let colors = compute_segment_colors(&image, &config).unwrap();
+        assert_eq!(colors, [RED, GREEN, BLUE, WHITE]);
+    }
+
+    #[test]
+    fn uniform_frame_fills_every_led() {
+        let image = frame(200, 20, |_, _| GREEN);
+        let colors = compute_segment_colors(&image, &neutral_config(5)).unwrap();
+        assert_eq!(colors, [GREEN; 5]);
+    }
+
+    #[test]
+    fn vertical_columns_include_the_last_pixel() {
+        let index = |x| {
+            led_index_for_pixel(x, 0, 100, 10, SegmentationMode::Vertical, 3, Flips::default())
+        };
+        assert_eq!(index(0), Some(0));
+        assert_eq!(index(33), Some(0));
+        assert_eq!(index(34), Some(1));
+        assert_eq!(index(99), Some(2));
+        assert_eq!(index(100), None);
+    }
+
+    #[test]
+    fn horizontal_rows_top_to_bottom() {
+        let index = |y| {
+            led_index_for_pixel(0, y, 10, 40, SegmentationMode::Horizontal, 4, Flips::default())
+        };
+        assert_eq!(index(0), Some(0));
+        assert_eq!(index(10), Some(1));
+        assert_eq!(index(39), Some(3));
+        assert_eq!(index(40), None);
+    }
+
+    #[test]
+    fn grid_cells_are_row_major() {
+        let grid = SegmentationMode::Grid { rows: 2, cols: 3 };
+        let index = |x, y| {
+            led_index_for_pixel(x, y, 30, 20, grid, 6, Flips::default())
+        };
+        assert_eq!(index(0, 0), Some(0));
+        assert_eq!(index(29, 0), Some(2));
+        assert_eq!(index(0, 19), Some(3));
+        assert_eq!(index(15, 15), Some(4));
+        assert_eq!(index(29, 19), Some(5));
+    }
+
+    #[test]
+    fn flips_mirror_the_frame() {
+        let horizontal = Flips {
+            horizontal: true,
+            vertical: false,
+        };
+        let vertical = Flips {
+            horizontal: false,
+            vertical: true,
+        };
+        let columns = |x, flips| {
+            led_index_for_pixel(x, 0, 100, 10, SegmentationMode::Vertical, 4, flips)
+        };
+        assert_eq!(columns(0, horizontal), Some(3));
+        assert_eq!(columns(99, horizontal), Some(0));
+        assert_eq!(columns(0, vertical), Some(0));
+
+        let rows = |y, flips| {
+            led_index_for_pixel(0, y, 10, 100, SegmentationMode::Horizontal, 4, flips)
+        };
+        assert_eq!(rows(0, vertical), Some(3));
+        assert_eq!(rows(99, vertical), Some(0));
+        assert_eq!(rows(0, horizontal), Some(0));
+    }
+
+    #[test]
+    fn no_segments_or_outside_pixels_map_nowhere() {
+        let flips = Flips::default();
+        for mode in [
+            SegmentationMode::Vertical,
+            SegmentationMode::Horizontal,
+            SegmentationMode::Grid { rows: 0, cols: 0 },
+            SegmentationMode::Edge,
+            SegmentationMode::Radial,
+        ] {
+            assert_eq!(led_index_for_pixel(5, 5, 10, 10, mode, 0, flips), None);
+            assert_eq!(led_index_for_pixel(10, 5, 10, 10, mode, 4, flips), None);
+            assert_eq!(led_index_for_pixel(5, 10, 10, 10, mode, 4, flips), None);
+        }
+    }
+
+    #[test]
+    fn edge_segments_run_clockwise_around_the_border() {
+        // A 40x20 border is 120 px long, so each of 12 LEDs covers 10 px of it
+        let index = |x, y| {
+            led_index_for_pixel(x, y, 40, 20, SegmentationMode::Edge, 12, Flips::default())
+        };
+        assert_eq!(index(0, 0), Some(0));
+        assert_eq!(index(39, 0), Some(3));
+        assert_eq!(index(39, 10), Some(5));
+        assert_eq!(index(20, 19), Some(7));
+        assert_eq!(index(0, 10), Some(10));
+        assert_eq!(index(0, 1), Some(11));
+        // Inner pixels go to the nearest edge
+        assert_eq!(index(5, 10), index(0, 10));
+        assert_eq!(index(20, 3), index(20, 0));
+    }
+
+    #[test]
+    fn radial_segments_sweep_clockwise_from_the_top() {
+        let index = |x, y, flips| {
+            led_index_for_pixel(x, y, 20, 20, SegmentationMode::Radial, 4, flips)
+        };
+        let none = Flips::default();
+        assert_eq!(index(15, 2, none), Some(0));
+        assert_eq!(index(15, 15, none), Some(1));
+        assert_eq!(index(3, 15, none), Some(2));
+        assert_eq!(index(3, 3, none), Some(3));
+        // Just either side of 12 o'clock are the first and last LED
+        assert_eq!(index(10, 0, none), Some(0));
+        assert_eq!(index(9, 0, none), Some(3));
+
+        let mirrored = Flips {
+            horizontal: true,
+            vertical: false,
+        };
+        assert_eq!(index(15, 2, mirrored), Some(3));
+        assert_eq!(index(3, 15, mirrored), Some(1));
+    }
+
+    /// Read every pixel of `image` the way the accumulators do.
+    fn read_rows(image: &RgbaImage) -> Vec<Option<Vec<[u8; 4]>>> {
+        let (width, stride) = (image.width() as usize, row_stride(image));
+        (0..image.height() as usize)
+            .map(|y| {
+                let row = row_pixels(image.as_raw(), y, stride, width)?;
+                Some((0..width).map(|x| read_pixel(row, x * 4)).collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn trailing_bytes_do_not_shear_rows() {
+        let (red, blue) = ([255, 0, 0, 255], [0, 0, 255, 255]);
+        // Two tight rows of 3 pixels, then 8 bytes that belong to no row
+        let raw: Vec<u8> = [[red; 3], [blue; 3]].concat().concat();
+        let raw = [raw, vec![0xAA; 8]].concat();
+        let image = RgbaImage::from_raw(3, 2, raw).unwrap();
+        assert_eq!(image.as_raw().len(), 32);
+        assert_eq!(row_stride(&image), 12);
+        assert_eq!(read_rows(&image), [Some(vec![red; 3]), Some(vec![blue; 3])]);
+    }
+
+    #[test]
+    fn rows_outside_the_buffer_are_skipped() {
+        let raw: Vec<u8> = [[0, 255, 0, 255]; 5].concat();
+        // The last pixel of the second row is missing
+        assert!(row_pixels(&raw, 0, 12, 3).is_some());
+        assert_eq!(row_pixels(&raw, 1, 12, 3), None);
+        assert_eq!(row_pixels(&raw, usize::MAX, 12, 3), None);
+        assert_eq!(row_pixels(&[], 0, 12, 3), None);
+    }
+
+    const CONTROLLERS: [&str; 3] = ["Corsair K70 Keyboard", "Razer Mouse", "ARGB Strip 2"];
+
+    #[test]
+    fn strict_match_ignores_keyboards() {
+        assert_eq!(match_device_name(CONTROLLERS, "Desk Strip", true, true), None);
+        assert_eq!(match_device_name(CONTROLLERS, "Razer", true, true), None);
+        assert_eq!(match_device_name(CONTROLLERS, "Razer Mouse", true, true), Some(1));
+    }
+
+    #[test]
+    fn loose_match_prefers_the_named_device() {
+        assert_eq!(match_device_name(CONTROLLERS, "Strip", false, true), Some(2));
+        assert_eq!(match_device_name(CONTROLLERS, "Desk Strip", false, true), Some(0));
+        assert_eq!(match_device_name(CONTROLLERS, "Desk Strip", false, false), None);
+        assert_eq!(match_device_name([], "Strip", false, true), None);
+    }
+
+    #[test]
+    fn toggling_led_count_resizes_buffers() {
+        let mut buffers = LedBuffers::new(4);
+        buffers.current = vec![RED, RED, BLUE, BLUE];
+        for led_count in [2, 8, 8, 0, 3, 4] {
+            buffers.resize(led_count);
+            assert_eq!(buffers.current.len(), led_count);
+            assert_eq!(buffers.step.len(), led_count);
+        }
+        assert!(!buffers.resize(4));
+
+        // Shrinking keeps the colors, resampled onto the new count
+        let mut buffers = LedBuffers::new(4);
+        buffers.current = vec![RED, RED, BLUE, BLUE];
+        assert!(buffers.resize(2));
+        assert_eq!(buffers.current, [RED, BLUE]);
+    }
+
+    #[test]
+    fn averager_restarts_when_led_count_changes() {
+        let mut averager = TargetAverager::default();
+        averager.add(&[RED; 3]);
+        averager.add(&[BLUE; 5]);
+        assert_eq!(averager.take_average(), Some(vec![BLUE; 5]));
+        assert_eq!(averager.take_average(), None);
+    }
+
+    #[test]
+    fn poisoned_status_is_still_readable() {
+        let status = Arc::new(Mutex::new(SyncStatus::default()));
+        let writer = Arc::clone(&status);
+        let panicked = std::thread::spawn(move || {
+            let mut status = writer.lock().unwrap();
+            status.frame_count = 7;
+            panic!("sync thread failed");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(status.is_poisoned());
+
+        assert_eq!(lock_or_recover(&status).frame_count, 7);
+        // Recovering clears the poison, so later locks succeed normally
+        assert!(!status.is_poisoned());
+        assert_eq!(status.lock().unwrap().frame_count, 7);
+    }
+
+    /// How many of `steps` transition steps, `step_ms` apart, a throttle writes.
+    fn coalesced_writes(steps: u64, step_ms: u64, interval_ms: u64) -> usize {
+        let start = Instant::now();
+        let mut throttle = WriteThrottle::new(Duration::from_millis(interval_ms));
+        (1..=steps)
+            .filter(|&step| {
+                let now = start + Duration::from_millis(step * step_ms);
+                throttle.should_write(now, step == steps)
+            })
+            .count()
+    }
+
+    #[test]
+    fn coalescing_follows_the_write_rate() {
+        // 100 steps over 1 s at one write per 50 ms: 20 writes plus the final step, not 100
+        assert_eq!(coalesced_writes(100, 10, 50), 21);
+        assert_eq!(coalesced_writes(100, 10, 100), 11);
+        // Without an interval every step is written
+        assert_eq!(coalesced_writes(100, 10, 0), 100);
+        // The final step is written even if it isn't due
+        assert_eq!(coalesced_writes(3, 10, 1000), 2);
+    }
+
+    #[test]
+    fn undersampled_segments_trigger_a_finer_step() {
+        // At step 10, a 40 px wide frame is sampled at x = 0, 10, 20, 30, so the last of
+        // five 8 px segments gets no samples
+        let image = frame(40, 20, |_, _| GREEN);
+        let coarse = Config {
+            sample_step: 10,
+            min_segment_samples: 0,
+            ..neutral_config(5)
+        };
+        let colors = compute_segment_colors(&image, &coarse).unwrap();
+        assert_ne!(colors[4], GREEN);
+
+        let guarded = Config {
+            min_segment_samples: 1,
+            ..coarse
+        };
+        assert_eq!(compute_segment_colors(&image, &guarded).unwrap(), [GREEN; 5]);
+    }
+
+    #[test]
+    fn verification_flags_mismatched_readback() {
+        let sent = [RED, GREEN, BLUE];
+        assert_eq!(count_color_mismatches(&sent, &sent, 0), 0);
+
+        // A device that applied a dimmer green and dropped the last LED
+        let reported = [RED, Color { r: 0, g: 240, b: 0 }];
+        assert_eq!(count_color_mismatches(&sent, &reported, 0), 2);
+        assert_eq!(count_color_mismatches(&sent, &reported, 20), 1);
+    }
+
+    /// Retry an attempt that fails `failures` times before succeeding, returning the
+    /// result and the number of attempts made.
+    async fn retry_failing(
+        failures: usize,
+        wait_ms: u64,
+        stop: bool,
+    ) -> (Result<Option<usize>, usize>, usize) {
+        let attempts = std::cell::Cell::new(0);
+        let stop_signal = AtomicBool::new(stop);
+        let result = retry_within(
+            Duration::from_millis(wait_ms),
+            Duration::from_millis(5),
+            &stop_signal,
+            || {
+                attempts.set(attempts.get() + 1);
+                let attempt = attempts.get();
+                async move {
+                    if attempt > failures {
+                        Ok(attempt)
+                    } else {
+                        Err(attempt)
+                    }
+                }
+            },
+        )
+        .await;
+        (result, attempts.get())
+    }
+
+    #[tokio::test]
+    async fn connect_is_retried_until_it_succeeds() {
+        assert_eq!(retry_failing(3, 1000, false).await, (Ok(Some(4)), 4));
+        assert_eq!(retry_failing(0, 0, false).await, (Ok(Some(1)), 1));
+    }
+
+    #[tokio::test]
+    async fn connect_gives_up_after_the_wait_window() {
+        // Without a wait window the first failure is final
+        assert_eq!(retry_failing(3, 0, false).await, (Err(1), 1));
+        let (result, attempts) = retry_failing(usize::MAX, 30, false).await;
+        assert!(result.is_err() && attempts > 1 && attempts < 20, "{attempts} attempts");
+    }
+
+    #[tokio::test]
+    async fn connect_wait_stops_on_the_stop_signal() {
+        assert_eq!(retry_failing(3, 1000, true).await, (Ok(None), 1));
+    }
+
+    #[test]
+    fn sends_only_the_leds_the_device_has() {
+        let device = TargetDevice {
+            id: 0,
+            name: "Strip".to_string(),
+            num_leds: effective_led_count(30, 18),
+            base_colors: None,
+            controlled_leds: None,
+            brightness_scale: 1.0,
+        };
+        let output = OutputStage::new(&Config::default()).unwrap();
+        let sent = device.frame(&[RED; 30], &output);
+        assert_eq!(sent, [RED; 18]);
+
+        // Fewer configured LEDs than the device has are kept, and unknown counts ignored
+        assert_eq!(effective_led_count(10, 18), 10);
+        assert_eq!(effective_led_count(30, 0), 30);
+    }
+
+    #[test]
+    fn scene_cuts_skip_the_debounce() {
+        let config = Config {
+            color_change_threshold: 0.05,
+            scene_cut_threshold: 0.5,
+            debounce_duration_ms: 200,
+            ..Config::default()
+        };
+        let just_now = Duration::from_millis(10);
+        let later = Duration::from_millis(250);
+
+        // Small changes wait out the debounce
+        assert!(!transition_allowed(0.2, just_now, &config));
+        assert!(transition_allowed(0.2, later, &config));
+        // Large ones go through immediately
+        assert!(transition_allowed(0.8, just_now, &config));
+        // Changes below the threshold never transition
+        assert!(!transition_allowed(0.01, later, &config));
+
+        let no_scene_cuts = Config {
+            scene_cut_threshold: 0.0,
+            ..config
+        };
+        assert!(!transition_allowed(0.8, just_now, &no_scene_cuts));
+    }
+
+    #[test]
+    fn debounce_target_is_the_average_of_the_window() {
+        let mut averager = TargetAverager::default();
+        averager.add(&[Color { r: 200, g: 0, b: 10 }, BLUE]);
+        averager.add(&[Color { r: 100, g: 50, b: 20 }, BLUE]);
+        averager.add(&[Color { r: 0, g: 100, b: 31 }, RED]);
+        assert_eq!(
+            averager.take_average(),
+            Some(vec![Color { r: 100, g: 50, b: 20 }, Color { r: 85, g: 0, b: 170 }])
+        );
+        // The next window starts empty
+        assert_eq!(averager.take_average(), None);
+    }
+
+    #[test]
+    fn small_changes_take_fewer_steps() {
+        let config = Config {
+            transition_steps: 50,
+            min_transition_steps: 2,
+            scale_transition_steps: true,
+            ..Config::default()
+        };
+        let gray = Color { r: 128, g: 128, b: 128 };
+        let nudged = Color { r: 133, g: 128, b: 128 };
+        let small = transition_step_count(&[gray], &[nudged], &config);
+        let large = transition_step_count(&[gray], &[WHITE], &config);
+        let full = transition_step_count(&[Color { r: 0, g: 0, b: 0 }], &[WHITE], &config);
+        assert!(small < large && large < full, "{small} {large} {full}");
+        assert_eq!(small, 2);
+        assert_eq!(full, 50);
+
+        // Without scaling every transition takes transition_steps
+        let fixed = Config {
+            scale_transition_steps: false,
+            ..config
+        };
+        assert_eq!(transition_step_count(&[gray], &[nudged], &fixed), 50);
+    }
+
+    #[test]
+    fn u64_and_f32_accumulators_agree() {
+        let image = frame(320, 180, |x, y| Color {
+            r: (x * 255 / 319) as u8,
+            g: (y * 255 / 179) as u8,
+            b: ((x + y) % 256) as u8,
+        });
+        for gamma in [1.0, 2.2] {
+            let config = |accumulator| Config {
+                accumulator,
+                gamma,
+                sample_step: 3,
+                segmentation: SegmentationMode::Grid { rows: 2, cols: 4 },
+                ..neutral_config(8)
+            };
+            let integer = compute_segment_colors(&image, &config(Accumulator::U64)).unwrap();
+            let float = compute_segment_colors(&image, &config(Accumulator::F32)).unwrap();
+            for (i, f) in integer.iter().zip(&float) {
+                let close = |a: u8, b: u8| a.abs_diff(b) <= 1;
+                assert!(close(i.r, f.r) && close(i.g, f.g) && close(i.b, f.b), "{i:?} vs {f:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn eight_k_frames_are_capped() {
+        let capped = cap_compute_resolution(RgbaImage::new(7680, 4320), 640, 0);
+        assert_eq!(capped.dimensions(), (640, 360));
+
+        // The tighter of the two limits wins, and the aspect survives either way
+        let (width, height) = cap_compute_resolution(RgbaImage::new(1920, 1080), 640, 300)
+            .dimensions();
+        assert!(width <= 640 && height <= 300, "{width}x{height}");
+        assert!((width as f32 / height as f32 - 16.0 / 9.0).abs() < 0.01, "{width}x{height}");
+
+        let small = cap_compute_resolution(RgbaImage::new(320, 180), 640, 360);
+        assert_eq!(small.dimensions(), (320, 180));
+    }
+
+    #[test]
+    fn staggered_leds_reach_full_brightness_at_different_steps() {
+        let config = Config {
+            transition_delay_ms: 10,
+            power_stagger_ms: 40,
+            easing: Easing::Linear,
+            ..neutral_config(5)
+        };
+        let (from, to) = (vec![color_to_srgb(BLACK); 5], vec![color_to_srgb(WHITE); 5]);
+        let span = TransitionSpan {
+            from: &from,
+            to: &to,
+            steps: 8,
+            stagger_steps: stagger_steps(&config),
+        };
+        let total_steps = 8 + span.stagger_steps.ceil() as usize;
+
+        // The first step at which each LED shows full white
+        let mut reached = [None; 5];
+        let mut out = [BLACK; 5];
+        for step in 1..=total_steps {
+            span.fill_step(step, &mut out, &config);
+            for (reached, &color) in reached.iter_mut().zip(&out) {
+                if color == WHITE {
+                    reached.get_or_insert(step);
+                }
+            }
+        }
+        assert_eq!(reached, [Some(8), Some(9), Some(10), Some(11), Some(12)]);
+
+        // Without a stagger they all arrive together
+        let span = TransitionSpan {
+            stagger_steps: 0.0,
+            ..span
+        };
+        span.fill_step(8, &mut out, &config);
+        assert_eq!(out, [WHITE; 5]);
+        span.fill_step(7, &mut out, &config);
+        assert!(out.iter().all(|&color| color == out[0] && color != WHITE), "{out:?}");
+    }
+
+    #[test]
+    fn fit_letterboxes_and_fill_crops() {
+        // 16:9 content with red and blue bars at its sides, on a 4:3 grid of LEDs
+        let image = frame(160, 90, |x, _| match x {
+            0..20 => RED,
+            140.. => BLUE,
+            _ => GREEN,
+        });
+        let fill = Color { r: 16, g: 32, b: 48 };
+        let config = |aspect_mode| Config {
+            segmentation: SegmentationMode::Grid { rows: 8, cols: 4 },
+            layout_aspect: 4.0 / 3.0,
+            aspect_mode,
+            aspect_fill_color: [fill.r, fill.g, fill.b],
+            ..neutral_config(32)
+        };
+
+        // Fit pads the frame above and below: the outer rows show the fill color, while
+        // the bars still reach the outer columns of the rows in between
+        let fit = compute_segment_colors(&image, &config(AspectMode::Fit)).unwrap();
+        assert_eq!(fit[..4], [fill; 4]);
+        assert_eq!(fit[28..], [fill; 4]);
+        for row in fit[4..28].chunks(4) {
+            assert!(row[0].r > 0 && row[3].b > 0 && !row.contains(&fill), "{row:?}");
+        }
+
+        // Fill crops the sides instead: the bars are gone and no LED shows the fill color
+        let filled = compute_segment_colors(&image, &config(AspectMode::Fill)).unwrap();
+        assert_eq!(filled, [GREEN; 32]);
+    }
+
+    #[test]
+    fn fit_padding_is_not_undersampled() {
+        // Fit pads this 16:9 frame to 4:3 above and below, so the top and bottom of the
+        // eight rows lie entirely in the transparent padding
+        let image = frame(160, 90, |_, _| GREEN);
+        let config = Config {
+            segmentation: SegmentationMode::Horizontal,
+            layout_aspect: 4.0 / 3.0,
+            aspect_mode: AspectMode::Fit,
+            min_segment_samples: 4,
+            sample_step: 8,
+            ..neutral_config(8)
+        };
+        let padded = prepare_frame(image, &config, None);
+        let layout = SegmentLayout::new(&config, 8, None);
+        let coarse = accumulate_with_min_samples(&padded, &config, 8, &[0; 256], None);
+        assert_eq!(coarse[0].3, 0);
+        assert_eq!(coarse[7].3, 0);
+        // The padded rows were visited plenty at the coarse step; it wasn't refined
+        assert!(visited_samples(&padded, &layout, 8)[0] >= 4);
+        let fine = accumulate_segments(&padded, &layout, 1, &[0; 256], 0.0);
+        assert!(coarse[2].3 < fine[2].3, "refined to step 1 for the padding");
+
+        // Real content below the minimum still forces a finer step
+        let starved = Config {
+            min_segment_samples: 1000,
+            ..config
+        };
+        let refined = accumulate_with_min_samples(&padded, &starved, 8, &[0; 256], None);
+        assert_eq!(refined, fine);
+    }
+
+    #[test]
+    fn idle_animation_breathes_around_the_held_colors() {
+        let config = Config {
+            idle_animation_after_ms: 5000,
+            idle_animation_amplitude: 0.1,
+            idle_animation_period_ms: 4000,
+            ..neutral_config(2)
+        };
+        let held = [Color { r: 200, g: 100, b: 50 }, Color { r: 40, g: 80, b: 120 }];
+        assert_eq!(idle_frame(&held, Duration::from_millis(4999), &config), None);
+
+        // One breath after the timeout, sampled every 100 ms
+        let reds: Vec<i32> = (0..40)
+            .map(|tick| {
+                let static_for = Duration::from_millis(5000 + tick * 100);
+                let frame = idle_frame(&held, static_for, &config).unwrap();
+                frame[0].r as i32
+            })
+            .collect();
+        assert_eq!(reds[0], 200, "the breath starts at the held brightness");
+        assert!(reds.iter().all(|r| (180..=220).contains(r)), "{reds:?}");
+        assert_eq!(reds.iter().max(), Some(&220));
+        assert_eq!(reds.iter().min(), Some(&180));
+        let mean = reds.iter().sum::<i32>() as f32 / reds.len() as f32;
+        assert!((mean - 200.0).abs() < 1.0, "mean {mean}");
+
+        let disabled = Config {
+            idle_animation_after_ms: 0,
+            ..config
+        };
+        assert_eq!(idle_frame(&held, Duration::from_secs(60), &disabled), None);
+    }
+
+    #[test]
+    fn lanes_share_one_bounded_compute_pool() {
+        use std::sync::atomic::AtomicUsize;
+
+        assert!(shared_compute_pool(0).unwrap().is_none());
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let job = || {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(2));
+            running.fetch_sub(1, Ordering::SeqCst);
+        };
+
+        // Two sync loops averaging at the same time, each asking for a 2-thread pool
+        let pools: Vec<_> = std::thread::scope(|scope| {
+            let lanes: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let pool = shared_compute_pool(2).unwrap().unwrap();
+                        pool.install(|| (0..32).into_par_iter().for_each(|_| job()));
+                        pool
+                    })
+                })
+                .collect();
+            lanes.into_iter().map(|lane| lane.join().unwrap()).collect()
+        });
+        assert!(Arc::ptr_eq(&pools[0], &pools[1]));
+        assert!(peak.load(Ordering::SeqCst) <= 2, "{peak:?} jobs ran at once");
+    }
+
+    #[test]
+    fn ring_gradient_has_no_seam() {
+        // One full turn of the hue wheel around a 12 LED ring: continuous all the way round
+        let ring: Vec<Color> = (0..12)
+            .map(|i| {
+                let hue = palette::RgbHue::from_degrees(i as f32 * 30.0);
+                srgb_to_color(Srgb::from_color(Hsv::new(hue, 1.0, 1.0)))
+            })
+            .collect();
+        let step = |colors: &[Color], i: usize| {
+            let (a, b) = (colors[i], colors[(i + 1) % colors.len()]);
+            a.r.abs_diff(b.r) as u32 + a.g.abs_diff(b.g) as u32 + a.b.abs_diff(b.b) as u32
+        };
+
+        // Smoothed as a ring, the step from LED 11 to LED 0 is like every other step
+        let wrapped = smooth_neighbours(&ring, 1.0, LedEdges::Wrap);
+        let interior = (0..11).map(|i| step(&wrapped, i)).max().unwrap();
+        assert!(step(&wrapped, 11).abs_diff(interior) <= 3, "{wrapped:?}");
+
+        // Treating the ends as a strip's opens a visible jump at the seam
+        let clamped = smooth_neighbours(&ring, 1.0, LedEdges::Clamp);
+        let interior = (0..11).map(|i| step(&clamped, i)).max().unwrap();
+        assert!(step(&clamped, 11) > interior + 50, "{clamped:?}");
+
+        // The staggered ramp meets opposite LED 0 instead of jumping back at the seam
+        let lags: Vec<f32> = (0..12).map(|i| stagger_lag(i, 12, 6.0, LedEdges::Wrap)).collect();
+        assert_eq!(lags, [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 5.0, 4.0, 3.0, 2.0, 1.0]);
+        assert_eq!(stagger_lag(11, 12, 6.0, LedEdges::Clamp), 6.0);
+    }
+
+    #[test]
+    fn spatial_smoothing_is_off_by_default() {
+        let colors = [RED, GREEN, BLUE];
+        let config = Config::default();
+        assert_eq!(smooth_neighbours(&colors, config.spatial_smoothing, config.led_edges), colors);
+        // Uniform colors are left alone at any strength
+        assert_eq!(smooth_neighbours(&[GREEN; 4], 1.0, LedEdges::Wrap), [GREEN; 4]);
+    }
+}
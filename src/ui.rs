@@ -4,13 +4,21 @@
 //! `Config` fields. It uses `ratatui` (based on `tui-rs`) and
 //! `crossterm` for handling user input in a terminal environment.
 
+use std::collections::VecDeque;
 use std::io;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use std::sync::atomic::{AtomicBool, Ordering}; // NEW
 
-use crate::config::Config;
-use crate::sync_loop::{start_sync_loop, SyncStatus};
+use crate::color_picker::swatch;
+use crate::config::{Config, Settings, UiTheme};
+use crate::fields::{ConfigField, FIELDS};
+use crate::diagnostics::write_report;
+use crate::error::KeyBloomError;
+use crate::sync_loop::{
+    list_controller_names, lock_or_recover, query_led_count, show_test_pattern, show_white,
+    start_sync_loop, SyncState, SyncStatus,
+};
 use crossterm::event::{
     self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode, KeyEventKind,
 };
@@ -19,15 +27,89 @@ use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
 use ratatui::backend::{Backend, CrosstermBackend};
-use ratatui::layout::{Alignment, Constraint, Direction};
+use ratatui::layout::{Alignment, Constraint, Direction, Rect};
 use ratatui::style::{Color as RColor, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::{Block, BorderType, Borders, List, ListItem, Paragraph};
+use ratatui::widgets::{
+    Block, BorderType, Borders, Clear, List, ListItem, Paragraph, Sparkline, Wrap,
+};
 use ratatui::{Frame, Terminal};
 use std::thread;
+use tracing::{error, info, warn};
+
+/// An entry of the main menu: a config field or an action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuItem {
+    Field(ConfigField),
+    DetectLeds,
+    ShowWhite,
+    TestPattern,
+    Profile,
+    PickDevice,
+    Theme,
+    Undo,
+    ResetToDefaults,
+    SaveAndSync,
+}
 
-// Define a new error type that implements Send + Sync + 'static
-type AnyError = Box<dyn std::error::Error + Send + Sync + 'static>;
+impl MenuItem {
+    /// The actions listed after the fields, in menu order.
+    const ACTIONS: [MenuItem; 9] = [
+        MenuItem::DetectLeds,
+        MenuItem::ShowWhite,
+        MenuItem::TestPattern,
+        MenuItem::Profile,
+        MenuItem::PickDevice,
+        MenuItem::Theme,
+        MenuItem::Undo,
+        MenuItem::ResetToDefaults,
+        MenuItem::SaveAndSync,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MenuItem::Field(field) => field.spec().label,
+            MenuItem::DetectLeds => "Detect LEDs",
+            MenuItem::ShowWhite => "Show White",
+            MenuItem::TestPattern => "Test Pattern",
+            MenuItem::Profile => "Profile",
+            MenuItem::PickDevice => "Pick Device",
+            MenuItem::Theme => "Theme",
+            MenuItem::Undo => "Undo Last Edit",
+            MenuItem::ResetToDefaults => "Reset to Defaults",
+            MenuItem::SaveAndSync => "Save and Sync",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            MenuItem::Field(field) => field.spec().description,
+            MenuItem::DetectLeds => {
+                "Ask OpenRGB how many LEDs the (first) device has and use that number."
+            }
+            MenuItem::ShowWhite => {
+                "Light every LED white with the current calibration, to tune the channel gains."
+            }
+            MenuItem::TestPattern => {
+                "Light LED 0 red, 1 green, 2 blue and the rest dim white, to check count and order."
+            }
+            MenuItem::Profile => {
+                "Active profile; a new name saves the current settings, empty uses the default."
+            }
+            MenuItem::PickDevice => {
+                "Choose the (first) device from the controllers OpenRGB reports."
+            }
+            MenuItem::Theme => {
+                "Press Enter to cycle the menu colors (saved with the configuration)."
+            }
+            MenuItem::Undo => "Restore the settings from before the last change.",
+            MenuItem::ResetToDefaults => {
+                "Set every option to its default (nothing is written until you save)."
+            }
+            MenuItem::SaveAndSync => "Save current configuration and exit the menu.",
+        }
+    }
+}
 
 /// Represents the TUI's input mode for editing a configuration field or just navigating.
 #[derive(Debug, PartialEq, Clone)]
@@ -35,16 +117,23 @@ pub enum InputMode {
     Normal,
     Editing,
     Syncing,
+    PickingDevice,
+    /// Asking whether to reset every setting to its default.
+    ConfirmingReset,
 }
 
+/// Number of samples kept for the sync screen's brightness waveform.
+const HISTORY_LEN: usize = 240;
+
+/// How long the sync screen shows a connection timeout before returning to the menu.
+const TIMEOUT_NOTICE_DURATION: Duration = Duration::from_secs(3);
+
 /// The main application state for the TUI.
 pub struct App {
     /// The active configuration for KeyBloom.
     pub config: Config,
-    /// List of configuration options in textual form.
-    pub options: Vec<&'static str>,
-    /// Descriptions for each configuration option to display to the user.
-    pub descriptions: Vec<&'static str>,
+    /// The menu entries: every field of `FIELDS`, then the actions.
+    pub items: Vec<MenuItem>,
     /// Indicates whether we're in `Normal`, `Editing`, or `Syncing` mode.
     pub input_mode: InputMode,
     /// The temporary buffer that holds user input when editing.
@@ -53,58 +142,64 @@ pub struct App {
     pub list_state: ratatui::widgets::ListState,
     /// Indicates whether the UI needs to be redrawn.
     pub dirty: bool,
+    /// Validation errors from the last rejected edit, shown in the description pane.
+    pub validation_errors: Vec<String>,
+    /// Name of the active profile; `None` edits the default config file.
+    pub active_profile: Option<String>,
+    /// Controller names offered by the device picker.
+    pub device_choices: Vec<String>,
+    /// Selection within `device_choices`.
+    pub device_picker_state: ratatui::widgets::ListState,
+    /// Result of the last menu action (e.g. where a diagnostics report was written).
+    pub notice: Option<String>,
+    /// The config before the last change, restored by "Undo Last Edit".
+    pub undo: Option<Config>,
+    /// Average brightness (0-255) of the most recent frames, oldest first, for the sync
+    /// screen's waveform. Sampled once per redraw and bounded to `HISTORY_LEN` entries.
+    pub brightness_history: VecDeque<u64>,
+    /// `SyncStatus::frame_count` of the newest frame in `brightness_history`.
+    pub history_frame: usize,
     /// Shared synchronization status (updated by the sync loop).
     pub sync_status: Arc<Mutex<SyncStatus>>,
     /// Handle to the running sync loop, if any.
     pub sync_handle: Option<thread::JoinHandle<()>>,
+    /// When the last sync loop finished, for the restart cooldown.
+    pub last_stopped: Option<Instant>,
+    /// When the sync screen started showing a connection timeout.
+    pub timed_out_at: Option<Instant>,
     /// Shared stop signal to gracefully terminate the sync loop.
     pub stop_signal: Arc<AtomicBool>, // NEW
 }
 
 impl App {
     /// Create a new `App` instance from a given `Config`.
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: Config, active_profile: Option<String>) -> Self {
         let mut list_state = ratatui::widgets::ListState::default();
         list_state.select(Some(0));
 
         App {
             config,
-            options: vec![
-                "Number of LEDs",
-                "Transition Steps",
-                "Transition Delay (ms)",
-                "Frame Delay (ms)",
-                "Color Change Threshold",
-                "Brightness Factor",
-                "Saturation Factor",
-                "Debounce Duration (ms)",
-                "OpenRGB Host",
-                "OpenRGB Port",
-                "Device Name",
-                "Monitor Index",
-                "Save and Sync",
-            ],
-            descriptions: vec![
-                "Set the number of LEDs on your device.",
-                "Define how many steps the color transition should take.",
-                "Specify the delay (ms) between each transition step.",
-                "Set the delay (ms) between each frame capture.",
-                "Threshold for significant color changes (0.0-1.0).",
-                "Factor to adjust overall brightness (larger = brighter).",
-                "Factor to adjust color saturation (larger = more vibrant).",
-                "Minimum duration (ms) between transitions to prevent rapid changes.",
-                "Hostname or IP of the OpenRGB server.",
-                "Port number of the OpenRGB server.",
-                "Name of the OpenRGB device to control.",
-                "Index of the monitor to capture (0-based).",
-                "Save current configuration and exit the menu.",
-            ],
+            items: FIELDS
+                .iter()
+                .map(|spec| MenuItem::Field(spec.field))
+                .chain(MenuItem::ACTIONS)
+                .collect(),
             input_mode: InputMode::Normal,
             input: String::new(),
             list_state,
             dirty: true,
+            validation_errors: Vec::new(),
+            active_profile,
+            device_choices: Vec::new(),
+            device_picker_state: ratatui::widgets::ListState::default(),
+            notice: None,
+            undo: None,
+            brightness_history: VecDeque::with_capacity(HISTORY_LEN),
+            history_frame: 0,
             sync_status: Arc::new(Mutex::new(SyncStatus::default())),
             sync_handle: None,
+            last_stopped: None,
+            timed_out_at: None,
             stop_signal: Arc::new(AtomicBool::new(false)), // NEW
         }
     }
@@ -112,8 +207,10 @@ impl App {
     /// Move selection down in the options list.
     pub fn next(&mut self) {
         if let Some(selected) = self.list_state.selected() {
-            let next = if selected >= self.options.len() - 1 { 0 } else { selected + 1 };
+            let next = if selected >= self.items.len() - 1 { 0 } else { selected + 1 };
             self.list_state.select(Some(next));
+            self.validation_errors.clear();
+            self.notice = None;
             self.dirty = true;
         }
     }
@@ -122,11 +219,13 @@ impl App {
     pub fn previous(&mut self) {
         if let Some(selected) = self.list_state.selected() {
             let prev = if selected == 0 {
-                self.options.len() - 1
+                self.items.len() - 1
             } else {
                 selected - 1
             };
             self.list_state.select(Some(prev));
+            self.validation_errors.clear();
+            self.notice = None;
             self.dirty = true;
         }
     }
@@ -140,22 +239,16 @@ impl App {
             InputMode::Normal => InputMode::Editing,
             InputMode::Editing => InputMode::Normal,
             InputMode::Syncing => InputMode::Syncing,
+            InputMode::PickingDevice => InputMode::PickingDevice,
+            InputMode::ConfirmingReset => InputMode::ConfirmingReset,
         };
         if self.input_mode == InputMode::Editing {
-            let selected = self.list_state.selected().unwrap_or(0);
-            self.input = match selected {
-                0 => self.config.num_leds.to_string(),
-                1 => self.config.transition_steps.to_string(),
-                2 => self.config.transition_delay_ms.to_string(),
-                3 => self.config.frame_delay_ms.to_string(),
-                4 => self.config.color_change_threshold.to_string(),
-                5 => self.config.brightness_factor.to_string(),
-                6 => self.config.saturation_factor.to_string(),
-                7 => self.config.debounce_duration_ms.to_string(),
-                8 => self.config.openrgb_host.clone(),
-                9 => self.config.openrgb_port.to_string(),
-                10 => self.config.device_name.clone(),
-                11 => self.config.monitor_index.to_string(),
+            self.input = match self.selected_item() {
+                MenuItem::Field(field) => field.get(&self.config),
+                MenuItem::Profile => {
+                    self.notice = Some(format!("Profiles: {}", Config::list_profiles().join(", ")));
+                    self.active_profile.clone().unwrap_or_default()
+                }
                 _ => "".to_string(),
             };
         } else if self.input_mode == InputMode::Normal {
@@ -165,66 +258,257 @@ impl App {
         self.dirty = true;
     }
 
+    /// The highlighted menu entry.
+    pub fn selected_item(&self) -> MenuItem {
+        let selected = self.list_state.selected().unwrap_or(0);
+        self.items[selected.min(self.items.len() - 1)]
+    }
+
     /// Update the `config` with the contents of `self.input` for the selected option.
     ///
     /// Tries to parse numeric fields or assigns for string fields. If parsing fails,
-    /// the old value is retained.
+    /// the old value is retained. Edits that fail `Config::validate` are rolled back and
+    /// the reasons are stored in `validation_errors`.
     pub fn update_config(&mut self) {
-        if let Some(selected) = self.list_state.selected() {
-            match selected {
-                0 => {
-                    self.config.num_leds = self.input.parse().unwrap_or(self.config.num_leds);
-                }
-                1 => {
-                    self.config.transition_steps =
-                        self.input.parse().unwrap_or(self.config.transition_steps);
-                }
-                2 => {
-                    self.config.transition_delay_ms =
-                        self.input.parse().unwrap_or(self.config.transition_delay_ms);
-                }
-                3 => {
-                    self.config.frame_delay_ms =
-                        self.input.parse().unwrap_or(self.config.frame_delay_ms);
-                }
-                4 => {
-                    self.config.color_change_threshold =
-                        self.input.parse().unwrap_or(self.config.color_change_threshold);
-                }
-                5 => {
-                    self.config.brightness_factor =
-                        self.input.parse().unwrap_or(self.config.brightness_factor);
-                }
-                6 => {
-                    self.config.saturation_factor =
-                        self.input.parse().unwrap_or(self.config.saturation_factor);
-                }
-                7 => {
-                    self.config.debounce_duration_ms =
-                        self.input.parse().unwrap_or(self.config.debounce_duration_ms);
-                }
-                8 => {
-                    self.config.openrgb_host = self.input.clone();
-                }
-                9 => {
-                    self.config.openrgb_port =
-                        self.input.parse().unwrap_or(self.config.openrgb_port);
-                }
-                10 => {
-                    self.config.device_name = self.input.clone();
-                }
-                11 => {
-                    self.config.monitor_index =
-                        self.input.parse().unwrap_or(self.config.monitor_index);
+        let previous = self.config.clone();
+        match self.selected_item() {
+            MenuItem::Field(field) => field.set(&mut self.config, &self.input),
+            MenuItem::Profile => {
+                // Switching replaces the whole config, so there's no edit to validate
+                let name = self.input.trim().to_string();
+                self.switch_profile((!name.is_empty()).then_some(name));
+                self.dirty = true;
+                return;
+            }
+            _ => {}
+        }
+        match self.config.validate() {
+            Ok(()) => {
+                self.validation_errors.clear();
+                self.remember_undo(previous);
+            }
+            Err(errors) => {
+                self.config = previous;
+                self.validation_errors = errors;
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Step the selected numeric field by its nudge step, `direction` times.
+    ///
+    /// Other entries are left alone. Like typed edits, a result that fails validation is
+    /// rolled back.
+    pub fn nudge_selected(&mut self, direction: f64) {
+        let MenuItem::Field(field) = self.selected_item() else {
+            return;
+        };
+        let previous = self.config.clone();
+        let Some(value) = field.nudge(&mut self.config, direction) else {
+            return;
+        };
+        match self.config.validate() {
+            Ok(()) => {
+                self.validation_errors.clear();
+                self.notice = Some(format!("{}: {value}", field.spec().label));
+                self.remember_undo(previous);
+            }
+            Err(errors) => {
+                self.config = previous;
+                self.validation_errors = errors;
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Fetch the controllers from OpenRGB and open the device picker.
+    pub async fn open_device_picker(&mut self) {
+        match list_controller_names(&self.config).await {
+            Ok(names) if names.is_empty() => {
+                self.validation_errors = vec!["OpenRGB reports no devices.".to_string()];
+            }
+            Ok(names) => {
+                let current = &self.config.primary_device().device_name;
+                let selected = names.iter().position(|name| name == current).unwrap_or(0);
+                self.device_choices = names;
+                self.device_picker_state.select(Some(selected));
+                self.input_mode = InputMode::PickingDevice;
+            }
+            Err(err) => {
+                self.validation_errors = vec![format!("Could not list devices: {err}")];
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Close the device picker, using the highlighted controller if `accept` is set.
+    pub fn close_device_picker(&mut self, accept: bool) {
+        if accept {
+            let selected = self.device_picker_state.selected().unwrap_or(0);
+            if let Some(name) = self.device_choices.get(selected).cloned() {
+                let previous = self.config.clone();
+                self.config.primary_device_mut().device_name = name.clone();
+                self.remember_undo(previous);
+                self.notice = Some(format!("Using device '{name}'."));
+            }
+        }
+        self.device_choices.clear();
+        self.input_mode = InputMode::Normal;
+        self.dirty = true;
+    }
+
+    /// Move the device picker selection by `offset`, wrapping around.
+    pub fn move_device_selection(&mut self, offset: isize) {
+        let len = self.device_choices.len() as isize;
+        if len == 0 {
+            return;
+        }
+        let selected = self.device_picker_state.selected().unwrap_or(0) as isize;
+        self.device_picker_state.select(Some((selected + offset).rem_euclid(len) as usize));
+        self.dirty = true;
+    }
+
+    /// Make `name` the active profile and remember it for the next start.
+    ///
+    /// An existing profile is loaded; a new name saves the current settings under it.
+    /// `None` switches back to the default config file.
+    fn switch_profile(&mut self, name: Option<String>) {
+        let result = match &name {
+            Some(name) if Config::list_profiles().contains(name) => {
+                Config::load_profile(name).map(|config| self.config = config)
+            }
+            Some(name) => self.config.save_as(name),
+            None => {
+                self.config = Config::load();
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            self.notice = Some(format!("Failed to switch profile: {err}"));
+            return;
+        }
+        let settings = Settings {
+            last_profile: name.clone(),
+        };
+        if let Err(err) = settings.save() {
+            warn!("Failed to remember the active profile: {err}");
+        }
+        self.notice = Some(match &name {
+            Some(name) => format!("Using profile '{name}'."),
+            None => "Using the default configuration.".to_string(),
+        });
+        self.active_profile = name;
+    }
+
+    /// Keep `previous` for undo, unless the change left the config as it was.
+    fn remember_undo(&mut self, previous: Config) {
+        if toml::to_string(&previous).ok() != toml::to_string(&self.config).ok() {
+            self.undo = Some(previous);
+        }
+    }
+
+    /// Restore the config from before the last change; undoing again redoes it.
+    pub fn undo_last_edit(&mut self) {
+        match self.undo.take() {
+            Some(previous) => {
+                self.undo = Some(std::mem::replace(&mut self.config, previous));
+                self.validation_errors.clear();
+                self.notice = Some("Restored the previous settings.".to_string());
+            }
+            None => self.notice = Some("Nothing to undo.".to_string()),
+        }
+        self.dirty = true;
+    }
+
+    /// Ask for confirmation before resetting to the defaults.
+    pub fn request_reset(&mut self) {
+        self.input_mode = InputMode::ConfirmingReset;
+        self.dirty = true;
+    }
+
+    /// Close the reset prompt, resetting every setting if `confirmed`.
+    ///
+    /// The defaults are only written to disk when the configuration is saved.
+    pub fn finish_reset(&mut self, confirmed: bool) {
+        if confirmed {
+            let previous = std::mem::take(&mut self.config);
+            self.remember_undo(previous);
+            self.validation_errors.clear();
+            self.notice = Some("Reset to defaults; save to keep them.".to_string());
+        }
+        self.input_mode = InputMode::Normal;
+        self.dirty = true;
+    }
+
+    /// Switch to the next UI theme.
+    pub fn cycle_theme(&mut self) {
+        self.config.ui_theme = self.config.ui_theme.next();
+        self.notice = Some(format!("Theme: {:?}", self.config.ui_theme));
+        self.dirty = true;
+    }
+
+    /// Save the configuration to the active profile, or to the default file if none.
+    pub fn save_config(&self) -> io::Result<()> {
+        match &self.active_profile {
+            Some(name) => self.config.save_as(name),
+            None => self.config.save(),
+        }
+    }
+
+    /// Set the primary device's LED count to the one reported by OpenRGB.
+    pub async fn detect_leds(&mut self) {
+        match query_led_count(&self.config).await {
+            Ok(led_count) => {
+                let previous = self.config.clone();
+                self.config.primary_device_mut().num_leds = led_count;
+                match self.config.validate() {
+                    Ok(()) => {
+                        self.validation_errors.clear();
+                        self.notice = Some(format!("Detected {led_count} LEDs."));
+                        self.remember_undo(previous);
+                    }
+                    Err(errors) => {
+                        self.config = previous;
+                        self.validation_errors = errors;
+                    }
                 }
-                _ => {}
             }
+            Err(err) => self.validation_errors = vec![format!("LED detection failed: {err}")],
+        }
+        self.dirty = true;
+    }
+
+    /// Light every LED white through the output stage, to check the channel calibration.
+    pub async fn show_white(&mut self) {
+        match show_white(&self.config).await {
+            Ok(()) => {
+                self.validation_errors.clear();
+                self.notice = Some("All LEDs set to white.".to_string());
+            }
+            Err(err) => self.validation_errors = vec![format!("Show white failed: {err}")],
+        }
+        self.dirty = true;
+    }
+
+    /// Light the LED test pattern on the configured devices.
+    pub async fn show_test_pattern(&mut self) {
+        match show_test_pattern(&self.config).await {
+            Ok(lit) => {
+                self.validation_errors.clear();
+                self.notice = Some(format!("Test pattern shown on {lit}."));
+            }
+            Err(err) => self.validation_errors = vec![format!("Test pattern failed: {err}")],
         }
         self.dirty = true;
     }
 
     /// Start the actual sync loop in background (spawning a new thread with its own Tokio runtime).
-    pub fn start_sync(&mut self) {
+    pub async fn start_sync(&mut self) {
+        self.prepare_restart().await;
+
+        self.brightness_history.clear();
+        self.history_frame = lock_or_recover(&self.sync_status).frame_count;
+
         // Reset to false in case we had a previous run
         self.stop_signal.store(false, Ordering::Relaxed); // NEW
 
@@ -243,7 +527,7 @@ impl App {
             // Run the async sync loop within the runtime
             rt.block_on(async {
                 if let Err(err) = start_sync_loop(&config, sync_status, stop_signal).await { // MODIFIED
-                    eprintln!("Error in sync loop: {err}");
+                    error!("Error in sync loop: {err}");
                 }
             });
         });
@@ -254,7 +538,62 @@ impl App {
         self.dirty = true;
     }
 
+    /// Join a previous sync loop and wait until `restart_cooldown_ms` have passed since it
+    /// exited, so two loops never write at once.
+    ///
+    /// The cooldown is awaited rather than slept, so the TUI's runtime isn't blocked.
+    async fn prepare_restart(&mut self) {
+        if self.sync_handle.is_some() {
+            self.stop_sync();
+        }
+        if let Some(stopped_at) = self.last_stopped {
+            let cooldown = Duration::from_millis(self.config.restart_cooldown_ms);
+            if let Some(remaining) = cooldown.checked_sub(stopped_at.elapsed()) {
+                tokio::time::sleep(remaining).await;
+            }
+        }
+    }
+
+    /// Add the newest frame from `sync_status` to `brightness_history`, if it is new.
+    pub fn sample_history(&mut self) {
+        let status = lock_or_recover(&self.sync_status);
+        if status.frame_count == self.history_frame || status.current_colors.is_empty() {
+            return;
+        }
+        self.history_frame = status.frame_count;
+        let total: f32 = status
+            .current_colors
+            .iter()
+            .map(|c| 0.2126 * c.r as f32 + 0.7152 * c.g as f32 + 0.0722 * c.b as f32)
+            .sum();
+        let average = total / status.current_colors.len() as f32;
+        drop(status);
+
+        if self.brightness_history.len() == HISTORY_LEN {
+            self.brightness_history.pop_front();
+        }
+        self.brightness_history.push_back(average.round() as u64);
+    }
+
+    /// Return to the menu once a connection timeout has been shown for
+    /// `TIMEOUT_NOTICE_DURATION`, keeping the error as the menu's notice.
+    pub fn check_timed_out(&mut self) {
+        if self.input_mode != InputMode::Syncing
+            || lock_or_recover(&self.sync_status).state != SyncState::TimedOut
+        {
+            self.timed_out_at = None;
+            return;
+        }
+        let shown_since = *self.timed_out_at.get_or_insert_with(Instant::now);
+        if shown_since.elapsed() >= TIMEOUT_NOTICE_DURATION {
+            self.timed_out_at = None;
+            self.stop_sync();
+        }
+    }
+
     /// Abort the sync loop (if running) and return to the normal mode.
+    ///
+    /// If the loop failed, its error becomes the menu's notice.
     pub fn stop_sync(&mut self) {
         // Tell the sync loop to break from its while-loop
         self.stop_signal.store(true, Ordering::Relaxed); // NEW
@@ -262,8 +601,12 @@ impl App {
         if let Some(handle) = self.sync_handle.take() {
             // This will now return quickly, because the sync loop sees stop_signal == true
             handle.join().unwrap_or_else(|e| {
-                eprintln!("Failed to join sync thread: {:?}", e);
+                error!("Failed to join sync thread: {:?}", e);
             });
+            self.last_stopped = Some(Instant::now());
+        }
+        if let Some(err) = lock_or_recover(&self.sync_status).last_error.clone() {
+            self.notice = Some(format!("Sync failed: {err}"));
         }
 
         self.input_mode = InputMode::Normal;
@@ -271,6 +614,80 @@ impl App {
     }
 }
 
+/// The styles the TUI draws with, picked by `Config::ui_theme`.
+struct Theme {
+    banner: Style,
+    signature: Style,
+    option: Style,
+    highlight: Style,
+    error: Style,
+    notice: Style,
+    description: Style,
+    input: Style,
+    hint: Style,
+    /// Sync screen header while starting or running.
+    active: Style,
+}
+
+impl Theme {
+    fn new(theme: UiTheme) -> Self {
+        let fg = |color: RColor| Style::default().fg(color);
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        match theme {
+            UiTheme::Default => Theme {
+                banner: fg(RColor::Yellow),
+                signature: fg(RColor::Rgb(255, 214, 0)),
+                option: fg(RColor::White).add_modifier(Modifier::BOLD),
+                highlight: fg(RColor::Black).bg(RColor::Cyan).add_modifier(Modifier::BOLD),
+                error: fg(RColor::Red),
+                notice: fg(RColor::Green),
+                description: fg(RColor::LightBlue),
+                input: fg(RColor::Green),
+                hint: fg(RColor::Gray),
+                active: fg(RColor::Yellow),
+            },
+            UiTheme::Dark => Theme {
+                banner: fg(RColor::Rgb(200, 170, 80)),
+                signature: fg(RColor::Rgb(200, 170, 80)),
+                option: fg(RColor::Gray),
+                highlight: fg(RColor::White)
+                    .bg(RColor::Rgb(60, 60, 90))
+                    .add_modifier(Modifier::BOLD),
+                error: fg(RColor::Rgb(230, 110, 110)),
+                notice: fg(RColor::Rgb(130, 200, 130)),
+                description: fg(RColor::Rgb(140, 170, 210)),
+                input: fg(RColor::Rgb(130, 200, 130)),
+                hint: fg(RColor::DarkGray),
+                active: fg(RColor::Rgb(200, 170, 80)),
+            },
+            UiTheme::HighContrast => Theme {
+                banner: fg(RColor::White).add_modifier(Modifier::BOLD),
+                signature: fg(RColor::White),
+                option: fg(RColor::White).add_modifier(Modifier::BOLD),
+                highlight: fg(RColor::Black).bg(RColor::Yellow).add_modifier(Modifier::BOLD),
+                error: fg(RColor::LightRed).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                notice: fg(RColor::LightYellow).add_modifier(Modifier::BOLD),
+                description: fg(RColor::White),
+                input: fg(RColor::LightYellow).add_modifier(Modifier::BOLD),
+                hint: fg(RColor::White),
+                active: fg(RColor::LightYellow).add_modifier(Modifier::BOLD),
+            },
+            UiTheme::Mono => Theme {
+                banner: bold,
+                signature: Style::default(),
+                option: Style::default(),
+                highlight: bold.add_modifier(Modifier::REVERSED),
+                error: bold.add_modifier(Modifier::UNDERLINED),
+                notice: bold,
+                description: Style::default(),
+                input: Style::default().add_modifier(Modifier::UNDERLINED),
+                hint: Style::default().add_modifier(Modifier::DIM),
+                active: bold,
+            },
+        }
+    }
+}
+
 /// Renders the main TUI layout onto the frame.
 ///
 /// # Arguments
@@ -281,11 +698,17 @@ pub fn ui(f: &mut Frame<'_>, app: &mut App) {
     match app.input_mode {
         InputMode::Normal | InputMode::Editing => render_menu(f, app),
         InputMode::Syncing => render_sync_screen(f, app),
+        InputMode::PickingDevice => render_device_picker(f, app),
+        InputMode::ConfirmingReset => {
+            render_menu(f, app);
+            render_reset_prompt(f, app);
+        }
     }
 }
 
 fn render_menu(f: &mut Frame<'_>, app: &mut App) {
-    let area = f.size();
+    let theme = Theme::new(app.config.ui_theme);
+    let area = f.area();
     let chunks = ratatui::layout::Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
@@ -310,18 +733,14 @@ fn render_menu(f: &mut Frame<'_>, app: &mut App) {
     let header_paragraph = Paragraph::new(ascii_art)
         .block(header_block)
         .alignment(Alignment::Center)
-        .style(Style::default().fg(RColor::Yellow));
+        .style(theme.banner);
     f.render_widget(header_paragraph, chunks[0]);
 
     // Configuration options list
     let items: Vec<ListItem> = app
-        .options
+        .items
         .iter()
-        .map(|opt| ListItem::new(*opt).style(
-            Style::default()
-                .fg(RColor::White)
-                .add_modifier(Modifier::BOLD),
-        ))
+        .map(|item| ListItem::new(item.label()).style(theme.option))
         .collect();
 
     let list = List::new(items)
@@ -332,31 +751,27 @@ fn render_menu(f: &mut Frame<'_>, app: &mut App) {
                 .border_type(BorderType::Rounded)
                 .title_alignment(Alignment::Center),
         )
-        .highlight_style(
-            Style::default()
-                .fg(RColor::Black)
-                .bg(RColor::Cyan)
-                .add_modifier(Modifier::BOLD),
-        )
+        .highlight_style(theme.highlight)
         .highlight_symbol(">> ");
     f.render_stateful_widget(list, chunks[1], &mut app.list_state);
 
     // Description of currently selected option
-    let selected = app.list_state.selected().unwrap_or(0);
-    let description = if selected < app.descriptions.len() {
-        app.descriptions[selected]
-    } else {
-        ""
-    };
+    let description = app.selected_item().description();
     let desc_block = Block::default()
         .title("Option Description")
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
         .title_alignment(Alignment::Center);
-    let desc_paragraph = Paragraph::new(description)
-        .block(desc_block)
-        .style(Style::default().fg(RColor::LightBlue))
-        .alignment(Alignment::Left);
+    // Show why the last edit was rejected in place of the description
+    let desc_paragraph = if !app.validation_errors.is_empty() {
+        Paragraph::new(app.validation_errors.join("\n")).style(theme.error)
+    } else if let Some(notice) = &app.notice {
+        Paragraph::new(notice.as_str()).style(theme.notice)
+    } else {
+        Paragraph::new(description).style(theme.description)
+    }
+    .block(desc_block)
+    .alignment(Alignment::Left);
     f.render_widget(desc_paragraph, chunks[2]);
 
     // Input/edit area or Instructions
@@ -371,52 +786,96 @@ fn render_menu(f: &mut Frame<'_>, app: &mut App) {
             .title_alignment(Alignment::Center);
         let input_widget = Paragraph::new(app.input.as_str())
             .block(editing_block)
-            .style(Style::default().fg(RColor::Green))
+            .style(theme.input)
             .alignment(Alignment::Left);
-        f.render_widget(input_widget, chunks[3]);
+
+        // Color fields show what the input parses to next to the edit box
+        let input_area = match app.selected_item() {
+            MenuItem::Field(field) if field.has_preview() => {
+                let [input_area, swatch_area] = ratatui::layout::Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Min(1), Constraint::Length(20)])
+                    .areas(chunks[3]);
+                let preview = field.preview(&app.config, &app.input);
+                let swatch = Paragraph::new(swatch(preview, theme.hint)).block(
+                    input_block
+                        .clone()
+                        .title("Preview")
+                        .title_alignment(Alignment::Center),
+                );
+                f.render_widget(swatch, swatch_area);
+                input_area
+            }
+            _ => chunks[3],
+        };
+        f.render_widget(input_widget, input_area);
 
         // Place the cursor at the end of the input
-        let cursor_x = chunks[3].x + app.input.len() as u16 + 1;
-        let cursor_y = chunks[3].y + 1;
-        f.set_cursor(cursor_x, cursor_y);
+        let cursor_x = input_area.x + app.input.len() as u16 + 1;
+        let cursor_y = input_area.y + 1;
+        f.set_cursor_position((cursor_x, cursor_y));
     } else {
         let help_block = input_block
             .clone()
             .title("Instructions")
             .title_alignment(Alignment::Center);
-        let info_text = "Press 'q' to exit. Use ↑↓ to navigate. Press Enter to edit.";
+        let info_text = "Press 'q' to exit. Use ↑↓ to navigate. Press Enter to edit.\n\
+                         Use ←→ or -/+ to adjust numbers. Press 'd' to write a diagnostics report.";
         let info = Paragraph::new(info_text)
             .block(help_block)
-            .style(Style::default().fg(RColor::Gray))
+            .style(theme.hint)
             .alignment(Alignment::Center);
         f.render_widget(info, chunks[3]);
     }
 
     // Author signature
     let author_paragraph = Paragraph::new("Alexander Bayerl | With ❤️ from Austria")
-        .style(Style::default().fg(RColor::Rgb(255, 214, 0)))
+        .style(theme.signature)
         .alignment(Alignment::Right);
     f.render_widget(author_paragraph, chunks[5]);
 }
 
 fn render_sync_screen(f: &mut Frame<'_>, app: &mut App) {
-    let sync_status = app.sync_status.lock().unwrap();
+    let theme = Theme::new(app.config.ui_theme);
+    app.sample_history();
+    let sync_status = lock_or_recover(&app.sync_status);
 
     // Define layout
     let chunks = ratatui::layout::Layout::default()
         .direction(Direction::Vertical)
         .margin(2)
         .constraints([
-            Constraint::Length(3),
+            Constraint::Length(4),
             Constraint::Min(1),
+            Constraint::Length(6),
             Constraint::Length(5),
         ])
-        .split(f.size());
+        .split(f.area());
 
     // Header
-    let header = Paragraph::new("🔄 Synchronization in Progress")
-        .style(Style::default().fg(RColor::Yellow).add_modifier(Modifier::BOLD))
-        .alignment(Alignment::Center);
+    let (header_text, header_style) = match sync_status.state {
+        SyncState::Starting => ("⏳ Starting Synchronization...", theme.active),
+        SyncState::Running => ("🔄 Synchronization in Progress", theme.active),
+        SyncState::Reconnecting => ("🔌 Connection lost, reconnecting to OpenRGB...", theme.error),
+        SyncState::Stopped if sync_status.last_error.is_some() => {
+            ("⚠ Synchronization Failed (press 'm' for the menu)", theme.error)
+        }
+        SyncState::Stopped => ("⏹ Synchronization Stopped", theme.hint),
+        SyncState::TimedOut => {
+            ("⌛ Connection to OpenRGB timed out, returning to the menu...", theme.error)
+        }
+    };
+    let mut header_lines = vec![Line::from(header_text)];
+    if let Some(notice) = &sync_status.capture_notice {
+        header_lines.push(Line::styled(notice.clone(), theme.error));
+    }
+    if let Some(err) = &sync_status.last_error {
+        header_lines.push(Line::styled(err.clone(), theme.error));
+    }
+    let header = Paragraph::new(header_lines)
+        .style(header_style.add_modifier(Modifier::BOLD))
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
     f.render_widget(header, chunks[0]);
 
     // Body - Display current colors
@@ -438,11 +897,88 @@ fn render_sync_screen(f: &mut Frame<'_>, app: &mut App) {
         .style(Style::default());
     f.render_widget(list, chunks[1]);
 
-    // Footer with controls
-    let footer = Paragraph::new("Press 'm' to return to Menu | 'q' to Quit")
-        .style(Style::default().fg(RColor::Gray))
+    // Waveform of the average brightness, newest frame on the right. Flicker shows up as
+    // spikes, a long debounce as flat steps.
+    let history = app.brightness_history.make_contiguous();
+    let visible = history.len().saturating_sub(chunks[2].width.saturating_sub(2) as usize);
+    let waveform = Sparkline::default()
+        .block(Block::default().title("Brightness History").borders(Borders::ALL))
+        .data(&history[visible..])
+        .max(255)
+        .style(theme.active);
+    f.render_widget(waveform, chunks[2]);
+
+    // Footer with timings and controls
+    let fps = match sync_status.target_fps {
+        Some(target) => format!("{:.1} / {target} FPS", sync_status.fps),
+        None => format!("{:.1} FPS", sync_status.fps),
+    };
+    let footer_text = format!(
+        "{fps} | capture {:.1} ms | processing {:.1} ms\n\
+         Press 'm' to return to Menu | 'q' to Quit",
+        sync_status.avg_capture_ms, sync_status.avg_process_ms
+    );
+    let footer = Paragraph::new(footer_text)
+        .style(theme.hint)
+        .alignment(Alignment::Center);
+    f.render_widget(footer, chunks[3]);
+}
+
+/// Draw the yes/no reset confirmation centered over the menu.
+fn render_reset_prompt(f: &mut Frame<'_>, app: &App) {
+    let theme = Theme::new(app.config.ui_theme);
+    let area = f.area();
+    let width = area.width.min(50);
+    let height = area.height.min(5);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let prompt = Paragraph::new("Reset every setting to its default?\n\n[y] Reset   [n] Cancel")
+        .block(
+            Block::default()
+                .title("Reset to Defaults")
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title_alignment(Alignment::Center),
+        )
+        .style(theme.error)
+        .alignment(Alignment::Center);
+    f.render_widget(Clear, popup);
+    f.render_widget(prompt, popup);
+}
+
+fn render_device_picker(f: &mut Frame<'_>, app: &mut App) {
+    let theme = Theme::new(app.config.ui_theme);
+    let chunks = ratatui::layout::Layout::default()
+        .direction(Direction::Vertical)
+        .margin(2)
+        .constraints([Constraint::Min(1), Constraint::Length(3)])
+        .split(f.area());
+
+    let items: Vec<ListItem> = app
+        .device_choices
+        .iter()
+        .map(|name| ListItem::new(name.as_str()))
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title("OpenRGB Devices")
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title_alignment(Alignment::Center),
+        )
+        .highlight_style(theme.highlight)
+        .highlight_symbol(">> ");
+    f.render_stateful_widget(list, chunks[0], &mut app.device_picker_state);
+
+    let footer = Paragraph::new("Use ↑↓ to choose, Enter to select, Esc to cancel.")
+        .style(theme.hint)
         .alignment(Alignment::Center);
-    f.render_widget(footer, chunks[2]);
+    f.render_widget(footer, chunks[1]);
 }
 
 /// Runs the TUI application loop, handling events and rendering.
@@ -454,15 +990,16 @@ fn render_sync_screen(f: &mut Frame<'_>, app: &mut App) {
 ///
 /// # Returns
 ///
-/// An `AnyError` if an error occurs during the run.
+/// A `KeyBloomError` if an error occurs during the run.
 pub async fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     app: &mut App,
-) -> Result<(), AnyError> {
+) -> Result<(), KeyBloomError> {
     let tick_rate = Duration::from_millis(200);
     let mut last_tick = Instant::now();
 
     loop {
+        app.check_timed_out();
         let now = Instant::now();
         let timeout = tick_rate
             .checked_sub(last_tick.elapsed())
@@ -482,9 +1019,10 @@ pub async fn run_app<B: Backend>(
                         _ => None,
                     };
     
+                    // Global keys; while editing every character goes into the input instead
                     if let Some(c) = key_char {
                         match c {
-                            'm' => {
+                            'm' if app.input_mode != InputMode::Editing => {
                                 if app.input_mode == InputMode::Syncing {
                                     app.stop_sync();
                                 } else {
@@ -492,12 +1030,22 @@ pub async fn run_app<B: Backend>(
                                 }
                                 continue; // Skip further processing
                             }
-                            'q' => {
+                            'q' if app.input_mode != InputMode::Editing => {
                                 if app.input_mode == InputMode::Syncing {
                                     app.stop_sync();
                                 }
                                 break;
                             }
+                            'd' if app.input_mode == InputMode::Normal => {
+                                app.notice = Some(match write_report(&app.config).await {
+                                    Ok(path) => {
+                                        format!("Diagnostics written to {}", path.display())
+                                    }
+                                    Err(err) => format!("Failed to write diagnostics: {err}"),
+                                });
+                                app.dirty = true;
+                                continue;
+                            }
                             _ => {}
                         }
                     }
@@ -512,26 +1060,34 @@ pub async fn run_app<B: Backend>(
                                 KeyCode::Up => {
                                     app.previous();
                                 }
-                                KeyCode::Enter => {
-                                    if let Some(selected) = app.list_state.selected() {
-                                        // "Save and Sync" is the last option
-                                        if selected == app.options.len() - 1 {
-                                            // Attempt to save configuration
-                                            match app.config.save() {
-                                                Ok(_) => {
-                                                    eprintln!("Configuration saved successfully.");
-                                                    // Now start the sync
-                                                    app.start_sync();
-                                                }
-                                                Err(err) => {
-                                                    eprintln!("Failed to save configuration: {}", err);
-                                                }
+                                KeyCode::Right | KeyCode::Char('+') => app.nudge_selected(1.0),
+                                KeyCode::Left | KeyCode::Char('-') => app.nudge_selected(-1.0),
+                                KeyCode::Enter => match app.selected_item() {
+                                    MenuItem::SaveAndSync => {
+                                        // Attempt to save configuration
+                                        match app.save_config() {
+                                            Ok(_) => {
+                                                info!("Configuration saved successfully.");
+                                                // Now start the sync
+                                                app.start_sync().await;
+                                            }
+                                            Err(err) => {
+                                                error!("Failed to save configuration: {}", err);
+                                                app.notice = Some(format!(
+                                                    "Failed to save configuration: {err}"
+                                                ));
                                             }
-                                        } else {
-                                            app.toggle_edit();
                                         }
                                     }
-                                }
+                                    MenuItem::DetectLeds => app.detect_leds().await,
+                                    MenuItem::ShowWhite => app.show_white().await,
+                                    MenuItem::TestPattern => app.show_test_pattern().await,
+                                    MenuItem::PickDevice => app.open_device_picker().await,
+                                    MenuItem::Theme => app.cycle_theme(),
+                                    MenuItem::Undo => app.undo_last_edit(),
+                                    MenuItem::ResetToDefaults => app.request_reset(),
+                                    MenuItem::Field(_) | MenuItem::Profile => app.toggle_edit(),
+                                },
                                 _ => {}
                             }
                         }
@@ -558,6 +1114,20 @@ pub async fn run_app<B: Backend>(
                         InputMode::Syncing => {
                             // Handle other keys if necessary
                         }
+                        InputMode::PickingDevice => match key.code {
+                            KeyCode::Down => app.move_device_selection(1),
+                            KeyCode::Up => app.move_device_selection(-1),
+                            KeyCode::Enter => app.close_device_picker(true),
+                            KeyCode::Esc => app.close_device_picker(false),
+                            _ => {}
+                        },
+                        InputMode::ConfirmingReset => match key.code {
+                            KeyCode::Char('y') | KeyCode::Char('Y') => app.finish_reset(true),
+                            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                                app.finish_reset(false)
+                            }
+                            _ => {}
+                        },
                     }
                 }
             }
@@ -572,8 +1142,16 @@ pub async fn run_app<B: Backend>(
 /// # Arguments
 ///
 /// * `config` - A mutable reference to the current KeyBloom configuration.
-pub async fn show_menu(config: &mut Config) -> io::Result<()> {
-    let mut app = App::new(config.clone());
+/// * `active_profile` - The profile `config` was loaded from, if any.
+pub async fn show_menu(config: &mut Config, active_profile: Option<String>) -> io::Result<()> {
+    let mut app = App::new(config.clone(), active_profile);
+    if config.mqtt.is_some() {
+        warn!("The [mqtt] settings are only used when running with --headless.");
+        app.notice = Some("MQTT is only connected when running with --headless.".to_string());
+    }
+    if config.toggle_hotkey.is_some() {
+        warn!("The toggle_hotkey is only listened for when running with --headless.");
+    }
 
     // Start up the TUI
     enable_raw_mode()?;
@@ -585,8 +1163,8 @@ pub async fn show_menu(config: &mut Config) -> io::Result<()> {
     let run_result = match run_app(&mut terminal, &mut app).await {
         Ok(_) => Ok(()),
         Err(err) => {
-            eprintln!("Error running TUI menu: {}", err);
-            Err(io::Error::new(io::ErrorKind::Other, err))
+            error!("Error running TUI menu: {}", err);
+            Err(io::Error::other(err))
         }
     };
 
@@ -602,7 +1180,53 @@ pub async fn show_menu(config: &mut Config) -> io::Result<()> {
     app.stop_sync();
 
     // Reload config from disk if user selected "Save and Sync"
-    *config = Config::load();
+    *config = match &app.active_profile {
+        Some(name) => Config::load_profile(name).unwrap_or_else(|_| Config::load()),
+        None => Config::load(),
+    };
 
     run_result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn restart_waits_for_teardown_and_cooldown() {
+        let config = Config {
+            restart_cooldown_ms: 100,
+            ..Config::default()
+        };
+        let mut app = App::new(config, None);
+
+        // Stand in for a sync loop that takes a moment to notice the stop signal
+        let exited = Arc::new(AtomicBool::new(false));
+        let stop_signal = Arc::clone(&app.stop_signal);
+        let loop_exited = Arc::clone(&exited);
+        app.sync_handle = Some(thread::spawn(move || {
+            while !stop_signal.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            thread::sleep(Duration::from_millis(20));
+            loop_exited.store(true, Ordering::Relaxed);
+        }));
+        app.input_mode = InputMode::Syncing;
+
+        app.stop_sync();
+        assert!(exited.load(Ordering::Relaxed));
+        let stopped_at = app.last_stopped.expect("stop_sync records when the loop exited");
+
+        app.prepare_restart().await;
+        assert!(app.sync_handle.is_none());
+        assert!(stopped_at.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn first_start_does_not_wait() {
+        let mut app = App::new(Config::default(), None);
+        let started = Instant::now();
+        app.prepare_restart().await;
+        assert!(started.elapsed() < Duration::from_millis(250));
+    }
+}
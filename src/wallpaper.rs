@@ -0,0 +1,116 @@
+//! Finding the desktop wallpaper for `SyncMode::Wallpaper`.
+//!
+//! Desktops don't agree on where the wallpaper is configured, so the common ones are asked
+//! in turn: GNOME (and other desktops using its settings), KDE Plasma, Windows and macOS.
+//! `wallpaper_image` in the config skips the detection entirely.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use image::RgbaImage;
+use tracing::{debug, info};
+
+use crate::config::Config;
+use crate::error::KeyBloomError;
+
+/// Load the configured `wallpaper_image`, or else the detected desktop wallpaper.
+pub fn load(config: &Config) -> Result<RgbaImage, KeyBloomError> {
+    let path = match &config.wallpaper_image {
+        Some(path) => path.clone(),
+        None => detect().ok_or(KeyBloomError::NoWallpaper)?,
+    };
+    info!("Using wallpaper {}", path.display());
+    Ok(image::open(&path)?.to_rgba8())
+}
+
+/// Path of the current desktop wallpaper, if it can be found and is a file.
+pub fn detect() -> Option<PathBuf> {
+    let candidates = [gnome(), plasma(), windows(), macos()];
+    let path = candidates.into_iter().flatten().find(|path| path.is_file());
+    if path.is_none() {
+        debug!("No wallpaper found through gsettings, Plasma, the registry or System Events.");
+    }
+    path
+}
+
+/// Output of `program args`, trimmed, if it ran successfully.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// GNOME, Budgie, Cinnamon and others storing the wallpaper as a `file://` URI.
+fn gnome() -> Option<PathBuf> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let dark = command_output("gsettings", &["get", "org.gnome.desktop.interface", "color-scheme"])
+        .is_some_and(|scheme| scheme.contains("dark"));
+    let key = if dark { "picture-uri-dark" } else { "picture-uri" };
+    let uri = command_output("gsettings", &["get", "org.gnome.desktop.background", key])?;
+    file_uri_to_path(uri.trim_matches('\''))
+}
+
+/// KDE Plasma, which keeps an `Image=` entry per desktop in its applet config.
+fn plasma() -> Option<PathBuf> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let config_dir = directories::BaseDirs::new()?.config_dir().to_path_buf();
+    let applets_file = config_dir.join("plasma-org.kde.plasma.desktop-appletsrc");
+    let applets = std::fs::read_to_string(applets_file).ok()?;
+    applets
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Image="))
+        .and_then(|value| file_uri_to_path(value).or_else(|| Some(PathBuf::from(value))))
+}
+
+/// Windows, from the `WallPaper` value of the current user's desktop settings.
+fn windows() -> Option<PathBuf> {
+    if !cfg!(target_os = "windows") {
+        return None;
+    }
+    let args = ["query", r"HKCU\Control Panel\Desktop", "/v", "WallPaper"];
+    let output = command_output("reg", &args)?;
+    output
+        .lines()
+        .find_map(|line| line.split_once("REG_SZ"))
+        .map(|(_, path)| PathBuf::from(path.trim()))
+}
+
+/// macOS, asking System Events for the picture of the current desktop.
+fn macos() -> Option<PathBuf> {
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+    let script = "tell application \"System Events\" to get picture of current desktop";
+    command_output("osascript", &["-e", script]).map(PathBuf::from)
+}
+
+/// Turn a `file://` URI into a path, decoding percent escapes.
+fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| encoded.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(decoded).ok()?))
+}
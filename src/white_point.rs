@@ -0,0 +1,157 @@
+//! Adaptive white point estimation.
+//!
+//! `WhitePointEstimator` treats the brightest near-neutral pixels of each frame as the
+//! scene's "white", smooths that estimate over time, and derives per-channel gains that
+//! neutralize it — similar to a camera's auto white balance.
+
+use image::RgbaImage;
+use palette::Srgb;
+use std::time::Duration;
+
+/// Pixels more saturated than this are not considered candidates for white.
+const MAX_NEUTRAL_SATURATION: f32 = 0.25;
+/// Pixels darker than this (max channel) are not considered candidates for white.
+const MIN_WHITE_LEVEL: u8 = 96;
+/// Fraction of the candidate pixels, brightest first, averaged into the estimate.
+const BRIGHTEST_FRACTION: f32 = 0.02;
+/// Per-channel gains are clamped to this range so odd scenes can't wreck the output.
+const GAIN_RANGE: (f32, f32) = (0.5, 2.0);
+
+/// Tracks a smoothed estimate of the scene's white point.
+pub struct WhitePointEstimator {
+    /// Smoothed white point, normalized to [0.0..1.0] per channel.
+    white: [f32; 3],
+    /// Time constant of the exponential smoothing.
+    time_constant: Duration,
+}
+
+impl WhitePointEstimator {
+    /// Create an estimator that starts from neutral white.
+    pub fn new(time_constant: Duration) -> Self {
+        Self {
+            white: [1.0, 1.0, 1.0],
+            time_constant,
+        }
+    }
+
+    /// Fold the white point of `frame` into the estimate, `elapsed` after the previous frame.
+    ///
+    /// Frames without any bright near-neutral pixels leave the estimate unchanged.
+    pub fn observe(&mut self, frame: &RgbaImage, sampling_step: usize, elapsed: Duration) {
+        let Some(frame_white) = estimate_frame_white(frame, sampling_step) else {
+            return;
+        };
+        let alpha = if self.time_constant.is_zero() {
+            1.0
+        } else {
+            1.0 - (-elapsed.as_secs_f32() / self.time_constant.as_secs_f32()).exp()
+        };
+        for (smoothed, observed) in self.white.iter_mut().zip(frame_white) {
+            *smoothed += (observed - *smoothed) * alpha;
+        }
+    }
+
+    /// Per-channel gains that map the current white estimate to neutral gray.
+    pub fn gains(&self) -> [f32; 3] {
+        let mean = self.white.iter().sum::<f32>() / 3.0;
+        self.white.map(|channel| {
+            if channel <= f32::EPSILON {
+                1.0
+            } else {
+                (mean / channel).clamp(GAIN_RANGE.0, GAIN_RANGE.1)
+            }
+        })
+    }
+
+    /// Normalize a color relative to the estimated white point.
+    pub fn apply(&self, srgb: Srgb<f32>) -> Srgb<f32> {
+        let [r, g, b] = self.gains();
+        Srgb::new(
+            (srgb.red * r).clamp(0.0, 1.0),
+            (srgb.green * g).clamp(0.0, 1.0),
+            (srgb.blue * b).clamp(0.0, 1.0),
+        )
+    }
+}
+
+/// Average the brightest low-saturation pixels of `frame`, normalized to [0.0..1.0].
+fn estimate_frame_white(frame: &RgbaImage, sampling_step: usize) -> Option<[f32; 3]> {
+    // Bucket candidates by brightness so the brightest ones can be picked without sorting
+    let mut buckets = [(0u64, 0u64, 0u64, 0u64); 256];
+    let mut candidates = 0u64;
+    let step = sampling_step.max(1);
+
+    for y in (0..frame.height()).step_by(step) {
+        for x in (0..frame.width()).step_by(step) {
+            let [r, g, b, a] = frame.get_pixel(x, y).0;
+            let max = r.max(g).max(b);
+            let min = r.min(g).min(b);
+            if a < 26 || max < MIN_WHITE_LEVEL {
+                continue;
+            }
+            let saturation = (max - min) as f32 / max as f32;
+            if saturation > MAX_NEUTRAL_SATURATION {
+                continue;
+            }
+            let bucket = &mut buckets[max as usize];
+            bucket.0 += r as u64;
+            bucket.1 += g as u64;
+            bucket.2 += b as u64;
+            bucket.3 += 1;
+            candidates += 1;
+        }
+    }
+    if candidates == 0 {
+        return None;
+    }
+
+    let wanted = ((candidates as f32 * BRIGHTEST_FRACTION).ceil() as u64).max(1);
+    let (mut r, mut g, mut b, mut count) = (0u64, 0u64, 0u64, 0u64);
+    for bucket in buckets.iter().rev() {
+        r += bucket.0;
+        g += bucket.1;
+        b += bucket.2;
+        count += bucket.3;
+        if count >= wanted {
+            break;
+        }
+    }
+    let scale = 255.0 * count as f32;
+    Some([r as f32 / scale, g as f32 / scale, b as f32 / scale])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// Largest difference between the channels of `srgb`.
+    fn spread(srgb: Srgb<f32>) -> f32 {
+        let (max, min) = [srgb.green, srgb.blue]
+            .into_iter()
+            .fold((srgb.red, srgb.red), |(max, min), c| (max.max(c), min.min(c)));
+        max - min
+    }
+
+    #[test]
+    fn warm_white_converges_toward_neutral() {
+        let warm = [250, 225, 200];
+        let frame = RgbaImage::from_pixel(32, 18, Rgba([warm[0], warm[1], warm[2], 255]));
+        let warm = Srgb::new(250.0 / 255.0, 225.0 / 255.0, 200.0 / 255.0);
+        let mut estimator = WhitePointEstimator::new(Duration::from_millis(500));
+        assert_eq!(estimator.gains(), [1.0, 1.0, 1.0]);
+
+        let mut last = spread(estimator.apply(warm));
+        for _ in 0..60 {
+            estimator.observe(&frame, 1, Duration::from_millis(50));
+            let now = spread(estimator.apply(warm));
+            assert!(now <= last + 1e-6, "drifted away from neutral: {now} > {last}");
+            last = now;
+        }
+        assert!(last < 0.01, "still tinted after 3s: spread {last}");
+
+        // Red is pulled down and blue boosted to cancel the warm cast
+        let [r, g, b] = estimator.gains();
+        assert!(r < g && g < b, "{:?}", [r, g, b]);
+    }
+}